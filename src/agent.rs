@@ -2,9 +2,10 @@ use std::collections::HashMap;
 use std::fs::{self, File};
 use std::future::Future;
 use std::io::Read;
-use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::process;
 use std::sync::Arc;
+use std::time::Duration;
 use anyhow::{Error, Result};
 use clap::{value_t, values_t};
 use ed25519_compact::{KeyPair, Seed};
@@ -13,21 +14,22 @@ use log::{debug, error, info, warn};
 use nix::{unistd::gethostname, sys::utsname::uname};
 use rustls::RootCertStore;
 use signal_hook::{iterator::Signals, {consts::signal::{SIGINT, SIGTERM, SIGUSR1}}};
+use tokio::runtime::Handle;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
-use trust_dns_resolver::TokioAsyncResolver;
-use trust_dns_resolver::config::{LookupIpStrategy, ResolverConfig, ResolverOpts};
-use trust_dns_resolver::system_conf::read_system_conf;
+use tokio::time::timeout;
 use synapi::{Client, Config as ClientConfig, Region};
 use netdiag::Bind;
 use crate::args::{App, Args};
-use crate::cfg::Config;
+use crate::cmd;
+use crate::cfg::{self, Config};
 use crate::ctl::Server;
 use crate::exec::Factory;
 use crate::export::Exporter;
-use crate::net::{Listener, Network, Resolver, tls::TrustAnchors};
+use crate::net::{Listener, Network, Origins, Resolver, Rules, Settings, tls::TrustAnchors};
 use crate::output::Output;
-use crate::secure;
-use crate::status::Monitor;
+use crate::secure::{self, Privileges};
+use crate::status::{Monitor, Queue};
+use crate::task::{Budget, Limits};
 use crate::update::Updater;
 use crate::watch::{Event, Watcher};
 
@@ -87,10 +89,18 @@ pub fn agent(app: App, args: Args<'_, '_>) -> Result<()> {
     let ip4     = !args.is_present("ip6");
     let ip6     = !args.is_present("ip4");
     let user    = args.value_of("user");
+    let fwmark  = args.is_present("allow-fwmark");
+    let netns   = args.is_present("allow-netns");
     let update  = args.is_present("update");
     let listen  = args.opt("listen")?.unwrap_or_default();
     let output  = args.opt("output")?;
     let release = !args.is_present("rc");
+    let asn     = args.opt::<PathBuf>("asn")?;
+    let geo     = args.opt::<PathBuf>("geo")?;
+    let tasks   = args.opt::<String>("config")?;
+    let runs    = args.list("limit-runs");
+    let pps     = args.opt("limit-pps")?;
+    let http    = args.opt("limit-http")?;
 
     let mut bind = Bind::default();
     if let Some(addrs) = args.values_of("bind") {
@@ -120,22 +130,37 @@ pub fn agent(app: App, args: Args<'_, '_>) -> Result<()> {
     let id = hex::encode(&keys.pk[..6]);
     debug!("name '{name}' identity: {id}");
 
-    if let Err(e) = secure::apply(user) {
+    let privileges = Privileges { netns, mark: fwmark };
+
+    if let Err(e) = secure::apply(user, privileges) {
         error!("agent security failure: {e}");
     }
 
+    let allowed = secure::privileges(privileges);
+
+    let mut rules = match &tasks {
+        Some(path) => cfg::dns(path)?,
+        None       => Rules::default(),
+    };
+    rules.extend(cmd::rules(&args)?);
+
     let machine  = machine();
-    let resolver = resolver(&bind, net)?;
+    let resolver = Resolver::system(&bind, net, &Settings::default(), &rules)?;
     let roots    = trust_roots();
+    let origins  = Origins::load(asn.as_deref(), geo.as_deref())?;
     let listener = runtime.block_on(Listener::new(listen));
+    let limits   = Limits::parse(&runs, pps, http)?;
 
     let config = Config {
+        allowed:  allowed,
         bind:     bind.clone(),
+        budget:   Arc::new(Budget::new(&limits)),
         listener: listener,
         network:  net,
+        origins:  Arc::new(origins),
         resolver: resolver,
         roots:    roots.clone(),
-        tasks:    args.opt("config")?,
+        tasks:    tasks,
     };
 
     let factory = runtime.block_on(Factory::new(&config))?;
@@ -227,12 +252,52 @@ pub fn agent(app: App, args: Args<'_, '_>) -> Result<()> {
         }
     }
 
+    shutdown(&handle, &events);
+
     abort.abort();
     guard.join().unwrap();
 
     Ok(())
 }
 
+fn shutdown(handle: &Handle, events: &HashMap<String, Sender<Event>>) {
+    info!("shutting down, waiting up to {:?} for active runs", GRACE);
+
+    let (tx, mut rx) = channel(events.len().max(1));
+
+    for (region, events) in events {
+        let region = region.clone();
+        let events = events.clone();
+        let tx     = tx.clone();
+
+        handle.spawn(async move {
+            let (flush, mut done) = channel(1);
+
+            if let Err(e) = events.send(Event::Shutdown(GRACE, flush)).await {
+                warn!("region {region} shutdown error: {e:?}");
+                return;
+            }
+
+            if let Some(queue) = done.recv().await {
+                let _ = tx.send((region, queue)).await;
+            }
+        });
+    }
+
+    drop(tx);
+
+    let flushed = handle.block_on(timeout(GRACE + FLUSH, async {
+        while let Some((region, queue)) = rx.recv().await {
+            let Queue { length, records } = queue;
+            info!("region {region} flushed {records} records in {length} batches");
+        }
+    }));
+
+    if flushed.is_err() {
+        warn!("shutdown timed out, pending exports dropped");
+    }
+}
+
 fn load(path: &str) -> Result<KeyPair> {
     let mut file  = File::open(path)?;
     let mut bytes = Vec::new();
@@ -271,38 +336,6 @@ fn machine() -> String {
     machine
 }
 
-fn resolver(bind: &Bind, net: Option<Network>) -> Result<Resolver> {
-    let (config, mut options) = read_system_conf().unwrap_or_else(|e| {
-        warn!("resolver configuration error: {}", e);
-        let config  = ResolverConfig::google();
-        let options = ResolverOpts::default();
-        (config, options)
-    });
-
-    let domain  = config.domain().cloned();
-    let search  = config.search().to_vec();
-    let servers = config.name_servers().iter().map(|server| {
-        let mut server = server.clone();
-        let local = server.socket_addr.ip().is_loopback();
-        server.bind_addr = match server.socket_addr {
-            SocketAddr::V4(_) if !local => Some(bind.sa4()),
-            SocketAddr::V6(_) if !local => Some(bind.sa6()),
-            _                           => None,
-        };
-        server
-    }).collect::<Vec<_>>();
-
-    let config = ResolverConfig::from_parts(domain, search, servers);
-
-    options.ip_strategy = match net {
-        Some(Network::IPv4)        => LookupIpStrategy::Ipv4Only,
-        Some(Network::IPv6)        => LookupIpStrategy::Ipv6Only,
-        Some(Network::Dual) | None => LookupIpStrategy::Ipv4AndIpv6,
-    };
-
-    Ok(Resolver::new(TokioAsyncResolver::tokio(config, options)?))
-}
-
 fn trust_roots() -> RootCertStore {
     let mut store = RootCertStore::empty();
 
@@ -319,3 +352,6 @@ fn trust_roots() -> RootCertStore {
 
     store
 }
+
+const GRACE: Duration = Duration::from_secs(10);
+const FLUSH: Duration = Duration::from_secs(20);
@@ -52,6 +52,12 @@ impl<'a, 'y> Args<'a, 'y> {
         })
     }
 
+    pub fn list(&self, name: &str) -> Vec<String> {
+        self.values_of(name).map(|values| {
+            values.map(String::from).collect()
+        }).unwrap_or_default()
+    }
+
     fn subargs<'n>(&self, name: &'n str, args: &'a ArgMatches<'y>) -> Option<(&'n str, Self)> {
         let cmds = self.yaml["subcommands"].as_vec()?;
         let yaml = cmds.iter().flat_map(|yaml| {
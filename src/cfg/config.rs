@@ -1,24 +1,33 @@
+use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
 use std::convert::{TryFrom, TryInto};
 use std::fs::{metadata, File};
+use std::hash::{Hash, Hasher};
 use std::mem::take;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
 use netdiag::Bind;
 use rustls::RootCertStore;
 use tokio::sync::mpsc::Sender;
 use tokio::time::interval;
 use tracing::{debug, error};
-use synapi::agent::Agent;
-use synapi::tasks::{Device, Group, Kentik, State, Task};
-use crate::net::{Listener, Network, Resolver};
+use synapi::agent::{Agent, Net};
+use synapi::tasks::{Device, Group, Kentik, State, Task, TaskConfig};
+use crate::net::{Listener, Network, Origins, Resolver, Rules};
+use crate::secure::Privileges;
+use crate::task::Budget;
 use crate::watch::{self, Event};
 use super::schema::{self, Tasks};
 
 #[derive(Clone)]
 pub struct Config {
+    pub allowed:  Privileges,
     pub bind:     Bind,
+    pub budget:   Arc<Budget>,
     pub listener: Listener,
     pub network:  Option<Network>,
+    pub origins:  Arc<Origins>,
     pub resolver: Resolver,
     pub roots:    RootCertStore,
     pub tasks:    Option<String>,
@@ -46,6 +55,21 @@ impl Config {
     }
 }
 
+pub fn dns(path: &str) -> Result<Rules> {
+    let file  = File::open(path)?;
+    let tasks = serde_yaml::from_reader::<_, Tasks>(&file)?;
+    tasks.dns.unwrap_or_default().try_into()
+}
+
+pub fn task(spec: &str) -> Result<(Net, TaskConfig)> {
+    let task   = serde_yaml::from_str::<schema::Task>(spec)?;
+    let family = match task.compare {
+        true  => Net::Compare,
+        false => task.network.into(),
+    };
+    Ok((family, task.config.try_into()?))
+}
+
 pub struct Watcher {
     config:  String,
     agent:   Agent,
@@ -80,30 +104,31 @@ impl Watcher {
         let file  = File::open(&self.config)?;
         let tasks = serde_yaml::from_reader::<_, Tasks>(&file)?;
 
-        let tasks = tasks.tasks.into_iter().enumerate().map(|(index, task)| {
+        let mut ids = HashSet::new();
+
+        let tasks = tasks.tasks.into_iter().map(|task| {
+            let config = TaskConfig::try_from(task.config)?;
+            let family = match task.compare {
+                true  => Net::Compare,
+                false => task.network.into(),
+            };
+
             Ok(Task {
-                task:   index.try_into()?,
+                task:   id(&mut ids, task.name.as_deref(), &family, &config)?,
                 test:   0,
-                config: task.config.try_into()?,
-                family: task.network.try_into()?,
+                config: config,
+                family: family,
                 state:  State::Created,
             })
         }).collect::<Result<Vec<_>>>()?;
 
-        let mut outdated = take(&mut self.tasks);
-        for task in &mut outdated {
+        let mut changes = take(&mut self.tasks);
+        changes.retain(|old| !tasks.iter().any(|new| new.task == old.task));
+        for task in &mut changes {
             task.state = State::Deleted;
         };
 
-        events.send(Event::Tasks(watch::Tasks {
-            agent: self.agent.clone(),
-            tasks: vec![Group {
-                company: 0,
-                kentik:  self.kentik.clone(),
-                device:  self.device.clone(),
-                tasks:   outdated,
-            }],
-        })).await?;
+        changes.extend(tasks.iter().cloned());
 
         events.send(Event::Tasks(watch::Tasks {
             agent: self.agent.clone(),
@@ -111,7 +136,7 @@ impl Watcher {
                 company: 0,
                 kentik:  self.kentik.clone(),
                 device:  self.device.clone(),
-                tasks:   tasks.clone(),
+                tasks:   changes,
             }],
         })).await?;
 
@@ -122,6 +147,31 @@ impl Watcher {
     }
 }
 
+/// Local tasks are identified by their name, or by their config when
+/// unnamed, so editing or reordering the file only restarts tasks that
+/// actually changed.
+fn id(ids: &mut HashSet<u64>, name: Option<&str>, family: &Net, config: &TaskConfig) -> Result<u64> {
+    let mut id = match name {
+        Some(name) => digest(&name),
+        None       => digest(&(family, config)),
+    };
+
+    while !ids.insert(id) {
+        if let Some(name) = name {
+            return Err(anyhow!("duplicate task name: {name}"));
+        }
+        id = digest(&id);
+    }
+
+    Ok(id)
+}
+
+fn digest<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
 impl TryFrom<schema::Config> for synapi::tasks::TaskConfig {
     type Error = Error;
 
@@ -147,8 +197,18 @@ impl TryFrom<schema::Fetch> for synapi::tasks::FetchConfig {
             body:     c.body,
             headers:  c.headers,
             insecure: c.insecure,
-            period:   c.period.try_into()?,
+            period:   c.period.map(TryInto::try_into).transpose()?,
             expiry:   c.expiry.try_into()?,
+            policy:   c.policy,
+            race:     c.race,
+            eyeballs: c.eyeballs,
+            address:  c.address,
+            resolve:  c.resolve,
+            sni:      c.sni,
+            resolver: c.resolver.map(TryInto::try_into).transpose()?,
+            source:   c.source.map(TryInto::try_into).transpose()?,
+            schedule: c.schedule.map(TryInto::try_into).transpose()?,
+            followup: c.followup.into_iter().map(TryInto::try_into).collect::<Result<_>>()?,
         })
     }
 }
@@ -158,12 +218,17 @@ impl TryFrom<schema::Knock> for synapi::tasks::KnockConfig {
 
     fn try_from(c: schema::Knock) -> Result<Self, Self::Error> {
         Ok(Self {
-            target: c.target,
-            period: c.period.try_into()?,
-            count:  c.count.try_into()?,
-            expiry: c.expiry.try_into()?,
-            delay:  c.delay.try_into()?,
-            port:   c.port,
+            target:   c.target,
+            period:   c.period.map(TryInto::try_into).transpose()?,
+            count:    c.count.try_into()?,
+            expiry:   c.expiry.try_into()?,
+            delay:    c.delay.try_into()?,
+            port:     c.port,
+            policy:   c.policy,
+            resolver: c.resolver.map(TryInto::try_into).transpose()?,
+            source:   c.source.map(TryInto::try_into).transpose()?,
+            schedule: c.schedule.map(TryInto::try_into).transpose()?,
+            followup: c.followup.into_iter().map(TryInto::try_into).collect::<Result<_>>()?,
         })
     }
 }
@@ -173,11 +238,22 @@ impl TryFrom<schema::Ping> for synapi::tasks::PingConfig {
 
     fn try_from(c: schema::Ping) -> Result<Self, Self::Error> {
         Ok(Self {
-            target: c.target,
-            period: c.period.try_into()?,
-            count:  c.count.try_into()?,
-            delay:  c.delay.try_into()?,
-            expiry: c.expiry.try_into()?,
+            target:   c.target,
+            period:   c.period.map(TryInto::try_into).transpose()?,
+            count:    c.count.try_into()?,
+            delay:    c.delay.try_into()?,
+            expiry:   c.expiry.try_into()?,
+            size:     c.size,
+            dscp:     c.dscp.try_into()?,
+            ttl:      c.ttl,
+            df:       c.df,
+            protocol: c.protocol,
+            port:     c.port,
+            policy:   c.policy,
+            resolver: c.resolver.map(TryInto::try_into).transpose()?,
+            source:   c.source.map(TryInto::try_into).transpose()?,
+            schedule: c.schedule.map(TryInto::try_into).transpose()?,
+            followup: c.followup.into_iter().map(TryInto::try_into).collect::<Result<_>>()?,
         })
     }
 }
@@ -187,12 +263,15 @@ impl TryFrom<schema::Query> for synapi::tasks::QueryConfig {
 
     fn try_from(c: schema::Query) -> Result<Self, Self::Error> {
         Ok(Self {
-            target: c.target,
-            period: c.period.try_into()?,
-            expiry: c.expiry.try_into()?,
-            server: c.server,
-            port:   c.port,
-            record: c.record,
+            target:   c.target,
+            period:   c.period.map(TryInto::try_into).transpose()?,
+            expiry:   c.expiry.try_into()?,
+            server:   c.server,
+            port:     c.port,
+            record:   c.record,
+            source:   c.source.map(TryInto::try_into).transpose()?,
+            schedule: c.schedule.map(TryInto::try_into).transpose()?,
+            followup: c.followup.into_iter().map(TryInto::try_into).collect::<Result<_>>()?,
         })
     }
 }
@@ -202,10 +281,18 @@ impl TryFrom<schema::Shake> for synapi::tasks::ShakeConfig {
 
     fn try_from(c: schema::Shake) -> Result<Self, Self::Error> {
         Ok(Self {
-            target: c.target,
-            port:   c.port,
-            period: c.period.try_into()?,
-            expiry: c.expiry.try_into()?,
+            target:   c.target,
+            port:     c.port,
+            period:   c.period.map(TryInto::try_into).transpose()?,
+            expiry:   c.expiry.try_into()?,
+            policy:   c.policy,
+            address:  c.address,
+            resolve:  c.resolve,
+            sni:      c.sni,
+            resolver: c.resolver.map(TryInto::try_into).transpose()?,
+            source:   c.source.map(TryInto::try_into).transpose()?,
+            schedule: c.schedule.map(TryInto::try_into).transpose()?,
+            followup: c.followup.into_iter().map(TryInto::try_into).collect::<Result<_>>()?,
         })
     }
 }
@@ -218,15 +305,136 @@ impl TryFrom<schema::Trace> for synapi::tasks::TraceConfig {
             protocol: c.protocol,
             port:     c.port,
             target:   c.target,
-            period:   c.period.try_into()?,
+            period:   c.period.map(TryInto::try_into).transpose()?,
             count:    c.count.try_into()?,
             limit:    c.limit.try_into()?,
             delay:    c.delay.try_into()?,
             expiry:   c.expiry.try_into()?,
+            flows:    match c.flows {
+                Some(flows) => flows.try_into()?,
+                None        => 1usize.into(),
+            },
+            names:    c.names.unwrap_or(false),
+            policy:   c.policy,
+            resolver: c.resolver.map(TryInto::try_into).transpose()?,
+            source:   c.source.map(TryInto::try_into).transpose()?,
+            schedule: c.schedule.map(TryInto::try_into).transpose()?,
+            followup: c.followup.into_iter().map(TryInto::try_into).collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl TryFrom<schema::Resolver> for synapi::tasks::ResolverConfig {
+    type Error = Error;
+
+    fn try_from(c: schema::Resolver) -> Result<Self, Self::Error> {
+        Ok(Self {
+            servers:  c.servers,
+            protocol: c.protocol,
+            timeout:  c.timeout.map(TryInto::try_into).transpose()?,
+            nocache:  c.nocache,
+        })
+    }
+}
+
+impl TryFrom<schema::Source> for synapi::tasks::SourceConfig {
+    type Error = Error;
+
+    fn try_from(c: schema::Source) -> Result<Self, Self::Error> {
+        Ok(Self {
+            address:   c.address,
+            interface: c.interface,
+            vrf:       c.vrf,
+            netns:     c.netns,
+            fwmark:    c.fwmark,
+            dscp:      c.dscp.map(TryInto::try_into).transpose()?,
+        })
+    }
+}
+
+impl TryFrom<schema::Schedule> for synapi::tasks::ScheduleConfig {
+    type Error = Error;
+
+    fn try_from(c: schema::Schedule) -> Result<Self, Self::Error> {
+        Ok(Self {
+            align:       c.align,
+            jitter:      match c.jitter {
+                Some(jitter) => jitter.try_into()?,
+                None         => Default::default(),
+            },
+            adaptive:    c.adaptive.map(TryInto::try_into).transpose()?,
+            cron:        c.cron,
+            timezone:    c.timezone,
+            maintenance: c.maintenance.into_iter().map(TryInto::try_into).collect::<Result<_>>()?,
+        })
+    }
+}
+
+impl TryFrom<schema::Maintenance> for synapi::tasks::MaintenanceConfig {
+    type Error = Error;
+
+    fn try_from(c: schema::Maintenance) -> Result<Self, Self::Error> {
+        Ok(Self {
+            start:    c.start,
+            end:      c.end,
+            timezone: c.timezone,
+            action:   c.action,
+        })
+    }
+}
+
+impl TryFrom<schema::Adaptive> for synapi::tasks::AdaptiveConfig {
+    type Error = Error;
+
+    fn try_from(c: schema::Adaptive) -> Result<Self, Self::Error> {
+        Ok(Self {
+            period:  c.period.try_into()?,
+            after:   c.after,
+            recover: c.recover,
+            rtt:     c.rtt.map(TryInto::try_into).transpose()?,
+            loss:    c.loss,
         })
     }
 }
 
+impl TryFrom<schema::Followup> for synapi::tasks::FollowupConfig {
+    type Error = Error;
+
+    fn try_from(c: schema::Followup) -> Result<Self, Self::Error> {
+        Ok(Self {
+            action: c.action,
+            on:     c.on,
+            after:  c.after,
+            count:  c.count.map(TryInto::try_into).transpose()?,
+            expiry: c.expiry.map(TryInto::try_into).transpose()?,
+        })
+    }
+}
+
+impl TryFrom<schema::Dns> for Rules {
+    type Error = Error;
+
+    fn try_from(c: schema::Dns) -> Result<Self, Self::Error> {
+        let forward = c.forward.iter().flat_map(|(domain, servers)| {
+            servers.iter().map(move |server| format!("{}={}", domain, server))
+        }).collect::<Vec<_>>();
+
+        let search = c.search.unwrap_or_default();
+
+        let mut rules = Rules::parse(&[], &forward, &search)?;
+        for (name, addrs) in c.hosts {
+            for addr in addrs {
+                rules.host(&name, addr);
+            }
+        }
+
+        rules.upstreams = c.upstreams;
+        rules.fallback  = c.fallback;
+
+        Ok(rules)
+    }
+}
+
 impl TryFrom<schema::Count> for synapi::tasks::Count {
     type Error = Error;
 
@@ -243,6 +451,14 @@ impl TryFrom<schema::Count> for synapi::tasks::Limit {
     }
 }
 
+impl TryFrom<schema::Dscp> for synapi::tasks::Dscp {
+    type Error = Error;
+
+    fn try_from(c: schema::Dscp) -> Result<Self, Self::Error> {
+        Ok(c.0.into())
+    }
+}
+
 impl TryFrom<schema::Time> for synapi::tasks::Delay {
     type Error = Error;
 
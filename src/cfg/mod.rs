@@ -1,4 +1,6 @@
 pub use config::Config;
+pub use config::dns;
+pub use config::task;
 
 mod config;
 mod schema;
@@ -1,6 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
 use std::fmt;
+use std::net::IpAddr;
 use std::str::FromStr;
 use std::time::Duration;
 use anyhow::Result;
@@ -10,13 +11,33 @@ use crate::net::Network;
 #[derive(Debug, Deserialize)]
 pub struct Tasks {
     pub tasks: Vec<Task>,
+    #[serde(default)]
+    pub dns:   Option<Dns>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Dns {
+    #[serde(default)]
+    pub hosts:     HashMap<String, Vec<IpAddr>>,
+    #[serde(default)]
+    pub forward:   HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub search:    Option<Vec<String>>,
+    #[serde(default)]
+    pub upstreams: Vec<String>,
+    #[serde(default)]
+    pub fallback:  bool,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Task {
+    #[serde(default)]
+    pub name:    Option<String>,
     #[serde(flatten)]
     pub config:  Config,
     pub network: Network,
+    #[serde(default)]
+    pub compare: bool,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -35,47 +56,126 @@ pub struct Fetch {
     pub target:   String,
     pub method:   String,
     pub body:     Option<String>,
-    pub headers:  Option<HashMap<String, String>>,
+    pub headers:  Option<BTreeMap<String, String>>,
     pub insecure: bool,
-    pub period:   Time,
+    #[serde(default)]
+    pub period:   Option<Time>,
     pub expiry:   Time,
+    #[serde(default)]
+    pub policy:   String,
+    #[serde(default)]
+    pub race:     bool,
+    #[serde(default)]
+    pub eyeballs: bool,
+    #[serde(default)]
+    pub address:  Option<IpAddr>,
+    #[serde(default)]
+    pub resolve:  BTreeMap<String, IpAddr>,
+    #[serde(default)]
+    pub sni:      Option<String>,
+    #[serde(default)]
+    pub resolver: Option<Resolver>,
+    #[serde(default)]
+    pub source:   Option<Source>,
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    #[serde(default)]
+    pub followup: Vec<Followup>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Knock {
-    pub target: String,
-    pub port:   u16,
-    pub count:  Count,
-    pub period: Time,
-    pub delay:  Time,
-    pub expiry: Time,
+    pub target:   String,
+    pub port:     u16,
+    pub count:    Count,
+    #[serde(default)]
+    pub period:   Option<Time>,
+    pub delay:    Time,
+    pub expiry:   Time,
+    #[serde(default)]
+    pub policy:   String,
+    #[serde(default)]
+    pub resolver: Option<Resolver>,
+    #[serde(default)]
+    pub source:   Option<Source>,
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    #[serde(default)]
+    pub followup: Vec<Followup>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Ping {
-    pub target: String,
-    pub count:  Count,
-    pub period: Time,
-    pub delay:  Time,
-    pub expiry: Time,
+    pub target:   String,
+    pub count:    Count,
+    #[serde(default)]
+    pub period:   Option<Time>,
+    pub delay:    Time,
+    pub expiry:   Time,
+    #[serde(default)]
+    pub size:     Option<u16>,
+    #[serde(default)]
+    pub dscp:     Dscp,
+    #[serde(default)]
+    pub ttl:      Option<u8>,
+    #[serde(default)]
+    pub df:       bool,
+    #[serde(default)]
+    pub protocol: String,
+    #[serde(default)]
+    pub port:     u16,
+    #[serde(default)]
+    pub policy:   String,
+    #[serde(default)]
+    pub resolver: Option<Resolver>,
+    #[serde(default)]
+    pub source:   Option<Source>,
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    #[serde(default)]
+    pub followup: Vec<Followup>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Query {
-    pub target: String,
-    pub server: String,
-    pub port:   u16,
-    pub record: String,
-    pub period: Time,
-    pub expiry: Time,
+    pub target:   String,
+    pub server:   String,
+    pub port:     u16,
+    pub record:   String,
+    #[serde(default)]
+    pub period:   Option<Time>,
+    pub expiry:   Time,
+    #[serde(default)]
+    pub source:   Option<Source>,
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    #[serde(default)]
+    pub followup: Vec<Followup>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Shake {
-    pub target: String,
-    pub port:   u16,
-    pub period: Time,
-    pub expiry: Time,
+    pub target:   String,
+    pub port:     u16,
+    #[serde(default)]
+    pub period:   Option<Time>,
+    pub expiry:   Time,
+    #[serde(default)]
+    pub policy:   String,
+    #[serde(default)]
+    pub address:  Option<IpAddr>,
+    #[serde(default)]
+    pub resolve:  BTreeMap<String, IpAddr>,
+    #[serde(default)]
+    pub sni:      Option<String>,
+    #[serde(default)]
+    pub resolver: Option<Resolver>,
+    #[serde(default)]
+    pub source:   Option<Source>,
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    #[serde(default)]
+    pub followup: Vec<Followup>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -85,17 +185,115 @@ pub struct Trace {
     pub port:     u16,
     pub count:    Count,
     pub limit:    Count,
-    pub period:   Time,
+    #[serde(default)]
+    pub period:   Option<Time>,
     pub delay:    Time,
     pub expiry:   Time,
+    #[serde(default)]
+    pub flows:    Option<Count>,
+    #[serde(default)]
+    pub names:    Option<bool>,
+    #[serde(default)]
+    pub policy:   String,
+    #[serde(default)]
+    pub resolver: Option<Resolver>,
+    #[serde(default)]
+    pub source:   Option<Source>,
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    #[serde(default)]
+    pub followup: Vec<Followup>,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct Resolver {
+    #[serde(default)]
+    pub servers:  Vec<String>,
+    #[serde(default)]
+    pub protocol: String,
+    #[serde(default)]
+    pub timeout:  Option<Time>,
+    #[serde(default)]
+    pub nocache:  bool,
+}
+#[derive(Clone, Debug, Deserialize)]
+pub struct Source {
+    #[serde(default)]
+    pub address:   Option<IpAddr>,
+    #[serde(default)]
+    pub interface: Option<String>,
+    #[serde(default)]
+    pub vrf:       Option<String>,
+    #[serde(default)]
+    pub netns:     Option<String>,
+    #[serde(default)]
+    pub fwmark:    Option<u32>,
+    #[serde(default)]
+    pub dscp:      Option<Dscp>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Schedule {
+    #[serde(default)]
+    pub align:       bool,
+    #[serde(default)]
+    pub jitter:      Option<Time>,
+    #[serde(default)]
+    pub adaptive:    Option<Adaptive>,
+    #[serde(default)]
+    pub cron:        Option<String>,
+    #[serde(default)]
+    pub timezone:    Option<String>,
+    #[serde(default)]
+    pub maintenance: Vec<Maintenance>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Adaptive {
+    pub period:  Time,
+    #[serde(default)]
+    pub after:   Option<u32>,
+    #[serde(default)]
+    pub recover: Option<u32>,
+    #[serde(default)]
+    pub rtt:     Option<Time>,
+    #[serde(default)]
+    pub loss:    Option<u8>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Maintenance {
+    pub start:    String,
+    pub end:      String,
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub action:   String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Followup {
+    pub action: String,
+    #[serde(default)]
+    pub on:     Option<String>,
+    #[serde(default)]
+    pub after:  Option<u32>,
+    #[serde(default)]
+    pub count:  Option<Count>,
+    #[serde(default)]
+    pub expiry: Option<Time>,
+}
+
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Time(pub Duration);
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Count(pub u64);
 
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Dscp(pub u8);
+
 impl Time {
     pub fn as_secs(&self) -> u64 {
         self.0.as_secs()
@@ -116,6 +314,16 @@ impl<'d> Deserialize<'d> for Count {
     }
 }
 
+impl<'d> Deserialize<'d> for Dscp {
+    fn deserialize<D: Deserializer<'d>>(de: D) -> Result<Self, D::Error> {
+        let n = de.deserialize_u64(U64Visitor)?;
+        match u8::try_from(n) {
+            Ok(n) if n < 64 => Ok(Dscp(n)),
+            _               => Err(D::Error::custom(format!("dscp out of range: {}", n))),
+        }
+    }
+}
+
 impl<'d> Deserialize<'d> for Time {
     fn deserialize<D: Deserializer<'d>>(de: D) -> Result<Self, D::Error> {
         de.deserialize_any(TimeVisitor)
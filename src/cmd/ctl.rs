@@ -1,7 +1,8 @@
+use std::fs::read_to_string;
 use anyhow::{anyhow, Result};
 use crate::args::Args;
-use crate::ctl::{Client, Command, Trace};
-use crate::status::Report;
+use crate::ctl::{Client, Command, Control, Run, Trace};
+use crate::status::{Listing, Report};
 
 pub async fn ctl(args: Args<'_, '_>) -> Result<()> {
     let socket = args.arg::<String>("socket")?;
@@ -9,6 +10,8 @@ pub async fn ctl(args: Args<'_, '_>) -> Result<()> {
 
     match args.subcommand() {
         Some(("status", args)) => status(args, client).await,
+        Some(("run",    args)) => run(args, client).await,
+        Some(("task",   args)) => task(args, client).await,
         Some(("trace",  args)) => trace(args, client).await,
         Some(_) | None         => return Err(anyhow!("unsupported command")),
     }
@@ -26,6 +29,39 @@ async fn status(args: Args<'_, '_>, mut client: Client) -> Result<()> {
     Ok(())
 }
 
+async fn run(args: Args<'_, '_>, mut client: Client) -> Result<()> {
+    let region = args.opt("region")?.unwrap_or_default();
+    let run    = match args.subcommand() {
+        Some(("task",   args)) => Run::Task(args.arg("id")?),
+        Some(("config", args)) => Run::Config(read_to_string(args.arg::<String>("file")?)?),
+        Some(_) | None         => return Err(anyhow!("unsupported command")),
+    };
+
+    for record in client.send::<Vec<String>>(Command::Run(region, run)).await? {
+        println!("{record}");
+    }
+
+    Ok(())
+}
+
+async fn task(args: Args<'_, '_>, mut client: Client) -> Result<()> {
+    let region  = args.opt("region")?.unwrap_or_default();
+    let control = match args.subcommand() {
+        Some(("pause",  args)) => Control::Pause(args.arg("id")?),
+        Some(("resume", args)) => Control::Resume(args.arg("id")?),
+        Some(("list",   _))    => Control::List,
+        Some(_) | None         => return Err(anyhow!("unsupported command")),
+    };
+
+    for Listing { task, test, kind, paused, fast } in client.send::<Vec<Listing>>(Command::Task(region, control)).await? {
+        let state = if paused { "paused" } else { "running" };
+        let mode  = if fast { " (fast)" } else { "" };
+        println!("task {task} test {test} {kind} {state}{mode}");
+    }
+
+    Ok(())
+}
+
 async fn trace(args: Args<'_, '_>, mut client: Client) -> Result<()> {
     client.send(Command::Trace(match args.subcommand() {
         Some(("filter", args)) => Trace::Filter(args.arg("filter")?),
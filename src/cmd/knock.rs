@@ -3,12 +3,10 @@ use anyhow::Result;
 use clap::{value_t, values_t};
 use futures::{pin_mut, StreamExt};
 use tokio::time::sleep;
-use trust_dns_resolver::TokioAsyncResolver;
-use trust_dns_resolver::system_conf::read_system_conf;
 use netdiag::{Bind, Knock, Knocker};
 use crate::args::Args;
-use crate::net::{Network, Resolver};
-use super::resolve;
+use crate::net::{Network, Resolver, Settings};
+use super::{resolve, rules};
 
 pub async fn knock(args: Args<'_, '_>) -> Result<()> {
     let count  = value_t!(args, "count",  usize)?;
@@ -32,9 +30,8 @@ pub async fn knock(args: Args<'_, '_>) -> Result<()> {
         _             => Network::Dual,
     };
 
-    let (config, options) = read_system_conf()?;
-    let resolver = TokioAsyncResolver::tokio(config, options)?;
-    let resolver = Resolver::new(resolver.clone());
+    let rules    = rules(&args)?;
+    let resolver = Resolver::system(&bind, Some(net), &Settings::default(), &rules)?;
 
     let knocker = Knocker::new(&bind).await?;
 
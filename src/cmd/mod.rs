@@ -2,7 +2,7 @@ use std::net::IpAddr;
 use anyhow::Result;
 use futures::stream::{self, StreamExt};
 use crate::args::{App, Args};
-use crate::net::{Network, Resolver};
+use crate::net::{Network, Resolver, Rules};
 
 pub fn knock(app: App, args: Args<'_, '_>) -> Result<()> {
     app.runtime.block_on(knock::knock(args))
@@ -20,6 +20,18 @@ pub fn ctl(app: App, args: Args<'_, '_>) -> Result<()> {
     app.runtime.block_on(ctl::ctl(args))
 }
 
+pub fn rules(args: &Args<'_, '_>) -> Result<Rules> {
+    let hosts   = args.list("dns-host");
+    let forward = args.list("dns-forward");
+    let search  = args.list("dns-search");
+
+    let mut rules = Rules::parse(&hosts, &forward, &search)?;
+    rules.upstreams = args.list("dns-upstream");
+    rules.fallback  = args.is_present("dns-fallback");
+
+    Ok(rules)
+}
+
 pub async fn resolve(resolver: &Resolver, hosts: Vec<String>, net: Network) -> Vec<(String, IpAddr)> {
     stream::iter(hosts).filter_map(|host| async move {
         match resolver.lookup(&host, net).await {
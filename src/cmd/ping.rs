@@ -3,17 +3,20 @@ use anyhow::Result;
 use clap::{value_t, values_t};
 use futures::{pin_mut, StreamExt};
 use tokio::time::sleep;
-use trust_dns_resolver::TokioAsyncResolver;
-use trust_dns_resolver::system_conf::read_system_conf;
 use netdiag::{Bind, Ping, Pinger};
 use crate::args::Args;
-use crate::net::{Network, Resolver};
-use super::resolve;
+use crate::net::{Network, Resolver, Settings, Source};
+use crate::net::echo::{Echo, Options};
+use super::{resolve, rules};
 
 pub async fn ping(args: Args<'_, '_>) -> Result<()> {
     let count  = value_t!(args, "count",  usize)?;
     let delay  = value_t!(args, "delay",  u64)?;
     let expiry = value_t!(args, "expiry", u64)?;
+    let size   = args.opt("size")?;
+    let dscp   = args.opt::<u8>("dscp")?.unwrap_or(0);
+    let ttl    = args.opt("ttl")?;
+    let df     = args.is_present("df");
     let ip4    = !args.is_present("ip6");
     let ip6    = !args.is_present("ip4");
     let hosts  = values_t!(args, "host", String)?;
@@ -31,11 +34,12 @@ pub async fn ping(args: Args<'_, '_>) -> Result<()> {
         _             => Network::Dual,
     };
 
-    let (config, options) = read_system_conf()?;
-    let resolver = TokioAsyncResolver::tokio(config, options)?;
-    let resolver = Resolver::new(resolver.clone());
+    let rules    = rules(&args)?;
+    let resolver = Resolver::system(&bind, Some(net), &Settings::default(), &rules)?;
 
-    let pinger = Pinger::new(&bind).await?;
+    let pinger  = Pinger::new(&bind).await?;
+    let options = Options::new(size, dscp, ttl, df)?;
+    let source  = Source::default();
 
     let delay  = Duration::from_millis(delay);
     let expiry = Duration::from_millis(expiry);
@@ -43,8 +47,14 @@ pub async fn ping(args: Args<'_, '_>) -> Result<()> {
     for (host, addr) in resolve(&resolver, hosts, net).await {
         println!("ping {} ({})", host, addr);
 
-        let ping   = Ping { addr, count, expiry };
-        let stream = pinger.ping(&ping).enumerate();
+        let echo;
+        let stream = match options.is_default() {
+            true  => pinger.ping(&Ping { addr, count, expiry }).boxed(),
+            false => {
+                echo = Echo::new(&source, &bind, addr, &options).await?;
+                echo.ping(count, expiry).boxed()
+            }
+        }.enumerate();
         pin_mut!(stream);
 
         while let Some((n, item)) = stream.next().await {
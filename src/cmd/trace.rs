@@ -5,12 +5,11 @@ use anyhow::Result;
 use clap::{value_t, values_t};
 use futures::{pin_mut, stream::StreamExt};
 use tokio::time::sleep;
-use trust_dns_resolver::TokioAsyncResolver;
-use trust_dns_resolver::system_conf::read_system_conf;
 use netdiag::{Bind, Node, Protocol, Tracer};
 use crate::args::Args;
-use crate::net::{Network, Resolver};
-use super::resolve;
+use crate::net::{Network, Resolver, Settings, Source};
+use crate::net::mpls::{Label, Listener};
+use super::{resolve, rules};
 
 pub async fn trace(args: Args<'_, '_>) -> Result<()> {
     let delay  = value_t!(args, "delay",  u64)?;
@@ -19,6 +18,7 @@ pub async fn trace(args: Args<'_, '_>) -> Result<()> {
     let probes = value_t!(args, "probes", usize)?;
     let ip4    = !args.is_present("ip6");
     let ip6    = !args.is_present("ip4");
+    let names  = !args.is_present("numeric");
     let hosts  = values_t!(args, "host", String)?;
 
     let mut bind = Bind::default();
@@ -34,9 +34,8 @@ pub async fn trace(args: Args<'_, '_>) -> Result<()> {
         _             => Network::Dual,
     };
 
-    let (config, options) = read_system_conf()?;
-    let resolver = TokioAsyncResolver::tokio(config, options)?;
-    let resolver = Resolver::new(resolver.clone());
+    let rules    = rules(&args)?;
+    let resolver = Resolver::system(&bind, Some(net), &Settings::default(), &rules)?;
 
     let delay  = Duration::from_millis(delay);
     let expiry = Duration::from_millis(expiry);
@@ -54,7 +53,8 @@ pub async fn trace(args: Args<'_, '_>) -> Result<()> {
             _                 => Protocol::default(),
         };
 
-        let source = tracer.reserve(proto, addr).await?;
+        let source   = tracer.reserve(proto, addr).await?;
+        let listener = Listener::new(&Source::default(), addr)?;
 
         let mut done  = false;
         let mut ttl   = 1;
@@ -67,17 +67,28 @@ pub async fn trace(args: Args<'_, '_>) -> Result<()> {
             let stream = stream.take(probes);
             pin_mut!(stream);
 
-            while let Some(Ok(node)) = stream.next().await {
-                if let Node::Node(_, ip, rtt, last) = node {
-                    let rtt = format!("{:>0.2?}", rtt);
-                    nodes.entry(ip).or_default().push(rtt);
-                    done = last || ip == addr;
-                }
+            let ((), labels) = listener.watch(async {
+                while let Some(Ok(node)) = stream.next().await {
+                    if let Node::Node(_, ip, rtt, last) = node {
+                        let rtt = format!("{:>0.2?}", rtt);
+                        nodes.entry(ip).or_default().push(rtt);
+                        done = last || ip == addr;
+                    }
 
-                sleep(delay).await;
+                    sleep(delay).await;
+                }
+            }).await;
+
+            let mut ptr = HashMap::<IpAddr, String>::new();
+            if names {
+                for ip in nodes.keys() {
+                    if let Some(name) = resolver.reverse(*ip, expiry).await {
+                        ptr.insert(*ip, name);
+                    }
+                }
             }
 
-            print(&nodes, ttl, probes);
+            print(&nodes, &ptr, &labels, ttl, probes);
 
             ttl += 1;
         }
@@ -86,26 +97,40 @@ pub async fn trace(args: Args<'_, '_>) -> Result<()> {
     Ok(())
 }
 
-fn print(nodes: &HashMap<IpAddr, Vec<String>>, ttl: u8, probes: usize) {
+fn print(
+    nodes:  &HashMap<IpAddr, Vec<String>>,
+    names:  &HashMap<IpAddr, String>,
+    labels: &HashMap<IpAddr, Vec<Label>>,
+    ttl:    u8,
+    probes: usize,
+) {
     let mut count = 0;
 
     let mut output = nodes.iter().map(|(node, rtt)| {
         count += rtt.len();
-        let node = node.to_string();
-        let rtt  = rtt.join(", ");
-        (node, rtt)
+        let stack = labels.get(node).map(Vec::as_slice).unwrap_or_default();
+        let node  = match names.get(node) {
+            Some(name) => format!("{} ({})", name, node),
+            None       => node.to_string(),
+        };
+        let rtt   = rtt.join(", ");
+        (node, rtt, stack)
     }).collect::<Vec<_>>();
 
     if count < probes {
         let node = "* ".repeat(probes - count);
         let rtt  = String::new();
-        output.push((node, rtt));
+        output.push((node, rtt, &[]));
     }
 
-    for (n, (node, rtt)) in output.iter().enumerate() {
+    for (n, (node, rtt, stack)) in output.iter().enumerate() {
         match n {
             0 => println!("[{:>3}] {:32} {}", ttl, node, rtt),
             _ => println!("[{:>3}] {:32} {}", "",  node, rtt),
         }
+
+        for label in stack.iter() {
+            println!("[{:>3}]   MPLS {}", "", label);
+        }
     }
 }
@@ -2,6 +2,8 @@ pub use client::Client;
 pub use server::Server;
 
 pub use server::Command;
+pub use server::Control;
+pub use server::Run;
 pub use server::Trace;
 
 mod client;
@@ -13,9 +13,10 @@ use tokio::sync::mpsc::{channel, Sender};
 use tokio::task::spawn;
 use tokio_util::codec::Decoder;
 use tokio_util::codec::length_delimited::LengthDelimitedCodec;
-use crate::status::Report;
+use crate::cfg;
+use crate::status::{Listing, Report};
 use crate::trace::Handles;
-use crate::watch::Event;
+use crate::watch::{Event, Once};
 
 pub struct Server {
     sock:  PathBuf,
@@ -25,6 +26,8 @@ pub struct Server {
 #[derive(Debug, Deserialize, Serialize)]
 pub enum Command {
     Status(String),
+    Run(String, Run),
+    Task(String, Control),
     Trace(Trace),
 }
 
@@ -32,7 +35,22 @@ pub enum Command {
 #[serde(untagged)]
 pub enum Response {
     Empty,
-    Report(Report),
+    Report(Box<Report>),
+    Records(Vec<String>),
+    Tasks(Vec<Listing>),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum Run {
+    Task(u64),
+    Config(String),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum Control {
+    Pause(u64),
+    Resume(u64),
+    List,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -86,8 +104,10 @@ async fn handle(stream: UnixStream, state: Arc<State>) -> Result<()> {
 
     while let Some(frame) = codec.next().await {
         let response = match serde_json::from_slice(&frame?)? {
-            Command::Status(r) => status(r, &state.report).await?,
-            Command::Trace(r)  => trace(r, &state.handles)?,
+            Command::Status(r)  => status(r, &state.report).await?,
+            Command::Run(r, t)  => run(r, t, &state.report).await?,
+            Command::Task(r, c) => task(r, c, &state.report).await?,
+            Command::Trace(r)   => trace(r, &state.handles)?,
         };
         let response = serde_json::to_vec(&response)?;
         codec.send(response.into()).await?;
@@ -108,11 +128,53 @@ async fn status(region: String, events: &HashMap<String, Sender<Event>>) -> Resu
     sender.send(request).await?;
 
     match rx.recv().await {
-        Some(report) => Ok(Response::Report(report)),
+        Some(report) => Ok(Response::Report(Box::new(report))),
         None         => Err(anyhow!("report missing")),
     }
 }
 
+async fn run(region: String, run: Run, events: &HashMap<String, Sender<Event>>) -> Result<Response> {
+    let (tx, mut rx) = channel(1);
+
+    let sender = match events.get(&region) {
+        Some(region) => region,
+        None         => return Err(anyhow!("invalid region: {region}")),
+    };
+
+    let once = match run {
+        Run::Task(id)     => Once::Task(id),
+        Run::Config(spec) => {
+            let (family, config) = cfg::task(&spec)?;
+            Once::Config(family, Box::new(config))
+        }
+    };
+
+    let request = Event::Run(once, tx);
+    sender.send(request).await?;
+
+    match rx.recv().await {
+        Some(records) => Ok(Response::Records(records?.iter().map(serde_json::to_string).collect::<Result<_, _>>()?)),
+        None          => Err(anyhow!("result missing")),
+    }
+}
+
+async fn task(region: String, control: Control, events: &HashMap<String, Sender<Event>>) -> Result<Response> {
+    let (tx, mut rx) = channel(1);
+
+    let sender = match events.get(&region) {
+        Some(region) => region,
+        None         => return Err(anyhow!("invalid region: {region}")),
+    };
+
+    let request = Event::Control(control, tx);
+    sender.send(request).await?;
+
+    match rx.recv().await {
+        Some(tasks) => Ok(Response::Tasks(tasks?)),
+        None        => Err(anyhow!("tasks missing")),
+    }
+}
+
 fn trace(trace: Trace, handles: &Handles) -> Result<Response> {
     match trace {
         Trace::Filter(filter) => handles.filter(filter.0)?,
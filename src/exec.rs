@@ -1,29 +1,43 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::future::Future;
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use anyhow::{anyhow, Result};
-use log::{debug, error};
-use tokio::sync::mpsc::{Sender, Receiver};
+use log::{debug, error, info, warn};
+use parking_lot::Mutex;
+use tokio::sync::mpsc::{unbounded_channel, Sender, Receiver, UnboundedSender};
+use tokio::time::{sleep, Instant};
 use synapi::agent::Net;
-use synapi::tasks::{State, TaskConfig};
+use synapi::tasks::{FollowupConfig, ResolverConfig, State, TaskConfig};
 use synapi::tasks::{FetchConfig, KnockConfig, PingConfig, QueryConfig, ShakeConfig, TraceConfig};
 use netdiag::{Bind, Knocker, Pinger, Tracer};
 use crate::cfg::Config;
-use crate::export::{Exporter, Target};
-use crate::net::{Network, Resolver};
+use crate::export::{Envoy, Exporter, Record, Target};
+use crate::net::{Network, Origins, Resolver, Settings, Source};
 use crate::net::tls::Shaker;
+use crate::secure::Privileges;
 use crate::spawn::{Spawner, Handle};
-use crate::status::{Active, Report, Status};
-use crate::task::{Task, Fetcher};
+use crate::ctl::Control;
+use crate::status::{Active, Listing, Queue, Report, Status};
+use crate::task::{Budget, Followup, Task, Fetcher};
 use crate::task::{Fetch, Knock, Ping, Query, Shake, Trace};
-use crate::watch::{Event, Tasks};
+use crate::watch::{Event, Once, Tasks};
 
 pub struct Executor {
-    tasks:    HashMap<u64, Handle>,
+    tasks:    HashMap<u64, Entry>,
+    paused:   HashSet<u64>,
     rx:       Receiver<Event>,
     ex:       Exporter,
+    target:   Option<Arc<Target>>,
     bind:     Bind,
     network:  Option<Network>,
+    origins:  Arc<Origins>,
     resolver: Resolver,
+    budget:   Arc<Budget>,
+    allowed:  Privileges,
     active:   Arc<Active>,
     status:   Arc<Status>,
     spawner:  Arc<Spawner>,
@@ -32,6 +46,35 @@ pub struct Executor {
     pinger:   Arc<Pinger>,
     shaker:   Arc<Shaker>,
     tracer:   Arc<Tracer>,
+    cache:    Cache,
+    stop:     Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+struct Cache {
+    resolvers: Mutex<HashMap<u64, Resolver>>,
+    knockers:  Mutex<HashMap<Source, Weak<Knocker>>>,
+    pingers:   Mutex<HashMap<Source, Weak<Pinger>>>,
+    tracers:   Mutex<HashMap<Source, Weak<Tracer>>>,
+}
+
+struct Entry {
+    handle: Option<Handle>,
+    envoy:  Envoy,
+    hash:   u64,
+    test:   u64,
+    family: Net,
+    config: TaskConfig,
+    fast:   Arc<AtomicBool>,
+}
+
+enum Probe {
+    Fetch(Fetch),
+    Knock(Knock),
+    Ping(Ping),
+    Query(Query),
+    Shake(Shake),
+    Trace(Box<Trace>),
 }
 
 #[derive(Clone)]
@@ -55,15 +98,40 @@ impl Executor {
 
         while let Some(event) = self.rx.recv().await {
             match event {
-                Event::Tasks(tasks) => self.tasks(tasks).await?,
-                Event::Reset        => self.reset().await?,
-                Event::Report(tx)   => self.report(tx).await?,
+                Event::Tasks(tasks)        => self.tasks(tasks).await?,
+                Event::Reset               => self.reset().await?,
+                Event::Report(tx)          => self.report(tx).await?,
+                Event::Run(once, tx)       => self.run(once, tx).await?,
+                Event::Control(ctl, tx)    => self.control(ctl, tx).await?,
+                Event::Shutdown(grace, tx) => return self.shutdown(grace, tx).await,
             }
         }
 
         Ok(())
     }
 
+    async fn shutdown(&mut self, grace: Duration, tx: Sender<Queue>) -> Result<()> {
+        info!("stopping {} tasks", self.tasks.len());
+
+        self.stop.store(true, Ordering::Relaxed);
+
+        let deadline = Instant::now() + grace;
+        while self.active.running() > 0 && Instant::now() < deadline {
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        match self.active.running() {
+            0 => debug!("all runs finished"),
+            n => warn!("abandoning {} unfinished runs", n),
+        }
+
+        self.tasks.clear();
+
+        let _ = tx.send(self.ex.flush().await).await;
+
+        Ok(())
+    }
+
     async fn reset(&mut self) -> Result<()> {
         debug!("resetting task state");
         self.tasks.clear();
@@ -71,8 +139,6 @@ impl Executor {
     }
 
     async fn tasks(&mut self, Tasks { agent, tasks }: Tasks) -> Result<()> {
-        let resolver = self.resolver.clone();
-
         for group in tasks {
             let target = Arc::new(Target {
                 company: group.company,
@@ -82,31 +148,33 @@ impl Executor {
                 token:   group.kentik.token,
             });
 
+            self.target = Some(target.clone());
+
             for task in group.tasks {
                 let id      = task.task;
                 let test    = task.test;
                 let config  = task.config;
                 let state   = task.state;
                 let family  = task.family;
-
-                let network = self.network.unwrap_or_else(|| {
-                    match family {
-                        Net::IPv4 => Network::IPv4,
-                        Net::IPv6 => Network::IPv6,
-                        Net::Dual => Network::Dual,
+                let hash    = digest(&(test, &family, &config));
+
+                match (&state, self.tasks.get(&id)) {
+                    (State::Deleted, _)                    => (),
+                    (_, Some(entry)) if entry.hash == hash => {
+                        entry.envoy.retarget(target.clone());
+                        debug!("unchanged task {}", id);
+                        continue;
                     }
-                });
-
-                let active   = self.active.clone();
-                let envoy    = self.ex.envoy(target.clone());
-                let resolver = resolver.clone();
+                    _                                      => (),
+                }
 
-                let task = Task::new(active, id, test, network, envoy, resolver);
+                let envoy = self.ex.envoy(target.clone());
+                let task  = self.task(id, test, &family, envoy);
 
                 let result = match state {
-                    State::Created => self.insert(task, config).await,
+                    State::Created => self.insert(task, config, family, hash).await,
                     State::Deleted => self.delete(id),
-                    State::Updated => self.insert(task, config).await,
+                    State::Updated => self.insert(task, config, family, hash).await,
                 };
 
                 match result {
@@ -115,23 +183,31 @@ impl Executor {
                 }
             }
         }
+
         Ok(())
     }
 
-    async fn insert(&mut self, task: Task, cfg: TaskConfig) -> Result<()> {
-        let id = task.task;
+    async fn insert(&mut self, task: Task, cfg: TaskConfig, family: Net, hash: u64) -> Result<()> {
+        let id    = task.task;
+        let test  = task.test;
+        let envoy = task.envoy.clone();
+        let fast  = task.fast.clone();
 
-        let handle = match cfg {
-            TaskConfig::Fetch(cfg) => self.fetch(id, task, cfg)?,
-            TaskConfig::Knock(cfg) => self.knock(id, task, cfg)?,
-            TaskConfig::Ping(cfg)  => self.ping(id, task, cfg)?,
-            TaskConfig::Query(cfg) => self.query(id, task, cfg).await?,
-            TaskConfig::Shake(cfg) => self.shake(id, task, cfg)?,
-            TaskConfig::Trace(cfg) => self.trace(id, task, cfg)?,
-            _                      => Err(anyhow!("unsupported type"))?,
+        let probe  = self.probe(task, cfg.clone()).await?;
+        let handle = match self.paused.contains(&id) {
+            true  => None,
+            false => Some(self.spawner.spawn(id, probe.exec())),
         };
 
-        self.tasks.insert(id, handle);
+        self.tasks.insert(id, Entry {
+            handle: handle,
+            envoy:  envoy,
+            hash:   hash,
+            test:   test,
+            family: family,
+            config: cfg,
+            fast:   fast,
+        });
 
         Ok(())
     }
@@ -139,48 +215,318 @@ impl Executor {
     fn delete(&mut self, id: u64) -> Result<()> {
         debug!("deleted task {}", id);
         self.tasks.remove(&id);
+        self.paused.remove(&id);
+        Ok(())
+    }
+
+    async fn control(&mut self, control: Control, tx: Sender<Result<Vec<Listing>>>) -> Result<()> {
+        let result = match control {
+            Control::Pause(id)  => self.pause(id),
+            Control::Resume(id) => self.resume(id).await,
+            Control::List       => Ok(self.list()),
+        };
+
+        let _ = tx.send(result).await;
+
+        Ok(())
+    }
+
+    fn pause(&mut self, id: u64) -> Result<Vec<Listing>> {
+        let entry = self.tasks.get_mut(&id).ok_or_else(|| anyhow!("unknown task {}", id))?;
+
+        entry.handle = None;
+        self.paused.insert(id);
+
+        info!("paused task {}", id);
+
+        Ok(self.list().into_iter().filter(|task| task.task == id).collect())
+    }
+
+    async fn resume(&mut self, id: u64) -> Result<Vec<Listing>> {
+        let entry = self.tasks.get(&id).ok_or_else(|| anyhow!("unknown task {}", id))?;
+
+        if entry.handle.is_none() {
+            let task   = self.task(id, entry.test, &entry.family, entry.envoy.clone());
+            let fast   = task.fast.clone();
+            let probe  = self.probe(task, entry.config.clone()).await?;
+            let handle = self.spawner.spawn(id, probe.exec());
+
+            if let Some(entry) = self.tasks.get_mut(&id) {
+                entry.handle = Some(handle);
+                entry.fast   = fast;
+            }
+        }
+
+        self.paused.remove(&id);
+
+        info!("resumed task {}", id);
+
+        Ok(self.list().into_iter().filter(|task| task.task == id).collect())
+    }
+
+    fn list(&self) -> Vec<Listing> {
+        let mut tasks = self.tasks.iter().map(|(id, entry)| Listing {
+            task:   *id,
+            test:   entry.test,
+            kind:   entry.config.kind().to_owned(),
+            paused: entry.handle.is_none(),
+            fast:   entry.fast.load(Ordering::Relaxed),
+        }).collect::<Vec<_>>();
+        tasks.sort_unstable_by_key(|task| task.task);
+        tasks
+    }
+
+    async fn run(&self, once: Once, tx: Sender<Result<Vec<Record>>>) -> Result<()> {
+        let (records, mut rx) = unbounded_channel();
+
+        let probe = match self.once(once, records).await {
+            Ok(probe) => probe,
+            Err(e)    => {
+                let _ = tx.send(Err(e)).await;
+                return Ok(());
+            }
+        };
+
+        tokio::spawn(async move {
+            let result = probe.once().await;
+
+            let mut values = Vec::new();
+            while let Ok(record) = rx.try_recv() {
+                values.push(record);
+            }
+
+            let _ = tx.send(result.map(|_| values)).await;
+        });
+
         Ok(())
     }
 
-    fn fetch(&self, id: u64, task: Task, cfg: FetchConfig) -> Result<Handle> {
+    async fn once(&self, once: Once, records: UnboundedSender<Record>) -> Result<Probe> {
+        let (id, test, family, cfg, envoy) = match once {
+            Once::Task(id) => {
+                let entry = self.tasks.get(&id).ok_or_else(|| anyhow!("unknown task {}", id))?;
+                let envoy = self.ex.envoy(entry.envoy.target());
+                (id, entry.test, entry.family.clone(), entry.config.clone(), envoy)
+            },
+            Once::Config(family, cfg) => {
+                let target = self.target.clone().ok_or_else(|| anyhow!("no export target"))?;
+                (0, 0, family, *cfg, self.ex.envoy(target).detach())
+            },
+        };
+
+        debug!("running task {} once", id);
+
+        let envoy = envoy.tap(records);
+        let task  = self.task(id, test, &family, envoy);
+
+        self.probe(task, cfg).await
+    }
+
+    fn task(&self, id: u64, test: u64, family: &Net, envoy: Envoy) -> Task {
+        let network = self.network.unwrap_or_else(|| {
+            match family {
+                Net::IPv4    => Network::IPv4,
+                Net::IPv6    => Network::IPv6,
+                Net::Dual    => Network::Dual,
+                Net::Compare => Network::Dual,
+            }
+        });
+
+        let compare  = family == &Net::Compare;
+        let active   = self.active.clone();
+        let resolver = self.resolver.clone();
+        let budget   = self.budget.clone();
+
+        Task::new(active, id, test, network, compare, envoy, resolver, budget)
+    }
+
+    async fn probe(&self, mut task: Task, cfg: TaskConfig) -> Result<Probe> {
+        task.resolver = self.resolver(cfg.resolver())?;
+        task.source   = Source::new(cfg.source())?;
+        task.source.permit(self.allowed)?;
+        task.schedule = cfg.schedule().cloned().unwrap_or_default();
+        task.stop     = self.stop.clone();
+
+        if let Some(addr) = task.source.address {
+            task.network = Network::from(addr);
+        }
+
+        task.followup = self.followup(&task, cfg.followup()).await?;
+
+        match cfg {
+            TaskConfig::Fetch(cfg) => self.fetch(task, cfg),
+            TaskConfig::Knock(cfg) => self.knock(task, cfg).await,
+            TaskConfig::Ping(cfg)  => self.ping(task, cfg).await,
+            TaskConfig::Query(cfg) => self.query(task, cfg),
+            TaskConfig::Shake(cfg) => self.shake(task, cfg),
+            TaskConfig::Trace(cfg) => self.trace(task, cfg).await,
+            _                      => Err(anyhow!("unsupported type")),
+        }
+    }
+
+    fn resolver(&self, cfg: Option<&ResolverConfig>) -> Result<Resolver> {
+        let cfg = match cfg {
+            Some(cfg) => cfg,
+            None      => return Ok(self.resolver.clone()),
+        };
+
+        let key = digest(cfg);
+
+        let mut cache = self.cache.resolvers.lock();
+        cache.retain(|_, resolver| resolver.is_shared());
+
+        if let Some(resolver) = cache.get(&key) {
+            return Ok(resolver.clone());
+        }
+
+        let ResolverConfig { servers, protocol, timeout, nocache } = cfg;
+        let timeout  = timeout.map(Duration::from);
+        let settings = Settings::new(servers, protocol, timeout, *nocache)?;
+        let resolver = Resolver::system(&self.bind, self.network, &settings, self.resolver.rules())?;
+
+        cache.insert(key, resolver.clone());
+
+        Ok(resolver)
+    }
+
+    async fn followup(&self, task: &Task, cfgs: &[FollowupConfig]) -> Result<Option<Followup>> {
+        if cfgs.is_empty() {
+            return Ok(None);
+        }
+        let tracer = self.tracer(&task.source).await?;
+        Ok(Some(Followup::new(task, cfgs, tracer)?))
+    }
+
+    fn fetch(&self, task: Task, cfg: FetchConfig) -> Result<Probe> {
         let fetch = Fetch::new(task, cfg, self.fetcher.clone())?;
-        Ok(self.spawner.spawn(id, fetch.exec()))
+        Ok(Probe::Fetch(fetch))
+    }
+
+    async fn knock(&self, task: Task, cfg: KnockConfig) -> Result<Probe> {
+        let knocker = self.knocker(&task.source).await?;
+        let knock   = Knock::new(task, cfg, knocker)?;
+        Ok(Probe::Knock(knock))
+    }
+
+    async fn ping(&self, task: Task, cfg: PingConfig) -> Result<Probe> {
+        let pinger  = self.pinger(&task.source).await?;
+        let knocker = self.knocker(&task.source).await?;
+        let ping    = Ping::new(task, cfg, &self.bind, pinger, knocker)?;
+        Ok(Probe::Ping(ping))
     }
 
-    fn knock(&self, id: u64, task: Task, cfg: KnockConfig) -> Result<Handle> {
-        let knock = Knock::new(task, cfg, self.knocker.clone());
-        Ok(self.spawner.spawn(id, knock.exec()))
+    fn query(&self, task: Task, cfg: QueryConfig) -> Result<Probe> {
+        let query = Query::new(task, cfg, &self.bind)?;
+        Ok(Probe::Query(query))
     }
 
-    fn ping(&self, id: u64, task: Task, cfg: PingConfig) -> Result<Handle> {
-        let ping = Ping::new(task, cfg, self.pinger.clone());
-        Ok(self.spawner.spawn(id, ping.exec()))
+    fn shake(&self, task: Task, cfg: ShakeConfig) -> Result<Probe> {
+        let shake = Shake::new(task, cfg, self.shaker.clone())?;
+        Ok(Probe::Shake(shake))
     }
 
-    async fn query(&self, id: u64, task: Task, cfg: QueryConfig) -> Result<Handle> {
-        let query = Query::new(task, cfg, &self.bind).await?;
-        Ok(self.spawner.spawn(id, query.exec()))
+    async fn trace(&self, task: Task, cfg: TraceConfig) -> Result<Probe> {
+        let tracer = self.tracer(&task.source).await?;
+        let trace  = Trace::new(task, cfg, tracer, self.origins.clone(), self.resolver.clone())?;
+        Ok(Probe::Trace(Box::new(trace)))
     }
 
-    #[allow(dead_code)]
-    fn shake(&self, id: u64, task: Task, cfg: ShakeConfig) -> Result<Handle> {
-        let shake = Shake::new(task, cfg, self.shaker.clone());
-        Ok(self.spawner.spawn(id, shake.exec()))
+    async fn knocker(&self, source: &Source) -> Result<Arc<Knocker>> {
+        let cache = &self.cache.knockers;
+        self.shared(cache, source, &self.knocker, |bind| async move {
+            Knocker::new(&bind).await
+        }).await
     }
 
-    fn trace(&self, id: u64, task: Task, cfg: TraceConfig) -> Result<Handle> {
-        let trace = Trace::new(task, cfg, self.tracer.clone());
-        Ok(self.spawner.spawn(id, trace.exec()))
+    async fn pinger(&self, source: &Source) -> Result<Arc<Pinger>> {
+        let cache = &self.cache.pingers;
+        self.shared(cache, source, &self.pinger, |bind| async move {
+            Pinger::new(&bind).await
+        }).await
+    }
+
+    async fn tracer(&self, source: &Source) -> Result<Arc<Tracer>> {
+        let cache = &self.cache.tracers;
+        self.shared(cache, source, &self.tracer, |bind| async move {
+            Tracer::new(&bind).await
+        }).await
+    }
+
+    async fn shared<T, F, R>(
+        &self,
+        cache:  &Mutex<HashMap<Source, Weak<T>>>,
+        source: &Source,
+        global: &Arc<T>,
+        create: F,
+    ) -> Result<Arc<T>>
+    where
+        F: FnOnce(Bind) -> R + Send + 'static,
+        R: Future<Output = Result<T>>,
+        T: Send + 'static,
+    {
+        let source = Source {
+            address: source.address,
+            netns:   source.netns.clone(),
+            ..Source::default()
+        };
+
+        if source.is_default() {
+            return Ok(global.clone());
+        }
+
+        if let Some(shared) = cache.lock().get(&source).and_then(Weak::upgrade) {
+            return Ok(shared);
+        }
+
+        let bind   = source.bind(&self.bind)?;
+        let shared = Arc::new(source.create(move || create(bind)).await?);
+
+        let mut cache = cache.lock();
+        cache.retain(|_, shared| shared.strong_count() > 0);
+        cache.insert(source, Arc::downgrade(&shared));
+
+        Ok(shared)
     }
 
     async fn report(&self, tx: Sender<Report>) -> Result<()> {
-        let mut tasks = self.tasks.keys().copied().collect::<Vec<_>>();
-        tasks.sort_unstable();
+        let list = self.list();
+        let fast = list.iter().filter(|task| task.fast).map(|task| task.task).collect();
+
+        let (paused, tasks) = list.into_iter().partition::<Vec<_>, _>(|task| task.paused);
+        let paused = paused.into_iter().map(|task| task.task).collect();
+        let tasks  = tasks.into_iter().map(|task| task.task).collect();
 
         let active = &self.active;
         let export = self.ex.report().await;
+        let dns    = self.resolver.rules().clone();
+        let health = self.resolver.health();
+        let waits  = self.budget.waits();
+
+        Ok(tx.send(Report::new(active, export, tasks, paused, fast, dns, health, waits)).await?)
+    }
+}
 
-        Ok(tx.send(Report::new(active, export, tasks)).await?)
+impl Probe {
+    async fn exec(self) -> Result<()> {
+        match self {
+            Self::Fetch(fetch) => fetch.exec().await,
+            Self::Knock(knock) => knock.exec().await,
+            Self::Ping(ping)   => ping.exec().await,
+            Self::Query(query) => query.exec().await,
+            Self::Shake(shake) => shake.exec().await,
+            Self::Trace(trace) => trace.exec().await,
+        }
+    }
+
+    async fn once(self) -> Result<()> {
+        match self {
+            Self::Fetch(fetch) => fetch.once().await,
+            Self::Knock(knock) => knock.once().await,
+            Self::Ping(ping)   => ping.once().await,
+            Self::Query(query) => query.once().await,
+            Self::Shake(shake) => shake.once().await,
+            Self::Trace(trace) => trace.once().await,
+        }
     }
 }
 
@@ -203,7 +549,7 @@ impl Factory {
     }
 
     pub fn executor(&self, rx: Receiver<Event>, ex: Exporter) -> Result<Executor> {
-        let Config { bind, network, resolver, .. } = self.config.clone();
+        let Config { allowed, bind, budget, network, origins, resolver, .. } = self.config.clone();
 
         let active  = Arc::new(Active::new());
         let status  = Arc::new(Status::default());
@@ -211,12 +557,17 @@ impl Factory {
 
         Ok(Executor {
             tasks:    HashMap::new(),
+            paused:   HashSet::new(),
             rx:       rx,
             ex:       ex,
+            target:   None,
             bind:     bind,
             network:  network,
+            origins:  origins,
             active:   active,
             resolver: resolver,
+            budget:   budget,
+            allowed:  allowed,
             status:   status,
             spawner:  Arc::new(spawner),
             fetcher:  self.fetcher.clone(),
@@ -224,6 +575,14 @@ impl Factory {
             pinger:   self.pinger.clone(),
             shaker:   self.shaker.clone(),
             tracer:   self.tracer.clone(),
+            cache:    Cache::default(),
+            stop:     Arc::new(AtomicBool::new(false)),
         })
     }
 }
+
+fn digest<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
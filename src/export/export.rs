@@ -1,7 +1,9 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 use anyhow::Result;
+use parking_lot::RwLock;
 use tokio::sync::Mutex;
+use tokio::sync::mpsc::UnboundedSender;
 use synapi::Client;
 use crate::cfg::Config;
 use crate::output::Args;
@@ -15,9 +17,11 @@ pub enum Exporter {
     NewRelic(Arc<newrelic::Exporter>),
 }
 
+#[derive(Clone)]
 pub struct Envoy {
-    export: Arc<Mutex<HashMap<Key, Output>>>,
-    target: Arc<Target>,
+    export: Option<Arc<Mutex<HashMap<Key, Output>>>>,
+    target: Arc<RwLock<Arc<Target>>>,
+    tap:    Option<UnboundedSender<Record>>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
@@ -69,6 +73,14 @@ impl Exporter {
         Queue { length, records }
     }
 
+    pub async fn flush(&self) -> Queue {
+        match self {
+            Exporter::Influx(export)   => export.flush().await,
+            Exporter::Kentik(export)   => export.flush().await,
+            Exporter::NewRelic(export) => export.flush().await,
+        }
+    }
+
     pub async fn exec(self) -> Result<()> {
         match self {
             Self::Influx(export)   => export.exec().await,
@@ -80,22 +92,54 @@ impl Exporter {
 
 impl Envoy {
     pub fn new(export: Arc<Mutex<HashMap<Key, Output>>>, target: Arc<Target>) -> Self {
-        Self { export, target }
+        let target = Arc::new(RwLock::new(target));
+        Self { export: Some(export), target, tap: None }
+    }
+
+    pub fn tap(self, tap: UnboundedSender<Record>) -> Self {
+        Self { tap: Some(tap), ..self }
+    }
+
+    pub fn detach(self) -> Self {
+        Self { export: None, ..self }
+    }
+
+    pub fn target(&self) -> Arc<Target> {
+        self.target.read().clone()
+    }
+
+    pub fn retarget(&self, target: Arc<Target>) {
+        *self.target.write() = target;
     }
 
     pub async fn export<T: Into<Record>>(&self, record: T) {
+        let target = self.target();
+        let record = record.into();
+
+        if let Some(tap) = &self.tap {
+            let _ = tap.send(record.clone());
+        }
+
+        let export = match &self.export {
+            Some(export) => export,
+            None         => return,
+        };
+
         let key = Key {
-            company: self.target.company,
-            device:  self.target.device.id,
+            company: target.company,
+            device:  target.device.id,
         };
 
-        let mut export = self.export.lock().await;
+        let mut export = export.lock().await;
 
-        export.entry(key).or_insert_with(|| {
+        let output = export.entry(key).or_insert_with(|| {
             Output {
-                target: self.target.clone(),
+                target: target.clone(),
                 values: Vec::new(),
             }
-        }).values.push(record.into());
+        });
+
+        output.target = target;
+        output.values.push(record);
     }
 }
@@ -2,6 +2,7 @@ use std::convert::TryFrom;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use anyhow::Result;
 use crate::export::{Record, record::*};
+use crate::net::Network;
 use super::wire::{Field, Point, Tag};
 
 pub fn encode(agent: &str, rs: &[Record], buf: &mut Vec<u8>) -> Result<()> {
@@ -17,6 +18,8 @@ pub fn encode(agent: &str, rs: &[Record], buf: &mut Vec<u8>) -> Result<()> {
             Record::Trace(data)   => trace(data, agent, timestamp, buf)?,
             Record::Error(_)      => (),
             Record::Timeout(_)    => (),
+            Record::Followup(_)   => (),
+            Record::Mode(_)       => (),
         }
     }
 
@@ -25,29 +28,68 @@ pub fn encode(agent: &str, rs: &[Record], buf: &mut Vec<u8>) -> Result<()> {
 
 fn fetch(data: &Fetch, agent: &str, ts: u128, buf: &mut Vec<u8>) -> Result<()> {
     let addr   = data.addr.to_string();
+    let net    = Network::from(data.addr).to_string();
     let status = i32::from(data.status);
     let rtt    = as_micros(data.rtt);
     let dns    = as_micros(data.dns);
     let tcp    = as_micros(data.tcp);
     let tls    = as_micros(data.tls);
     let size   = i64::try_from(data.size)?;
+    let tries  = i64::try_from(data.attempts)?;
+    let lag    = as_micros(data.lag);
+    let winner = data.race.map(|race| race.winner.to_string());
+    let mark   = data.mark.map(|mark| mark.to_string());
+
+    let mut tags = vec![
+        Tag { key: "agent",  value: agent           },
+        Tag { key: "task",   value: "fetch"         },
+        Tag { key: "target", value: &data.target    },
+        Tag { key: "addr",   value: &addr           },
+        Tag { key: "family", value: &net            },
+    ];
+
+    let mut fields = vec![
+        Field { key: "status", value: status.into() },
+        Field { key: "size",   value: size.into()   },
+        Field { key: "rtt",    value: rtt.into()    },
+        Field { key: "dns",    value: dns.into()    },
+        Field { key: "tcp",    value: tcp.into()    },
+        Field { key: "tls",    value: tls.into()    },
+        Field { key: "lag",    value: lag.into()    },
+    ];
+
+    fields.push(Field { key: "attempts", value: tries.into() });
+
+    if data.pinned {
+        tags.push(Tag { key: "pinned", value: "true" });
+    }
+
+    if let Some(sni) = &data.sni {
+        tags.push(Tag { key: "sni", value: sni });
+    }
+
+    if let Some(device) = &data.device {
+        tags.push(Tag { key: "interface", value: device });
+    }
+
+    if let Some(mark) = &mark {
+        tags.push(Tag { key: "mark", value: mark });
+    }
+
+    if data.maint {
+        tags.push(Tag { key: "maintenance", value: "true" });
+    }
+
+    if let (Some(race), Some(winner)) = (data.race, &winner) {
+        let margin = as_micros(race.margin);
+        tags.push(Tag { key: "winner", value: winner });
+        fields.push(Field { key: "margin", value: margin.into() });
+    }
 
     Point {
         measurement: "ksynth",
-        tags:        &[
-            Tag { key: "agent",  value: agent           },
-            Tag { key: "task",   value: "fetch"         },
-            Tag { key: "target", value: &data.target    },
-            Tag { key: "addr",   value: &addr           },
-        ],
-        fields:      &[
-            Field { key: "status", value: status.into() },
-            Field { key: "size",   value: size.into()   },
-            Field { key: "rtt",    value: rtt.into()    },
-            Field { key: "dns",    value: dns.into()    },
-            Field { key: "tcp",    value: tcp.into()    },
-            Field { key: "tls",    value: tls.into()    },
-        ],
+        tags:        &tags,
+        fields:      &fields,
         timestamp:   ts,
     }.write(buf);
 
@@ -56,25 +98,44 @@ fn fetch(data: &Fetch, agent: &str, ts: u128, buf: &mut Vec<u8>) -> Result<()> {
 
 fn knock(data: &Knock, agent: &str, ts: u128, buf: &mut Vec<u8>) -> Result<()> {
     let addr = data.addr.to_string();
+    let net  = Network::from(data.addr).to_string();
     let lost = i32::try_from(data.lost)?;
     let sent = i32::try_from(data.sent)?;
     let loss = f64::try_from(lost)? / f64::try_from(sent)?;
+    let lag  = as_micros(data.lag);
+    let mark = data.mark.map(|mark| mark.to_string());
+
+    let mut tags = vec![
+        Tag { key: "agent",  value: agent           },
+        Tag { key: "task",   value: "knock"         },
+        Tag { key: "target", value: &data.target    },
+        Tag { key: "addr",   value: &addr           },
+        Tag { key: "family", value: &net            },
+    ];
+
+    if let Some(device) = &data.device {
+        tags.push(Tag { key: "interface", value: device });
+    }
+
+    if let Some(mark) = &mark {
+        tags.push(Tag { key: "mark", value: mark });
+    }
+
+    if data.maint {
+        tags.push(Tag { key: "maintenance", value: "true" });
+    }
 
     for rtt in &data.result {
         let time = as_micros(*rtt);
         Point {
             measurement: "ksynth",
-            tags:        &[
-                Tag { key: "agent",  value: agent           },
-                Tag { key: "task",   value: "knock"         },
-                Tag { key: "target", value: &data.target    },
-                Tag { key: "addr",   value: &addr           },
-            ],
+            tags:        &tags,
             fields:      &[
                 Field { key: "lost", value: lost.into()     },
                 Field { key: "sent", value: sent.into()     },
                 Field { key: "loss", value: loss.into()     },
                 Field { key: "rtt",  value: time.into()     },
+                Field { key: "lag",  value: lag.into()      },
             ],
             timestamp:   ts,
         }.write(buf);
@@ -85,25 +146,54 @@ fn knock(data: &Knock, agent: &str, ts: u128, buf: &mut Vec<u8>) -> Result<()> {
 
 fn ping(data: &Ping, agent: &str, ts: u128, buf: &mut Vec<u8>) -> Result<()> {
     let addr = data.addr.to_string();
+    let net  = Network::from(data.addr).to_string();
     let lost = i32::try_from(data.lost)?;
     let sent = i32::try_from(data.sent)?;
     let loss = f64::try_from(lost)? / f64::try_from(sent)?;
+    let lag  = as_micros(data.lag);
+    let size = data.size.to_string();
+    let dscp = data.dscp.to_string();
+    let ttl  = data.ttl.to_string();
+    let df   = data.df.to_string();
+    let prot = data.proto.to_string();
+    let mark = data.mark.map(|mark| mark.to_string());
+
+    let mut tags = vec![
+        Tag { key: "agent",  value: agent           },
+        Tag { key: "task",   value: "ping"          },
+        Tag { key: "target", value: &data.target    },
+        Tag { key: "addr",   value: &addr           },
+        Tag { key: "family", value: &net            },
+        Tag { key: "size",   value: &size           },
+        Tag { key: "dscp",   value: &dscp           },
+        Tag { key: "ttl",    value: &ttl            },
+        Tag { key: "df",     value: &df             },
+        Tag { key: "proto",  value: &prot           },
+    ];
+
+    if let Some(device) = &data.device {
+        tags.push(Tag { key: "interface", value: device });
+    }
+
+    if let Some(mark) = &mark {
+        tags.push(Tag { key: "mark", value: mark });
+    }
+
+    if data.maint {
+        tags.push(Tag { key: "maintenance", value: "true" });
+    }
 
     for rtt in &data.result {
         let time = as_micros(*rtt);
         Point {
             measurement: "ksynth",
-            tags:        &[
-                Tag { key: "agent",  value: agent           },
-                Tag { key: "task",   value: "ping"          },
-                Tag { key: "target", value: &data.target    },
-                Tag { key: "addr",   value: &addr           },
-            ],
+            tags:        &tags,
             fields:      &[
                 Field { key: "lost", value: lost.into()     },
                 Field { key: "sent", value: sent.into()     },
                 Field { key: "loss", value: loss.into()     },
                 Field { key: "rtt",  value: time.into()     },
+                Field { key: "lag",  value: lag.into()      },
             ],
             timestamp:   ts,
         }.write(buf);
@@ -115,16 +205,33 @@ fn ping(data: &Ping, agent: &str, ts: u128, buf: &mut Vec<u8>) -> Result<()> {
 fn query(data: &Query, agent: &str, ts: u128, buf: &mut Vec<u8>) -> Result<()> {
     let code = i32::from(data.code);
     let time = as_micros(data.time);
+    let lag  = as_micros(data.lag);
+    let mark = data.mark.map(|mark| mark.to_string());
+
+    let mut tags = vec![
+        Tag { key: "agent",  value: agent        },
+        Tag { key: "task",   value: "query"      },
+    ];
+
+    if let Some(device) = &data.device {
+        tags.push(Tag { key: "interface", value: device });
+    }
+
+    if let Some(mark) = &mark {
+        tags.push(Tag { key: "mark", value: mark });
+    }
+
+    if data.maint {
+        tags.push(Tag { key: "maintenance", value: "true" });
+    }
 
     Point {
         measurement: "ksynth",
-        tags:        &[
-            Tag { key: "agent",  value: agent        },
-            Tag { key: "task",   value: "query"      },
-        ],
+        tags:        &tags,
         fields:      &[
             Field { key: "code",  value: code.into() },
             Field { key: "rtt",   value: time.into() },
+            Field { key: "lag",   value: lag.into()  },
         ],
         timestamp:   ts,
     }.write(buf);
@@ -134,20 +241,47 @@ fn query(data: &Query, agent: &str, ts: u128, buf: &mut Vec<u8>) -> Result<()> {
 
 fn shake(data: &Shake, agent: &str, ts: u128, buf: &mut Vec<u8>) -> Result<()> {
     let addr = data.addr.to_string();
+    let net  = Network::from(data.addr).to_string();
     let port = format!("{}", data.port);
     let time = as_micros(data.time);
+    let lag  = as_micros(data.lag);
+    let mark = data.mark.map(|mark| mark.to_string());
+
+    let mut tags = vec![
+        Tag { key: "agent",  value: agent        },
+        Tag { key: "task",   value: "shake"      },
+        Tag { key: "target", value: &data.target },
+        Tag { key: "addr",   value: &addr        },
+        Tag { key: "family", value: &net         },
+        Tag { key: "port",   value: &port        },
+    ];
+
+    if data.pinned {
+        tags.push(Tag { key: "pinned", value: "true" });
+    }
+
+    if let Some(sni) = &data.sni {
+        tags.push(Tag { key: "sni", value: sni });
+    }
+
+    if let Some(device) = &data.device {
+        tags.push(Tag { key: "interface", value: device });
+    }
+
+    if let Some(mark) = &mark {
+        tags.push(Tag { key: "mark", value: mark });
+    }
+
+    if data.maint {
+        tags.push(Tag { key: "maintenance", value: "true" });
+    }
 
     Point {
         measurement: "ksynth",
-        tags:        &[
-            Tag { key: "agent",  value: agent        },
-            Tag { key: "task",   value: "shake"      },
-            Tag { key: "target", value: &data.target },
-            Tag { key: "addr",   value: &addr        },
-            Tag { key: "port",   value: &port        },
-        ],
+        tags:        &tags,
         fields:      &[
             Field { key: "rtt",  value: time.into()  },
+            Field { key: "lag",  value: lag.into()   },
         ],
         timestamp:   ts,
     }.write(buf);
@@ -157,21 +291,42 @@ fn shake(data: &Shake, agent: &str, ts: u128, buf: &mut Vec<u8>) -> Result<()> {
 
 fn trace(data: &Trace, agent: &str, ts: u128, buf: &mut Vec<u8>) -> Result<()> {
     let addr = data.addr.to_string();
+    let net  = Network::from(data.addr).to_string();
     let time = as_micros(data.time);
     let hops = data.hops.iter().map(|hop| hop.hop).max();
     let hops = i64::try_from(hops.unwrap_or_default())?;
+    let path = aspath(&data.path);
+    let lag  = as_micros(data.lag);
+    let mark = data.mark.map(|mark| mark.to_string());
+
+    let mut tags = vec![
+        Tag { key: "agent",  value: agent        },
+        Tag { key: "task",   value: "trace"      },
+        Tag { key: "target", value: &data.target },
+        Tag { key: "addr",   value: &addr        },
+        Tag { key: "family", value: &net         },
+    ];
+
+    if let Some(device) = &data.device {
+        tags.push(Tag { key: "interface", value: device });
+    }
+
+    if let Some(mark) = &mark {
+        tags.push(Tag { key: "mark", value: mark });
+    }
+
+    if data.maint {
+        tags.push(Tag { key: "maintenance", value: "true" });
+    }
 
     Point {
         measurement: "ksynth",
-        tags:        &[
-            Tag { key: "agent",  value: agent        },
-            Tag { key: "task",   value: "trace"      },
-            Tag { key: "target", value: &data.target },
-            Tag { key: "addr",   value: &addr        },
-        ],
+        tags:        &tags,
         fields:      &[
-            Field { key: "hops", value: hops.into()  },
-            Field { key: "rtt",  value: time.into()  },
+            Field { key: "hops",   value: hops.into()          },
+            Field { key: "rtt",    value: time.into()          },
+            Field { key: "aspath", value: path.as_str().into() },
+            Field { key: "lag",    value: lag.into()           },
         ],
         timestamp:   ts,
     }.write(buf);
@@ -179,6 +334,10 @@ fn trace(data: &Trace, agent: &str, ts: u128, buf: &mut Vec<u8>) -> Result<()> {
     Ok(())
 }
 
+fn aspath(path: &[u32]) -> String {
+    path.iter().map(u32::to_string).collect::<Vec<_>>().join(" ")
+}
+
 fn as_micros(d: Duration) -> i32 {
     i32::try_from(d.as_micros()).unwrap_or(0)
 }
@@ -10,6 +10,7 @@ use tokio::time::interval;
 use crate::cfg::Config;
 use crate::export::{Envoy, Key, Output, Record, Target};
 use crate::output::Args;
+use crate::status::Queue;
 use super::{client::{Auth, Client}, encode};
 
 pub struct Exporter {
@@ -59,15 +60,26 @@ impl Exporter {
 
         loop {
             ticker.tick().await;
+            self.flush_into(&mut buffer).await;
+        }
+    }
+
+    pub async fn flush(&self) -> Queue {
+        self.flush_into(&mut Vec::new()).await
+    }
+
+    async fn flush_into(&self, buffer: &mut Vec<u8>) -> Queue {
+        let mut queue = Queue::default();
 
-            for o in self.drain().await.values() {
-                match self.send(&o.values, &mut buffer).await {
-                    Ok(()) => (),
-                    Err(e) => warn!("export failed: {:?}", e),
-                }
-                buffer.clear();
+        for o in self.drain().await.values() {
+            match self.send(&o.values, buffer).await {
+                Ok(()) => queue.push(o),
+                Err(e) => warn!("export failed: {:?}", e),
             }
+            buffer.clear();
         }
+
+        queue
     }
 
     async fn send(&self, records: &[Record], buffer: &mut Vec<u8>) -> Result<()> {
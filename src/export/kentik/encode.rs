@@ -6,6 +6,7 @@ use capnp::{message, serialize_packed};
 use serde_json::json;
 use crate::chf_capnp::{c_h_f::Builder, packed_c_h_f};
 use crate::export::{Record, Target, record::*};
+use crate::net::Network;
 use crate::net::tls::Identity;
 use super::Customs;
 
@@ -30,14 +31,16 @@ pub fn encode(target: &Target, rs: &[Record]) -> Result<Vec<u8>> {
         };
 
         match record {
-            Record::Fetch(data)   => cs.fetch(msg, agent, data)?,
-            Record::Knock(data)   => cs.knock(msg, agent, data),
-            Record::Ping(data)    => cs.ping(msg, agent, data),
-            Record::Query(data)   => cs.query(msg, agent, data),
-            Record::Shake(data)   => cs.shake(msg, agent, data)?,
-            Record::Trace(data)   => cs.trace(msg, agent, data),
-            Record::Error(data)   => cs.error(msg, agent, data),
-            Record::Timeout(data) => cs.timeout(msg, agent, data),
+            Record::Fetch(data)    => cs.fetch(msg, agent, data)?,
+            Record::Knock(data)    => cs.knock(msg, agent, data),
+            Record::Ping(data)     => cs.ping(msg, agent, data),
+            Record::Query(data)    => cs.query(msg, agent, data),
+            Record::Shake(data)    => cs.shake(msg, agent, data)?,
+            Record::Trace(data)    => cs.trace(msg, agent, data),
+            Record::Error(data)    => cs.error(msg, agent, data),
+            Record::Timeout(data)  => cs.timeout(msg, agent, data),
+            Record::Followup(data) => cs.followup(msg, agent, data),
+            Record::Mode(data)     => cs.mode(msg, agent, data),
         };
     }
 
@@ -69,6 +72,22 @@ struct Columns {
     times:  Times,
     valid:  u32,
     until:  u32,
+    dscp:   u32,
+    ttl:    u32,
+    bytes:  u32,
+    frag:   u32,
+    winner: u32,
+    margin: u32,
+    tries:  u32,
+    pinned: u32,
+    sni:    u32,
+    device: u32,
+    mark:   u32,
+    lag:    u32,
+    run:    u32,
+    action: u32,
+    period: u32,
+    maint:  u32,
 }
 
 struct Stats {
@@ -130,20 +149,46 @@ impl Columns {
             },
             valid:   lookup("INT07")?,
             until:   lookup("INT64_03")?,
+            dscp:    lookup("INT09")?,
+            ttl:     lookup("INT10")?,
+            bytes:   lookup("INT11")?,
+            frag:    lookup("INT12")?,
+            winner:  lookup("INT08")?,
+            margin:  lookup("INT09")?,
+            tries:   lookup("INT10")?,
+            pinned:  lookup("INT11")?,
+            sni:     lookup("STR01")?,
+            device:  lookup("STR02")?,
+            mark:    lookup("INT13")?,
+            lag:     lookup("INT14")?,
+            run:     lookup("INT64_03")?,
+            action:  lookup("STR01")?,
+            period:  lookup("INT64_04")?,
+            maint:   lookup("INT15")?,
         })
     }
 
     fn fetch(&self, mut msg: Builder, agent: u64, data: &Fetch) -> Result<()> {
-        let Fetch { task, test, addr, status, dns, tcp, tls, rtt, size, .. } = *data;
+        let Fetch { task, test, addr, status, dns, tcp, tls, rtt, size, lag, .. } = *data;
 
         let times = &self.times;
         let size  = u32::try_from(size).unwrap_or(0);
+        let tries = u32::try_from(data.attempts).unwrap_or(0);
+        let sni   = data.sni.as_deref().unwrap_or_default();
+        let dev   = data.device.as_deref().unwrap_or_default();
+        let mark  = data.mark.unwrap_or_default();
 
         let (valid, until) = match data.server {
             Identity::Valid(until) => (1, u64::try_from(until.timestamp())?),
             _                      => (0, 0),
         };
 
+        let (winner, margin) = match data.race {
+            Some(Race { winner: Network::IPv4, margin }) => (4, as_micros(margin)),
+            Some(Race { winner: Network::IPv6, margin }) => (6, as_micros(margin)),
+            _                                            => (0, 0),
+        };
+
         match addr {
             IpAddr::V4(ip) => msg.set_ipv4_dst_addr(ip.into()),
             IpAddr::V6(ip) => msg.set_ipv6_dst_addr(&ip.octets()),
@@ -156,7 +201,7 @@ impl Columns {
             "duration":        as_micros(rtt),
         }]))?;
 
-        let mut customs = Customs::new("fetch", msg, 14);
+        let mut customs = Customs::new("fetch", msg, 23);
         customs.next(self.app,    |v| v.set_uint32_val(AGENT));
         customs.next(self.agent,  |v| v.set_uint64_val(agent));
         customs.next(self.kind,   |v| v.set_uint32_val(FETCH));
@@ -171,19 +216,30 @@ impl Columns {
         customs.next(times.json,  |v| v.set_str_val(&timing));
         customs.next(self.valid,  |v| v.set_uint32_val(valid));
         customs.next(self.until,  |v| v.set_uint64_val(until));
+        customs.next(self.winner, |v| v.set_uint32_val(winner));
+        customs.next(self.margin, |v| v.set_uint32_val(margin));
+        customs.next(self.tries,  |v| v.set_uint32_val(tries));
+        customs.next(self.pinned, |v| v.set_uint32_val(data.pinned.into()));
+        customs.next(self.sni,    |v| v.set_str_val(sni));
+        customs.next(self.device, |v| v.set_str_val(dev));
+        customs.next(self.mark,   |v| v.set_uint32_val(mark));
+        customs.next(self.lag,    |v| v.set_uint32_val(as_micros(lag)));
+        customs.next(self.maint,  |v| v.set_uint32_val(data.maint.into()));
 
         Ok(())
     }
 
     fn knock(&self, mut msg: Builder, agent: u64, data: &Knock) {
-        let Knock { task, test, addr, port, sent, lost, rtt, .. } = *data;
+        let Knock { task, test, addr, port, sent, lost, rtt, lag, .. } = *data;
+        let dev  = data.device.as_deref().unwrap_or_default();
+        let mark = data.mark.unwrap_or_default();
 
         match addr {
             IpAddr::V4(ip) => msg.set_ipv4_dst_addr(ip.into()),
             IpAddr::V6(ip) => msg.set_ipv6_dst_addr(&ip.octets()),
         };
 
-        let mut customs = Customs::new("knock", msg,  13);
+        let mut customs = Customs::new("knock", msg,  17);
         customs.next(self.app,     |v| v.set_uint32_val(AGENT));
         customs.next(self.agent,   |v| v.set_uint64_val(agent));
         customs.next(self.kind,    |v| v.set_uint32_val(KNOCK));
@@ -197,17 +253,32 @@ impl Columns {
         customs.next(self.rtt.avg, |v| v.set_uint32_val(as_micros(rtt.avg)));
         customs.next(self.rtt.std, |v| v.set_uint32_val(as_micros(rtt.std)));
         customs.next(self.rtt.jit, |v| v.set_uint32_val(as_micros(rtt.jit)));
+        customs.next(self.device,  |v| v.set_str_val(dev));
+        customs.next(self.mark,    |v| v.set_uint32_val(mark));
+        customs.next(self.lag,     |v| v.set_uint32_val(as_micros(lag)));
+        customs.next(self.maint,   |v| v.set_uint32_val(data.maint.into()));
     }
 
     fn ping(&self, mut msg: Builder, agent: u64, data: &Ping) {
-        let Ping { task, test, addr, sent, lost, rtt, .. } = *data;
+        let Ping { task, test, addr, sent, lost, rtt, size, dscp, ttl, df, proto, lag, .. } = *data;
+        let dev  = data.device.as_deref().unwrap_or_default();
+        let mark = data.mark.unwrap_or_default();
 
         match addr {
             IpAddr::V4(ip) => msg.set_ipv4_dst_addr(ip.into()),
             IpAddr::V6(ip) => msg.set_ipv6_dst_addr(&ip.octets()),
         };
 
-        let mut customs = Customs::new("ping", msg, 12);
+        let (protocol, port) = match proto {
+            Protocol::Icmp      => (IPPROTO_ICMP, 0),
+            Protocol::Tcp(port) => (IPPROTO_TCP,  port),
+            Protocol::Udp(port) => (IPPROTO_UDP,  port),
+        };
+
+        msg.set_protocol(protocol);
+        msg.set_l4_dst_port(port.into());
+
+        let mut customs = Customs::new("ping", msg, 20);
         customs.next(self.app,     |v| v.set_uint32_val(AGENT));
         customs.next(self.agent,   |v| v.set_uint64_val(agent));
         customs.next(self.kind,    |v| v.set_uint32_val(PING));
@@ -220,14 +291,24 @@ impl Columns {
         customs.next(self.rtt.avg, |v| v.set_uint32_val(as_micros(rtt.avg)));
         customs.next(self.rtt.std, |v| v.set_uint32_val(as_micros(rtt.std)));
         customs.next(self.rtt.jit, |v| v.set_uint32_val(as_micros(rtt.jit)));
+        customs.next(self.dscp,    |v| v.set_uint32_val(dscp.into()));
+        customs.next(self.ttl,     |v| v.set_uint32_val(ttl.into()));
+        customs.next(self.bytes,   |v| v.set_uint32_val(size.into()));
+        customs.next(self.frag,    |v| v.set_uint32_val(df.into()));
+        customs.next(self.device,  |v| v.set_str_val(dev));
+        customs.next(self.mark,    |v| v.set_uint32_val(mark));
+        customs.next(self.lag,     |v| v.set_uint32_val(as_micros(lag)));
+        customs.next(self.maint,   |v| v.set_uint32_val(data.maint.into()));
     }
 
     fn query(&self, msg: Builder, agent: u64, data: &Query) {
-        let Query { task, test, code, time, .. } = *data;
+        let Query { task, test, code, time, lag, .. } = *data;
         let record  = &data.record;
         let answers = &data.answers;
+        let dev     = data.device.as_deref().unwrap_or_default();
+        let mark    = data.mark.unwrap_or_default();
 
-        let mut customs = Customs::new("query", msg, 9);
+        let mut customs = Customs::new("query", msg, 13);
         customs.next(self.app,    |v| v.set_uint32_val(AGENT));
         customs.next(self.agent,  |v| v.set_uint64_val(agent));
         customs.next(self.kind,   |v| v.set_uint32_val(QUERY));
@@ -237,10 +318,14 @@ impl Columns {
         customs.next(self.data,   |v| v.set_str_val(answers));
         customs.next(self.record, |v| v.set_str_val(record));
         customs.next(self.time,   |v| v.set_uint32_val(as_micros(time)));
+        customs.next(self.device, |v| v.set_str_val(dev));
+        customs.next(self.mark,   |v| v.set_uint32_val(mark));
+        customs.next(self.lag,    |v| v.set_uint32_val(as_micros(lag)));
+        customs.next(self.maint,  |v| v.set_uint32_val(data.maint.into()));
     }
 
     fn shake(&self, mut msg: Builder, agent: u64, data: &Shake) -> Result<()> {
-        let Shake { task, test, addr, port, time, .. } = *data;
+        let Shake { task, test, addr, port, time, lag, .. } = *data;
 
         match addr {
             IpAddr::V4(ip) => msg.set_ipv4_dst_addr(ip.into()),
@@ -252,71 +337,137 @@ impl Columns {
             _                      => (0, 0),
         };
 
-        let mut customs = Customs::new("shake", msg, 9);
-        customs.next(self.app,   |v| v.set_uint32_val(AGENT));
-        customs.next(self.agent, |v| v.set_uint64_val(agent));
-        customs.next(self.kind,  |v| v.set_uint32_val(SHAKE));
-        customs.next(self.task,  |v| v.set_uint64_val(task));
-        customs.next(self.test,  |v| v.set_uint64_val(test));
-        customs.next(self.port,  |v| v.set_uint32_val(port.into()));
-        customs.next(self.time,  |v| v.set_uint32_val(as_micros(time)));
-        customs.next(self.valid, |v| v.set_uint32_val(valid));
-        customs.next(self.until, |v| v.set_uint64_val(until));
+        let sni  = data.sni.as_deref().unwrap_or_default();
+        let dev  = data.device.as_deref().unwrap_or_default();
+        let mark = data.mark.unwrap_or_default();
+
+        let mut customs = Customs::new("shake", msg, 15);
+        customs.next(self.app,    |v| v.set_uint32_val(AGENT));
+        customs.next(self.agent,  |v| v.set_uint64_val(agent));
+        customs.next(self.kind,   |v| v.set_uint32_val(SHAKE));
+        customs.next(self.task,   |v| v.set_uint64_val(task));
+        customs.next(self.test,   |v| v.set_uint64_val(test));
+        customs.next(self.port,   |v| v.set_uint32_val(port.into()));
+        customs.next(self.time,   |v| v.set_uint32_val(as_micros(time)));
+        customs.next(self.valid,  |v| v.set_uint32_val(valid));
+        customs.next(self.until,  |v| v.set_uint64_val(until));
+        customs.next(self.pinned, |v| v.set_uint32_val(data.pinned.into()));
+        customs.next(self.sni,    |v| v.set_str_val(sni));
+        customs.next(self.device, |v| v.set_str_val(dev));
+        customs.next(self.mark,   |v| v.set_uint32_val(mark));
+        customs.next(self.lag,    |v| v.set_uint32_val(as_micros(lag)));
+        customs.next(self.maint,  |v| v.set_uint32_val(data.maint.into()));
 
         Ok(())
     }
 
     fn trace(&self, mut msg: Builder, agent: u64, data: &Trace) {
-        let Trace { task, test, addr, time, .. } = *data;
+        let Trace { task, test, addr, time, lag, .. } = *data;
 
         let route = &data.route;
+        let path  = aspath(&data.path);
+        let dev   = data.device.as_deref().unwrap_or_default();
+        let mark  = data.mark.unwrap_or_default();
 
         match addr {
             IpAddr::V4(ip) => msg.set_ipv4_dst_addr(ip.into()),
             IpAddr::V6(ip) => msg.set_ipv6_dst_addr(&ip.octets()),
         };
 
-        let mut customs = Customs::new("trace", msg, 7);
-        customs.next(self.app,   |v| v.set_uint32_val(AGENT));
-        customs.next(self.agent, |v| v.set_uint64_val(agent));
-        customs.next(self.kind,  |v| v.set_uint32_val(TRACE));
-        customs.next(self.task,  |v| v.set_uint64_val(task));
-        customs.next(self.test,  |v| v.set_uint64_val(test));
-        customs.next(self.route, |v| v.set_str_val(route));
-        customs.next(self.time,  |v| v.set_uint32_val(as_micros(time)));
+        if let Some(asn) = data.path.last() {
+            msg.set_dst_as(*asn);
+            msg.set_dst_bgp_as_path(&path);
+        }
+
+        let mut customs = Customs::new("trace", msg, 11);
+        customs.next(self.app,    |v| v.set_uint32_val(AGENT));
+        customs.next(self.agent,  |v| v.set_uint64_val(agent));
+        customs.next(self.kind,   |v| v.set_uint32_val(TRACE));
+        customs.next(self.task,   |v| v.set_uint64_val(task));
+        customs.next(self.test,   |v| v.set_uint64_val(test));
+        customs.next(self.route,  |v| v.set_str_val(route));
+        customs.next(self.time,   |v| v.set_uint32_val(as_micros(time)));
+        customs.next(self.device, |v| v.set_str_val(dev));
+        customs.next(self.mark,   |v| v.set_uint32_val(mark));
+        customs.next(self.lag,    |v| v.set_uint32_val(as_micros(lag)));
+        customs.next(self.maint,  |v| v.set_uint32_val(data.maint.into()));
     }
 
     fn error(&self, msg: Builder, agent: u64, data: &Error) {
-        let mut customs = Customs::new("error", msg, 6);
+        let mut customs = Customs::new("error", msg, 9);
         customs.next(self.app,   |v| v.set_uint32_val(AGENT));
         customs.next(self.agent, |v| v.set_uint64_val(agent));
         customs.next(self.kind,  |v| v.set_uint32_val(ERROR));
         customs.next(self.task,  |v| v.set_uint64_val(data.task));
         customs.next(self.test,  |v| v.set_uint64_val(data.test));
+        customs.next(self.run,   |v| v.set_uint64_val(data.run));
         customs.next(self.cause, |v| v.set_str_val(&data.cause));
+        customs.next(self.lag,   |v| v.set_uint32_val(as_micros(data.lag)));
+        customs.next(self.maint, |v| v.set_uint32_val(data.maint.into()));
     }
 
     fn timeout(&self, msg: Builder, agent: u64, data: &Timeout) {
-        let mut customs = Customs::new("timeout", msg, 5);
+        let mut customs = Customs::new("timeout", msg, 8);
         customs.next(self.app,   |v| v.set_uint32_val(AGENT));
         customs.next(self.agent, |v| v.set_uint64_val(agent));
         customs.next(self.kind,  |v| v.set_uint32_val(TIMEOUT));
         customs.next(self.task,  |v| v.set_uint64_val(data.task));
         customs.next(self.test,  |v| v.set_uint64_val(data.test));
+        customs.next(self.run,   |v| v.set_uint64_val(data.run));
+        customs.next(self.lag,   |v| v.set_uint32_val(as_micros(data.lag)));
+        customs.next(self.maint, |v| v.set_uint32_val(data.maint.into()));
+    }
+
+    fn followup(&self, msg: Builder, agent: u64, data: &Followup) {
+        let mut customs = Customs::new("followup", msg, 9);
+        customs.next(self.app,    |v| v.set_uint32_val(AGENT));
+        customs.next(self.agent,  |v| v.set_uint64_val(agent));
+        customs.next(self.kind,   |v| v.set_uint32_val(FOLLOWUP));
+        customs.next(self.task,   |v| v.set_uint64_val(data.task));
+        customs.next(self.test,   |v| v.set_uint64_val(data.test));
+        customs.next(self.run,    |v| v.set_uint64_val(data.run));
+        customs.next(self.action, |v| v.set_str_val(data.action));
+        customs.next(self.data,   |v| v.set_str_val(&data.output));
+        customs.next(self.time,   |v| v.set_uint32_val(as_micros(data.time)));
     }
+
+    fn mode(&self, msg: Builder, agent: u64, data: &Mode) {
+        let mode = if data.fast { "fast" } else { "normal" };
+
+        let mut customs = Customs::new("mode", msg, 9);
+        customs.next(self.app,    |v| v.set_uint32_val(AGENT));
+        customs.next(self.agent,  |v| v.set_uint64_val(agent));
+        customs.next(self.kind,   |v| v.set_uint32_val(MODE));
+        customs.next(self.task,   |v| v.set_uint64_val(data.task));
+        customs.next(self.test,   |v| v.set_uint64_val(data.test));
+        customs.next(self.run,    |v| v.set_uint64_val(data.run));
+        customs.next(self.action, |v| v.set_str_val(mode));
+        customs.next(self.cause,  |v| v.set_str_val(data.cause));
+        customs.next(self.period, |v| v.set_uint64_val(data.period.as_secs()));
+    }
+}
+
+fn aspath(path: &[u32]) -> String {
+    path.iter().map(u32::to_string).collect::<Vec<_>>().join(" ")
 }
 
 fn as_micros(d: Duration) -> u32 {
     u32::try_from(d.as_micros()).unwrap_or(0)
 }
 
-pub const AGENT:   u32 = 10;
-
-pub const ERROR:   u32 = 0;
-pub const TIMEOUT: u32 = 1;
-pub const PING:    u32 = 2;
-pub const FETCH:   u32 = 3;
-pub const TRACE:   u32 = 4;
-pub const KNOCK:   u32 = 5;
-pub const QUERY:   u32 = 6;
-pub const SHAKE:   u32 = 7;
+pub const AGENT:    u32 = 10;
+
+pub const ERROR:    u32 = 0;
+pub const TIMEOUT:  u32 = 1;
+pub const PING:     u32 = 2;
+pub const FETCH:    u32 = 3;
+pub const TRACE:    u32 = 4;
+pub const KNOCK:    u32 = 5;
+pub const QUERY:    u32 = 6;
+pub const SHAKE:    u32 = 7;
+pub const FOLLOWUP: u32 = 8;
+pub const MODE:     u32 = 9;
+
+pub const IPPROTO_ICMP: u32 = 1;
+pub const IPPROTO_TCP:  u32 = 6;
+pub const IPPROTO_UDP:  u32 = 17;
@@ -8,6 +8,7 @@ use tokio::sync::Mutex;
 use tokio::time::interval;
 use synapi::Client;
 use crate::export::{Envoy, Key, Output, Record, Target};
+use crate::status::Queue;
 use super::encode;
 
 pub struct Exporter {
@@ -33,14 +34,21 @@ impl Exporter {
         let mut ticker = interval(Duration::from_secs(10));
         loop {
             ticker.tick().await;
+            self.flush().await;
+        }
+    }
 
-            for o in self.drain().await.values() {
-                match self.send(&o.target, &o.values).await {
-                    Ok(()) => (),
-                    Err(e) => warn!("export failed: {:?}", e),
-                }
+    pub async fn flush(&self) -> Queue {
+        let mut queue = Queue::default();
+
+        for o in self.drain().await.values() {
+            match self.send(&o.target, &o.values).await {
+                Ok(()) => queue.push(o),
+                Err(e) => warn!("export failed: {:?}", e),
             }
         }
+
+        queue
     }
 
     async fn send(&self, target: &Target, records: &[Record]) -> Result<()> {
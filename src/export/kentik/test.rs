@@ -10,6 +10,7 @@ use capnp::serialize_packed::try_read_message;
 use rand::{thread_rng, Rng};
 use synapi::tasks::{Column, Device, Kind};
 use crate::chf_capnp::{custom::value::Which, packed_c_h_f};
+use crate::net::Network;
 use crate::net::tls::Identity;
 use crate::stats::Summary;
 use crate::export::{Record, Target, record::*};
@@ -32,6 +33,22 @@ fn encode_fetch() -> Result<()> {
     assert_eq!(Value::from(record.rtt),    values["INT02"]);
     assert_eq!(Value::from(record.size),   values["INT03"]);
     assert_eq!(Value::from(record.addr),   dst_addr(record.addr, &values));
+    assert_eq!(Value::from(record.attempts), values["INT10"]);
+    assert_eq!(Value::from(u32::from(record.pinned)), values["INT11"]);
+    assert_eq!(Value::from(record.sni.as_ref().unwrap()), values["STR01"]);
+    assert_eq!(Value::from(record.device.as_ref().unwrap()), values["STR02"]);
+    assert_eq!(Value::from(record.mark.unwrap()), values["INT13"]);
+    assert_eq!(Value::from(record.lag), values["INT14"]);
+    assert_eq!(Value::from(record.maint), values["INT15"]);
+
+    let race   = record.race.unwrap();
+    let winner = match race.winner {
+        Network::IPv4 => 4u32,
+        _             => 6u32,
+    };
+
+    assert_eq!(Value::from(winner),        values["INT08"]);
+    assert_eq!(Value::from(race.margin),   values["INT09"]);
 
     Ok(())
 }
@@ -58,6 +75,10 @@ fn encode_knock() -> Result<()> {
     assert_eq!(Value::from(record.rtt.jit), values["INT07"]);
     assert_eq!(Value::from(record.port),    values["INT08"]);
     assert_eq!(Value::from(record.addr),    dst_addr(record.addr, &values));
+    assert_eq!(Value::from(record.device.as_ref().unwrap()), values["STR02"]);
+    assert_eq!(Value::from(record.mark.unwrap()), values["INT13"]);
+    assert_eq!(Value::from(record.lag), values["INT14"]);
+    assert_eq!(Value::from(record.maint), values["INT15"]);
 
     Ok(())
 }
@@ -82,7 +103,24 @@ fn encode_ping() -> Result<()> {
     assert_eq!(Value::from(record.rtt.avg), values["INT05"]);
     assert_eq!(Value::from(record.rtt.std), values["INT06"]);
     assert_eq!(Value::from(record.rtt.jit), values["INT07"]);
+    assert_eq!(Value::from(record.dscp),    values["INT09"]);
+    assert_eq!(Value::from(record.ttl),     values["INT10"]);
+    assert_eq!(Value::from(record.size),    values["INT11"]);
+    assert_eq!(Value::from(record.df),      values["INT12"]);
     assert_eq!(Value::from(record.addr),    dst_addr(record.addr, &values));
+    assert_eq!(Value::from(record.device.as_ref().unwrap()), values["STR02"]);
+    assert_eq!(Value::from(record.mark.unwrap()), values["INT13"]);
+    assert_eq!(Value::from(record.lag), values["INT14"]);
+    assert_eq!(Value::from(record.maint), values["INT15"]);
+
+    let (protocol, port) = match record.proto {
+        Protocol::Icmp      => (IPPROTO_ICMP, 0),
+        Protocol::Tcp(port) => (IPPROTO_TCP,  port),
+        Protocol::Udp(port) => (IPPROTO_UDP,  port),
+    };
+
+    assert_eq!(Value::from(protocol),       values["PROTOCOL"]);
+    assert_eq!(Value::from(port),           values["L4_DST_PORT"]);
 
     Ok(())
 }
@@ -104,6 +142,10 @@ fn encode_query() -> Result<()> {
     assert_eq!(Value::from(record.code),     values["INT02"]);
     assert_eq!(Value::from(&record.answers), values["STR00"]);
     assert_eq!(Value::from(&record.record),  values["STR01"]);
+    assert_eq!(Value::from(record.device.as_ref().unwrap()), values["STR02"]);
+    assert_eq!(Value::from(record.mark.unwrap()), values["INT13"]);
+    assert_eq!(Value::from(record.lag), values["INT14"]);
+    assert_eq!(Value::from(record.maint), values["INT15"]);
 
     Ok(())
 }
@@ -124,6 +166,12 @@ fn encode_shake() -> Result<()> {
     assert_eq!(Value::from(record.time),  values["INT01"]);
     assert_eq!(Value::from(record.port),  values["INT08"]);
     assert_eq!(Value::from(record.addr),  dst_addr(record.addr, &values));
+    assert_eq!(Value::from(u32::from(record.pinned)), values["INT11"]);
+    assert_eq!(Value::from(record.sni.as_ref().unwrap()), values["STR01"]);
+    assert_eq!(Value::from(record.device.as_ref().unwrap()), values["STR02"]);
+    assert_eq!(Value::from(record.mark.unwrap()), values["INT13"]);
+    assert_eq!(Value::from(record.lag), values["INT14"]);
+    assert_eq!(Value::from(record.maint), values["INT15"]);
 
     Ok(())
 }
@@ -143,8 +191,20 @@ fn encode_trace() -> Result<()> {
     assert_eq!(Value::from(TRACE),         values["INT00"]);
     assert_eq!(Value::from(record.time),   values["INT01"]);
     assert_eq!(Value::from(&record.route), values["STR00"]);
+    assert_eq!(Value::from(record.device.as_ref().unwrap()), values["STR02"]);
+    assert_eq!(Value::from(record.mark.unwrap()), values["INT13"]);
+    assert_eq!(Value::from(record.lag), values["INT14"]);
+    assert_eq!(Value::from(record.maint), values["INT15"]);
     assert_eq!(Value::from(record.addr),   dst_addr(record.addr, &values));
 
+    let path = record.path.iter().map(u32::to_string).collect::<Vec<_>>();
+    let last = record.path.last().copied().unwrap_or_default();
+
+    let path = path.join(" ");
+
+    assert_eq!(Value::from(last),          values["DST_AS"]);
+    assert_eq!(Value::from(&path),         values["DST_BGP_AS_PATH"]);
+
     Ok(())
 }
 
@@ -161,7 +221,10 @@ fn encode_error() -> Result<()> {
     assert_eq!(Value::from(record.task),   values["INT64_01"]);
     assert_eq!(Value::from(record.test),   values["INT64_02"]);
     assert_eq!(Value::from(ERROR),         values["INT00"]);
+    assert_eq!(Value::from(record.run),    values["INT64_03"]);
     assert_eq!(Value::from(&record.cause), values["STR00"]);
+    assert_eq!(Value::from(record.lag),    values["INT14"]);
+    assert_eq!(Value::from(record.maint),  values["INT15"]);
 
     Ok(())
 }
@@ -179,6 +242,53 @@ fn encode_timeout() -> Result<()> {
     assert_eq!(Value::from(record.task),  values["INT64_01"]);
     assert_eq!(Value::from(record.test),  values["INT64_02"]);
     assert_eq!(Value::from(TIMEOUT),      values["INT00"]);
+    assert_eq!(Value::from(record.run),   values["INT64_03"]);
+    assert_eq!(Value::from(record.lag),   values["INT14"]);
+    assert_eq!(Value::from(record.maint), values["INT15"]);
+
+    Ok(())
+}
+
+#[test]
+fn encode_followup() -> Result<()> {
+    let mut rng = thread_rng();
+
+    let record = Followup::gen(&mut rng);
+    let target = target(&mut rng);
+    let values = serde(&target, record.clone())?;
+
+    assert_eq!(Value::from(AGENT),          values["APP_PROTOCOL"]);
+    assert_eq!(Value::from(target.agent),   values["INT64_00"]);
+    assert_eq!(Value::from(record.task),    values["INT64_01"]);
+    assert_eq!(Value::from(record.test),    values["INT64_02"]);
+    assert_eq!(Value::from(record.run),     values["INT64_03"]);
+    assert_eq!(Value::from(FOLLOWUP),       values["INT00"]);
+    assert_eq!(Value::from(&record.output), values["STR00"]);
+    assert_eq!(Value::from(record.time),    values["INT01"]);
+    assert_eq!(Value::String(record.action.to_owned()), values["STR01"]);
+
+    Ok(())
+}
+
+#[test]
+fn encode_mode() -> Result<()> {
+    let mut rng = thread_rng();
+
+    let record = Mode::gen(&mut rng);
+    let target = target(&mut rng);
+    let values = serde(&target, record.clone())?;
+
+    let mode = if record.fast { "fast" } else { "normal" };
+
+    assert_eq!(Value::from(AGENT),        values["APP_PROTOCOL"]);
+    assert_eq!(Value::from(target.agent), values["INT64_00"]);
+    assert_eq!(Value::from(record.task),  values["INT64_01"]);
+    assert_eq!(Value::from(record.test),  values["INT64_02"]);
+    assert_eq!(Value::from(record.run),   values["INT64_03"]);
+    assert_eq!(Value::from(MODE),         values["INT00"]);
+    assert_eq!(Value::String(mode.to_owned()),         values["STR01"]);
+    assert_eq!(Value::String(record.cause.to_owned()), values["STR00"]);
+    assert_eq!(Value::from(record.period.as_secs()),   values["INT64_04"]);
 
     Ok(())
 }
@@ -193,6 +303,18 @@ pub enum Value {
     Other,
 }
 
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::UInt32(v.into())
+    }
+}
+
+impl From<u8> for Value {
+    fn from(v: u8) -> Self {
+        Value::UInt32(v.into())
+    }
+}
+
 impl From<u16> for Value {
     fn from(v: u16) -> Self {
         Value::UInt32(v.into())
@@ -257,8 +379,14 @@ fn serde<T: Into<Record>>(target: &Target, record: T) -> Result<HashMap<String,
             _                  => [0u8; 16],
         });
 
-        values.insert("IPV4_DST_ADDR".to_owned(), Value::AddrV4(ip4));
-        values.insert("IPV6_DST_ADDR".to_owned(), Value::AddrV6(ip6.into()));
+        let path = msg.get_dst_bgp_as_path()?.to_string();
+
+        values.insert("IPV4_DST_ADDR".to_owned(),   Value::AddrV4(ip4));
+        values.insert("IPV6_DST_ADDR".to_owned(),   Value::AddrV6(ip6.into()));
+        values.insert("PROTOCOL".to_owned(),        Value::UInt32(msg.get_protocol()));
+        values.insert("L4_DST_PORT".to_owned(),     Value::UInt32(msg.get_l4_dst_port()));
+        values.insert("DST_AS".to_owned(),          Value::UInt32(msg.get_dst_as()));
+        values.insert("DST_BGP_AS_PATH".to_owned(), Value::String(path));
 
         for customs in msg.get_custom().iter() {
             for custom in customs.iter() {
@@ -338,17 +466,25 @@ fn random<T: Random, R: Rng>(rng: &mut R) -> T {
 impl Random for Fetch  {
     fn gen<R: Rng>(rng: &mut R) -> Self {
         Self {
-            task:   random(rng),
-            test:   random(rng),
-            target: Arc::new(random(rng)),
-            addr:   random(rng),
-            server: Identity::Unknown,
-            status: random(rng),
-            dns:    random(rng),
-            tcp:    random(rng),
-            tls:    random(rng),
-            rtt:    random(rng),
-            size:   random(rng),
+            task:     random(rng),
+            test:     random(rng),
+            target:   Arc::new(random(rng)),
+            addr:     random(rng),
+            server:   Identity::Unknown,
+            status:   random(rng),
+            dns:      random(rng),
+            tcp:      random(rng),
+            tls:      random(rng),
+            rtt:      random(rng),
+            size:     random(rng),
+            race:     Some(random(rng)),
+            attempts: random(rng),
+            pinned:   rng.gen(),
+            sni:      Some(random(rng)),
+            device:   Some(random(rng)),
+            mark:     Some(random(rng)),
+            lag:      random(rng),
+            maint:    rng.gen(),
         }
     }
 }
@@ -365,6 +501,10 @@ impl Random for Knock  {
             lost:   random(rng),
             rtt:    random(rng),
             result: random(rng),
+            device: Some(random(rng)),
+            mark:   Some(random(rng)),
+            lag:    random(rng),
+            maint:  rng.gen(),
         }
     }
 }
@@ -380,6 +520,15 @@ impl Random for Ping  {
             lost:   random(rng),
             rtt:    random(rng),
             result: random(rng),
+            size:   random(rng),
+            dscp:   random(rng),
+            ttl:    random(rng),
+            df:     random(rng),
+            proto:  random(rng),
+            device: Some(random(rng)),
+            mark:   Some(random(rng)),
+            lag:    random(rng),
+            maint:  rng.gen(),
         }
     }
 }
@@ -393,6 +542,10 @@ impl Random for Query  {
             record:  random(rng),
             answers: random(rng),
             time:    random(rng),
+            device:  Some(random(rng)),
+            mark:    Some(random(rng)),
+            lag:     random(rng),
+            maint:   rng.gen(),
         }
     }
 }
@@ -407,6 +560,12 @@ impl Random for Shake  {
             port:   random(rng),
             server: Identity::Unknown,
             time:   random(rng),
+            pinned: rng.gen(),
+            sni:    Some(random(rng)),
+            device: Some(random(rng)),
+            mark:   Some(random(rng)),
+            lag:    random(rng),
+            maint:  rng.gen(),
         }
     }
 }
@@ -419,8 +578,14 @@ impl Random for Trace  {
             target: Arc::new(random(rng)),
             addr:   random(rng),
             hops:   random(rng),
+            graph:  None,
+            path:   random(rng),
             route:  random(rng),
             time:   random(rng),
+            device: Some(random(rng)),
+            mark:   Some(random(rng)),
+            lag:    random(rng),
+            maint:  rng.gen(),
         }
     }
 }
@@ -430,7 +595,10 @@ impl Random for Error  {
         Self {
             task:  random(rng),
             test:  random(rng),
+            run:   random(rng),
             cause: random(rng),
+            lag:   random(rng),
+            maint: rng.gen(),
         }
     }
 }
@@ -438,8 +606,38 @@ impl Random for Error  {
 impl Random for Timeout  {
     fn gen<R: Rng>(rng: &mut R) -> Self {
         Self {
-            task: random(rng),
-            test: random(rng),
+            task:  random(rng),
+            test:  random(rng),
+            run:   random(rng),
+            lag:   random(rng),
+            maint: rng.gen(),
+        }
+    }
+}
+
+impl Random for Followup  {
+    fn gen<R: Rng>(rng: &mut R) -> Self {
+        Self {
+            task:   random(rng),
+            test:   random(rng),
+            run:    random(rng),
+            action: "trace",
+            target: Arc::new(random(rng)),
+            output: random(rng),
+            time:   random(rng),
+        }
+    }
+}
+
+impl Random for Mode  {
+    fn gen<R: Rng>(rng: &mut R) -> Self {
+        Self {
+            task:   random(rng),
+            test:   random(rng),
+            run:    random(rng),
+            fast:   random(rng),
+            cause:  "failure",
+            period: Duration::from_secs(random::<u32, _>(rng).into()),
         }
     }
 }
@@ -447,8 +645,33 @@ impl Random for Timeout  {
 impl Random for Hop {
     fn gen<R: Rng>(_rng: &mut R) -> Self {
         Self {
-            hop:   0,
-            nodes: HashMap::new(),
+            hop:    0,
+            nodes:  HashMap::new(),
+            names:  HashMap::new(),
+            labels: HashMap::new(),
+            origin: HashMap::new(),
+        }
+    }
+}
+
+impl Random for Race {
+    fn gen<R: Rng>(rng: &mut R) -> Self {
+        Self {
+            winner: match rng.gen() {
+                true  => Network::IPv4,
+                false => Network::IPv6,
+            },
+            margin: random(rng),
+        }
+    }
+}
+
+impl Random for Protocol {
+    fn gen<R: Rng>(rng: &mut R) -> Self {
+        match rng.gen_range(0..3) {
+            0 => Protocol::Icmp,
+            1 => Protocol::Tcp(rng.gen()),
+            _ => Protocol::Udp(rng.gen()),
         }
     }
 }
@@ -465,6 +688,18 @@ impl Random for Summary  {
     }
 }
 
+impl Random for bool {
+    fn gen<R: Rng>(rng: &mut R) -> Self {
+        rng.gen()
+    }
+}
+
+impl Random for u8 {
+    fn gen<R: Rng>(rng: &mut R) -> Self {
+        rng.gen()
+    }
+}
+
 impl Random for u16 {
     fn gen<R: Rng>(rng: &mut R) -> Self {
         rng.gen()
@@ -3,6 +3,7 @@ pub use export::Exporter;
 pub use export::Key;
 pub use export::Output;
 
+pub use record::Graph;
 pub use record::Hop;
 pub use record::Record;
 pub use record::Target;
@@ -2,6 +2,7 @@ use std::net::IpAddr;
 use anyhow::Result;
 use serde::Serialize;
 use crate::export::{Record, record};
+use crate::net::Network;
 
 #[derive(Debug, Serialize)]
 #[serde(tag = "eventType", rename_all = "lowercase")]
@@ -14,19 +15,37 @@ pub enum Event<'a> {
     Trace(Trace<'a>),
     Error(Error<'a>),
     Timeout,
+    Followup(Followup<'a>),
+    Mode(Mode<'a>),
 }
 
 #[derive(Debug, Serialize)]
 pub struct Fetch<'a> {
-    agent:  &'a str,
-    target: &'a str,
-    addr:   &'a IpAddr,
-    status: u16,
-    dns:    u128,
-    tcp:    u128,
-    tls:    u128,
-    rtt:    u128,
-    size:   usize,
+    agent:    &'a str,
+    target:   &'a str,
+    addr:     &'a IpAddr,
+    family:   String,
+    status:   u16,
+    dns:      u128,
+    tcp:      u128,
+    tls:      u128,
+    rtt:      u128,
+    size:     usize,
+    attempts: usize,
+    pinned:   bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sni:      Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    winner:   Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    margin:   Option<u128>,
+    #[serde(rename = "interface", skip_serializing_if = "Option::is_none")]
+    device:   Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mark:     Option<u32>,
+    lag:      u128,
+    #[serde(rename = "maintenance")]
+    maint:    bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -34,9 +53,17 @@ pub struct Knock<'a> {
     agent:  &'a str,
     target: &'a str,
     addr:   &'a IpAddr,
+    family: String,
     port:   u16,
     sent:   u32,
     lost:   u32,
+    #[serde(rename = "interface", skip_serializing_if = "Option::is_none")]
+    device: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mark:   Option<u32>,
+    lag:    u128,
+    #[serde(rename = "maintenance")]
+    maint:  bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -44,8 +71,21 @@ pub struct Ping<'a> {
     agent:  &'a str,
     target: &'a str,
     addr:   &'a IpAddr,
+    family: String,
     sent:   u32,
     lost:   u32,
+    size:   u16,
+    dscp:   u8,
+    ttl:    u8,
+    df:     bool,
+    proto:  String,
+    #[serde(rename = "interface", skip_serializing_if = "Option::is_none")]
+    device: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mark:   Option<u32>,
+    lag:    u128,
+    #[serde(rename = "maintenance")]
+    maint:  bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -55,6 +95,13 @@ pub struct Query<'a> {
     code:    u16,
     answers: &'a str,
     time:    u128,
+    #[serde(rename = "interface", skip_serializing_if = "Option::is_none")]
+    device:  Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mark:    Option<u32>,
+    lag:     u128,
+    #[serde(rename = "maintenance")]
+    maint:   bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -62,8 +109,19 @@ pub struct Shake<'a> {
     agent:  &'a str,
     target: &'a str,
     addr:   &'a IpAddr,
+    family: String,
     port:   u16,
     time:   u128,
+    pinned: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sni:    Option<&'a str>,
+    #[serde(rename = "interface", skip_serializing_if = "Option::is_none")]
+    device: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mark:   Option<u32>,
+    lag:    u128,
+    #[serde(rename = "maintenance")]
+    maint:  bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -71,42 +129,89 @@ pub struct Trace<'a> {
     agent:  &'a str,
     target: &'a str,
     addr:   &'a IpAddr,
+    family: String,
     hops:   usize,
+    aspath: &'a [u32],
     time:   u128,
+    #[serde(rename = "interface", skip_serializing_if = "Option::is_none")]
+    device: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mark:   Option<u32>,
+    lag:    u128,
+    #[serde(rename = "maintenance")]
+    maint:  bool,
 }
 
 #[derive(Debug, Serialize)]
 pub struct Error<'a> {
     agent: &'a str,
+    task:  u64,
+    run:   u64,
     cause: &'a str,
+    lag:   u128,
+    #[serde(rename = "maintenance")]
+    maint: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Followup<'a> {
+    agent:  &'a str,
+    task:   u64,
+    run:    u64,
+    action: &'a str,
+    target: &'a str,
+    output: &'a str,
+    time:   u128,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Mode<'a> {
+    agent:  &'a str,
+    task:   u64,
+    run:    u64,
+    mode:   &'a str,
+    cause:  &'a str,
+    period: u64,
 }
 
 pub fn encode(agent: &str, rs: &[Record], buf: &mut Vec<u8>) -> Result<()> {
     Ok(serde_json::to_writer(buf, &rs.iter().map(|r| {
         Ok(match r {
-            Record::Fetch(data)   => fetch(data, agent)?,
-            Record::Knock(data)   => knock(data, agent)?,
-            Record::Ping(data)    => ping(data, agent)?,
-            Record::Query(data)   => query(data, agent)?,
-            Record::Shake(data)   => shake(data, agent)?,
-            Record::Trace(data)   => trace(data, agent)?,
-            Record::Error(data)   => error(data, agent)?,
-            Record::Timeout(_)    => Event::Timeout,
+            Record::Fetch(data)    => fetch(data, agent)?,
+            Record::Knock(data)    => knock(data, agent)?,
+            Record::Ping(data)     => ping(data, agent)?,
+            Record::Query(data)    => query(data, agent)?,
+            Record::Shake(data)    => shake(data, agent)?,
+            Record::Trace(data)    => trace(data, agent)?,
+            Record::Error(data)    => error(data, agent)?,
+            Record::Timeout(_)     => Event::Timeout,
+            Record::Followup(data) => followup(data, agent)?,
+            Record::Mode(data)     => mode(data, agent)?,
         })
     }).collect::<Result<Vec<_>>>()?)?)
 }
 
 fn fetch<'a>(data: &'a record::Fetch, agent: &'a str) -> Result<Event<'a>> {
     Ok(Event::Fetch(Fetch {
-        agent:  agent,
-        target: &data.target,
-        addr:   &data.addr,
-        status: data.status,
-        dns:    data.dns.as_micros(),
-        tcp:    data.tcp.as_micros(),
-        tls:    data.tls.as_micros(),
-        rtt:    data.rtt.as_micros(),
-        size:   data.size,
+        agent:    agent,
+        target:   &data.target,
+        addr:     &data.addr,
+        family:   Network::from(data.addr).to_string(),
+        status:   data.status,
+        dns:      data.dns.as_micros(),
+        tcp:      data.tcp.as_micros(),
+        tls:      data.tls.as_micros(),
+        rtt:      data.rtt.as_micros(),
+        size:     data.size,
+        attempts: data.attempts,
+        pinned:   data.pinned,
+        sni:      data.sni.as_deref(),
+        winner:   data.race.map(|race| race.winner.to_string()),
+        margin:   data.race.map(|race| race.margin.as_micros()),
+        device:   data.device.as_deref(),
+        mark:     data.mark,
+        lag:      data.lag.as_micros(),
+        maint:    data.maint,
     }))
 }
 
@@ -115,9 +220,14 @@ fn knock<'a>(data: &'a record::Knock, agent: &'a str) -> Result<Event<'a>> {
         agent:  agent,
         target: &data.target,
         addr:   &data.addr,
+        family: Network::from(data.addr).to_string(),
         port:   data.port,
         sent:   data.sent,
         lost:   data.lost,
+        device: data.device.as_deref(),
+        mark:   data.mark,
+        lag:    data.lag.as_micros(),
+        maint:  data.maint,
     }))
 }
 
@@ -126,8 +236,18 @@ fn ping<'a>(data: &'a record::Ping, agent: &'a str) -> Result<Event<'a>> {
         agent:  agent,
         target: &data.target,
         addr:   &data.addr,
+        family: Network::from(data.addr).to_string(),
         sent:   data.sent,
         lost:   data.lost,
+        size:   data.size,
+        dscp:   data.dscp,
+        ttl:    data.ttl,
+        df:     data.df,
+        proto:  data.proto.to_string(),
+        device: data.device.as_deref(),
+        mark:   data.mark,
+        lag:    data.lag.as_micros(),
+        maint:  data.maint,
     }))
 }
 
@@ -138,6 +258,10 @@ fn query<'a>(data: &'a record::Query, agent: &'a str) -> Result<Event<'a>> {
         code:    data.code,
         answers: &data.answers,
         time:    data.time.as_micros(),
+        device:  data.device.as_deref(),
+        mark:    data.mark,
+        lag:     data.lag.as_micros(),
+        maint:   data.maint,
     }))
 }
 
@@ -146,8 +270,15 @@ fn shake<'a>(data: &'a record::Shake, agent: &'a str) -> Result<Event<'a>> {
         agent:  agent,
         target: &data.target,
         addr:   &data.addr,
+        family: Network::from(data.addr).to_string(),
         port:   data.port,
         time:   data.time.as_micros(),
+        pinned: data.pinned,
+        sni:    data.sni.as_deref(),
+        device: data.device.as_deref(),
+        mark:   data.mark,
+        lag:    data.lag.as_micros(),
+        maint:  data.maint,
     }))
 }
 
@@ -156,14 +287,47 @@ fn trace<'a>(data: &'a record::Trace, agent: &'a str) -> Result<Event<'a>> {
         agent:  agent,
         target: &data.target,
         addr:   &data.addr,
+        family: Network::from(data.addr).to_string(),
         hops:   data.hops.len(),
+        aspath: &data.path,
         time:   data.time.as_micros(),
+        device: data.device.as_deref(),
+        mark:   data.mark,
+        lag:    data.lag.as_micros(),
+        maint:  data.maint,
     }))
 }
 
 fn error<'a>(data: &'a record::Error, agent: &'a str) -> Result<Event<'a>> {
     Ok(Event::Error(Error {
         agent:  agent,
+        task:   data.task,
+        run:    data.run,
         cause:  &data.cause,
+        lag:    data.lag.as_micros(),
+        maint:  data.maint,
+    }))
+}
+
+fn followup<'a>(data: &'a record::Followup, agent: &'a str) -> Result<Event<'a>> {
+    Ok(Event::Followup(Followup {
+        agent:  agent,
+        task:   data.task,
+        run:    data.run,
+        action: data.action,
+        target: &data.target,
+        output: &data.output,
+        time:   data.time.as_micros(),
+    }))
+}
+
+fn mode<'a>(data: &'a record::Mode, agent: &'a str) -> Result<Event<'a>> {
+    Ok(Event::Mode(Mode {
+        agent:  agent,
+        task:   data.task,
+        run:    data.run,
+        mode:   if data.fast { "fast" } else { "normal" },
+        cause:  data.cause,
+        period: data.period.as_secs(),
     }))
 }
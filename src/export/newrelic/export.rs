@@ -10,6 +10,7 @@ use tokio::time::interval;
 use crate::cfg::Config;
 use crate::export::{Envoy, Key, Output, Target};
 use crate::output::Args;
+use crate::status::Queue;
 use super::event::Client;
 
 pub struct Exporter {
@@ -43,14 +44,21 @@ impl Exporter {
 
         loop {
             ticker.tick().await;
+            self.flush().await;
+        }
+    }
 
-            for o in self.drain().await.values() {
-                match self.client.send(&o.values).await {
-                    Ok(()) => (),
-                    Err(e) => warn!("export failed: {:?}", e),
-                }
+    pub async fn flush(&self) -> Queue {
+        let mut queue = Queue::default();
+
+        for o in self.drain().await.values() {
+            match self.client.send(&o.values).await {
+                Ok(()) => queue.push(o),
+                Err(e) => warn!("export failed: {:?}", e),
             }
         }
+
+        queue
     }
 
     async fn drain(&self) -> HashMap<Key, Output> {
@@ -2,6 +2,7 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use anyhow::Result;
 use serde::Serialize;
 use crate::export::{Record, record::*};
+use crate::net::Network;
 use super::metric::{Attribute, Attributes, Metric};
 
 #[derive(Debug, Default, Serialize)]
@@ -29,6 +30,8 @@ pub fn encode(agent: &str, rs: &[Record], buf: &mut Vec<u8>) -> Result<()> {
             Record::Trace(data)   => trace(data, agent, timestamp, buf)?,
             Record::Error(_)      => continue,
             Record::Timeout(_)    => continue,
+            Record::Followup(_)   => continue,
+            Record::Mode(_)       => continue,
         }
         buf.push(b',');
     }
@@ -40,20 +43,40 @@ pub fn encode(agent: &str, rs: &[Record], buf: &mut Vec<u8>) -> Result<()> {
 
 fn fetch(data: &Fetch, agent: &str, ts: Duration, buf: &mut Vec<u8>) -> Result<()> {
     let addr = data.addr.to_string();
+    let net  = Network::from(data.addr).to_string();
     let code = data.status as f64;
     let rtt  = as_micros(data.rtt);
     let dns  = as_micros(data.rtt);
     let tcp  = as_micros(data.tcp);
     let tls  = as_micros(data.tls);
     let size = data.size as f64;
+    let race = data.race.map(|race| (race.winner.to_string(), as_micros(race.margin)));
 
-    let common = &[
+    let mut common = vec![
         Attribute::String("agent",  agent),
         Attribute::String("task",   "fetch"),
         Attribute::String("target", &data.target),
         Attribute::String("addr",   &addr),
+        Attribute::String("family", &net),
+        Attribute::Boolean("pinned", data.pinned),
     ];
 
+    if let Some(sni) = &data.sni {
+        common.push(Attribute::String("sni", sni));
+    }
+
+    if let Some(device) = &data.device {
+        common.push(Attribute::String("interface", device));
+    }
+
+    if let Some(mark) = data.mark {
+        common.push(Attribute::Number("mark", mark.into()));
+    }
+
+    if data.maint {
+        common.push(Attribute::Boolean("maintenance", true));
+    }
+
     let code = Metric::gauge("ksynth.fetch.code", code, ts);
     let size = Metric::gauge("ksynth.fetch.size", size, ts);
     let rtt  = Metric::gauge("ksynth.fetch.rtt",  rtt, ts);
@@ -61,9 +84,18 @@ fn fetch(data: &Fetch, agent: &str, ts: Duration, buf: &mut Vec<u8>) -> Result<(
     let tcp  = Metric::gauge("ksynth.fetch.tcp",  tcp, ts);
     let tls  = Metric::gauge("ksynth.fetch.tls",  tls, ts);
 
-    let attributes = Attributes(common);
+    let mut metrics = vec![code, size, rtt, dns, tcp, tls];
+    metrics.push(Metric::gauge("ksynth.fetch.attempts", data.attempts as f64, ts));
+    metrics.push(Metric::gauge("ksynth.fetch.lag", as_micros(data.lag), ts));
+
+    if let Some((winner, margin)) = &race {
+        common.push(Attribute::String("winner", winner));
+        metrics.push(Metric::gauge("ksynth.fetch.margin", *margin, ts));
+    }
+
+    let attributes = Attributes(&common);
     serde_json::to_writer(buf, &Payload {
-        metrics: &[code, size, rtt, dns, tcp, tls],
+        metrics: &metrics,
         common:  Common { attributes },
     })?;
 
@@ -72,17 +104,31 @@ fn fetch(data: &Fetch, agent: &str, ts: Duration, buf: &mut Vec<u8>) -> Result<(
 
 fn knock(data: &Knock, agent: &str, ts: Duration, buf: &mut Vec<u8>) -> Result<()> {
     let addr = data.addr.to_string();
+    let net  = Network::from(data.addr).to_string();
     let lost = f64::from(data.lost);
     let sent = f64::from(data.sent);
     let loss = lost / sent;
 
-    let common = &[
+    let mut common = vec![
         Attribute::String("agent",  agent),
         Attribute::String("task",   "fetch"),
         Attribute::String("target", &data.target),
         Attribute::String("addr",   &addr),
+        Attribute::String("family", &net),
     ];
 
+    if let Some(device) = &data.device {
+        common.push(Attribute::String("interface", device));
+    }
+
+    if let Some(mark) = data.mark {
+        common.push(Attribute::Number("mark", mark.into()));
+    }
+
+    if data.maint {
+        common.push(Attribute::Boolean("maintenance", true));
+    }
+
     let mut metrics = Vec::new();
     for rtt in &data.result {
         let time = as_micros(*rtt);
@@ -91,8 +137,9 @@ fn knock(data: &Knock, agent: &str, ts: Duration, buf: &mut Vec<u8>) -> Result<(
         metrics.push(Metric::gauge("ksynth.knock.loss", loss, ts));
         metrics.push(Metric::gauge("ksynth.knock.rtt",  time, ts));
     }
+    metrics.push(Metric::gauge("ksynth.knock.lag", as_micros(data.lag), ts));
 
-    let attributes = Attributes(common);
+    let attributes = Attributes(&common);
     serde_json::to_writer(buf, &Payload {
         metrics: &metrics,
         common:  Common { attributes },
@@ -103,17 +150,37 @@ fn knock(data: &Knock, agent: &str, ts: Duration, buf: &mut Vec<u8>) -> Result<(
 
 fn ping(data: &Ping, agent: &str, ts: Duration, buf: &mut Vec<u8>) -> Result<()> {
     let addr = data.addr.to_string();
+    let net  = Network::from(data.addr).to_string();
     let lost = f64::from(data.lost);
     let sent = f64::from(data.sent);
     let loss = lost / sent;
+    let prot = data.proto.to_string();
 
-    let common = &[
+    let mut common = vec![
         Attribute::String("agent",  agent),
         Attribute::String("task",   "fetch"),
         Attribute::String("target", &data.target),
         Attribute::String("addr",   &addr),
+        Attribute::String("family", &net),
+        Attribute::String("proto",  &prot),
+        Attribute::Number("size",   data.size.into()),
+        Attribute::Number("dscp",   data.dscp.into()),
+        Attribute::Number("ttl",    data.ttl.into()),
+        Attribute::Boolean("df",    data.df),
     ];
 
+    if let Some(device) = &data.device {
+        common.push(Attribute::String("interface", device));
+    }
+
+    if let Some(mark) = data.mark {
+        common.push(Attribute::Number("mark", mark.into()));
+    }
+
+    if data.maint {
+        common.push(Attribute::Boolean("maintenance", true));
+    }
+
     let mut metrics = Vec::new();
     for rtt in &data.result {
         let time = as_micros(*rtt);
@@ -122,8 +189,9 @@ fn ping(data: &Ping, agent: &str, ts: Duration, buf: &mut Vec<u8>) -> Result<()>
         metrics.push(Metric::gauge("ksynth.ping.loss", loss, ts));
         metrics.push(Metric::gauge("ksynth.ping.rtt",  time, ts));
     }
+    metrics.push(Metric::gauge("ksynth.ping.lag", as_micros(data.lag), ts));
 
-    let attributes = Attributes(common);
+    let attributes = Attributes(&common);
     serde_json::to_writer(buf, &Payload {
         metrics: &metrics,
         common:  Common { attributes },
@@ -136,17 +204,30 @@ fn query(data: &Query, agent: &str, ts: Duration, buf: &mut Vec<u8>) -> Result<(
     let code = f64::from(data.code);
     let time = as_micros(data.time);
 
-    let common = &[
+    let mut common = vec![
         Attribute::String("agent", agent),
         Attribute::String("task",  "fetch"),
     ];
 
+    if let Some(device) = &data.device {
+        common.push(Attribute::String("interface", device));
+    }
+
+    if let Some(mark) = data.mark {
+        common.push(Attribute::Number("mark", mark.into()));
+    }
+
+    if data.maint {
+        common.push(Attribute::Boolean("maintenance", true));
+    }
+
     let code = Metric::gauge("ksynth.query.code", code, ts);
     let rtt  = Metric::gauge("ksynth.query.rtt",  time, ts);
+    let lag  = Metric::gauge("ksynth.query.lag",  as_micros(data.lag), ts);
 
-    let attributes = Attributes(common);
+    let attributes = Attributes(&common);
     serde_json::to_writer(buf, &Payload {
-        metrics: &[code, rtt],
+        metrics: &[code, rtt, lag],
         common:  Common { attributes },
     })?;
 
@@ -155,22 +236,42 @@ fn query(data: &Query, agent: &str, ts: Duration, buf: &mut Vec<u8>) -> Result<(
 
 fn shake(data: &Shake, agent: &str, ts: Duration, buf: &mut Vec<u8>) -> Result<()> {
     let addr = data.addr.to_string();
+    let net  = Network::from(data.addr).to_string();
     let time = as_micros(data.time);
     let port = u64::from(data.port);
 
-    let common = &[
+    let mut common = vec![
         Attribute::String("agent",  agent),
         Attribute::String("task",   "fetch"),
         Attribute::String("target", &data.target),
         Attribute::String("addr",   &addr),
+        Attribute::String("family", &net),
         Attribute::Number("port",   port),
+        Attribute::Boolean("pinned", data.pinned),
     ];
 
+    if let Some(sni) = &data.sni {
+        common.push(Attribute::String("sni", sni));
+    }
+
+    if let Some(device) = &data.device {
+        common.push(Attribute::String("interface", device));
+    }
+
+    if let Some(mark) = data.mark {
+        common.push(Attribute::Number("mark", mark.into()));
+    }
+
+    if data.maint {
+        common.push(Attribute::Boolean("maintenance", true));
+    }
+
     let rtt = Metric::gauge("ksynth.shake.rtt",  time, ts);
+    let lag = Metric::gauge("ksynth.shake.lag",  as_micros(data.lag), ts);
 
-    let attributes = Attributes(common);
+    let attributes = Attributes(&common);
     serde_json::to_writer(buf, &Payload {
-        metrics: &[rtt],
+        metrics: &[rtt, lag],
         common:  Common { attributes },
     })?;
 
@@ -179,29 +280,50 @@ fn shake(data: &Shake, agent: &str, ts: Duration, buf: &mut Vec<u8>) -> Result<(
 
 fn trace(data: &Trace, agent: &str, ts: Duration, buf: &mut Vec<u8>) -> Result<()> {
     let addr = data.addr.to_string();
+    let net  = Network::from(data.addr).to_string();
     let time = as_micros(data.time);
     let hops = data.hops.iter().map(|hop| hop.hop).max();
     let hops = hops.unwrap_or_default() as f64;
+    let path = aspath(&data.path);
 
-    let common = &[
+    let mut common = vec![
         Attribute::String("agent",  agent),
         Attribute::String("task",   "fetch"),
         Attribute::String("target", &data.target),
         Attribute::String("addr",   &addr),
+        Attribute::String("family", &net),
+        Attribute::String("aspath", &path),
     ];
 
+    if let Some(device) = &data.device {
+        common.push(Attribute::String("interface", device));
+    }
+
+    if let Some(mark) = data.mark {
+        common.push(Attribute::Number("mark", mark.into()));
+    }
+
+    if data.maint {
+        common.push(Attribute::Boolean("maintenance", true));
+    }
+
     let hops = Metric::gauge("ksynth.trace.hops", hops, ts);
     let rtt  = Metric::gauge("ksynth.trace.rtt",  time, ts);
+    let lag  = Metric::gauge("ksynth.trace.lag",  as_micros(data.lag), ts);
 
-    let attributes = Attributes(common);
+    let attributes = Attributes(&common);
     serde_json::to_writer(buf, &Payload {
-        metrics: &[hops, rtt],
+        metrics: &[hops, rtt, lag],
         common:  Common { attributes },
     })?;
 
     Ok(())
 }
 
+fn aspath(path: &[u32]) -> String {
+    path.iter().map(u32::to_string).collect::<Vec<_>>().join(" ")
+}
+
 fn as_micros(d: Duration) -> f64 {
     d.as_micros() as f64
 }
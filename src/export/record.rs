@@ -1,9 +1,12 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::net::IpAddr;
 use std::sync::Arc;
 use std::time::Duration;
 use serde::Serialize;
 use synapi::tasks::Device;
+use crate::net::{Network, Origin};
+use crate::net::mpls::Label;
 use crate::net::tls::Identity;
 use crate::stats::Summary;
 
@@ -16,7 +19,8 @@ pub struct Target {
     pub token:   String,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum Record {
     Fetch(Fetch),
     Knock(Knock),
@@ -26,24 +30,40 @@ pub enum Record {
     Trace(Trace),
     Error(Error),
     Timeout(Timeout),
+    Followup(Followup),
+    Mode(Mode),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Fetch {
-    pub task:    u64,
-    pub test:    u64,
-    pub target:  Arc<String>,
-    pub addr:    IpAddr,
-    pub server:  Identity,
-    pub status:  u16,
-    pub dns:     Duration,
-    pub tcp:     Duration,
-    pub tls:     Duration,
-    pub rtt:     Duration,
-    pub size:    usize,
+    pub task:     u64,
+    pub test:     u64,
+    pub target:   Arc<String>,
+    pub addr:     IpAddr,
+    pub server:   Identity,
+    pub status:   u16,
+    pub dns:      Duration,
+    pub tcp:      Duration,
+    pub tls:      Duration,
+    pub rtt:      Duration,
+    pub size:     usize,
+    pub attempts: usize,
+    pub pinned:   bool,
+    pub sni:      Option<String>,
+    pub race:     Option<Race>,
+    pub device:   Option<String>,
+    pub mark:     Option<u32>,
+    pub lag:      Duration,
+    pub maint:    bool,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct Race {
+    pub winner: Network,
+    pub margin: Duration,
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct Knock {
     pub task:    u64,
     pub test:    u64,
@@ -54,9 +74,13 @@ pub struct Knock {
     pub lost:    u32,
     pub rtt:     Summary,
     pub result:  Vec<Duration>,
+    pub device:  Option<String>,
+    pub mark:    Option<u32>,
+    pub lag:     Duration,
+    pub maint:   bool,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Ping {
     pub task:    u64,
     pub test:    u64,
@@ -66,9 +90,18 @@ pub struct Ping {
     pub lost:    u32,
     pub rtt:     Summary,
     pub result:  Vec<Duration>,
+    pub size:    u16,
+    pub dscp:    u8,
+    pub ttl:     u8,
+    pub df:      bool,
+    pub proto:   Protocol,
+    pub device:  Option<String>,
+    pub mark:    Option<u32>,
+    pub lag:     Duration,
+    pub maint:   bool,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Query {
     pub task:    u64,
     pub test:    u64,
@@ -76,9 +109,13 @@ pub struct Query {
     pub record:  String,
     pub answers: String,
     pub time:    Duration,
+    pub device:  Option<String>,
+    pub mark:    Option<u32>,
+    pub lag:     Duration,
+    pub maint:   bool,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Shake {
     pub task:    u64,
     pub test:    u64,
@@ -87,36 +124,128 @@ pub struct Shake {
     pub port:    u16,
     pub server:  Identity,
     pub time:    Duration,
+    pub pinned:  bool,
+    pub sni:     Option<String>,
+    pub device:  Option<String>,
+    pub mark:    Option<u32>,
+    pub lag:     Duration,
+    pub maint:   bool,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Trace {
     pub task:    u64,
     pub test:    u64,
     pub target:  Arc<String>,
     pub addr:    IpAddr,
     pub hops:    Vec<Hop>,
+    pub graph:   Option<Graph>,
+    pub path:    Vec<u32>,
     pub route:   String,
     pub time:    Duration,
+    pub device:  Option<String>,
+    pub mark:    Option<u32>,
+    pub lag:     Duration,
+    pub maint:   bool,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Icmp,
+    Tcp(u16),
+    Udp(u16),
 }
 
 #[derive(Clone, Debug, Serialize)]
 pub struct Hop {
-    pub hop:   usize,
-    pub nodes: HashMap<IpAddr, Vec<u64>>,
+    pub hop:    usize,
+    pub nodes:  HashMap<IpAddr, Vec<u64>>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub names:  HashMap<IpAddr, String>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<IpAddr, Vec<Label>>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub origin: HashMap<IpAddr, Origin>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Graph {
+    pub flows: usize,
+    pub nodes: Vec<Vertex>,
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Vertex {
+    pub hop:    usize,
+    pub addr:   IpAddr,
+    pub flows:  Vec<usize>,
+    pub rtt:    Vec<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name:   Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<Label>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<Origin>,
+}
+
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct Edge {
+    pub flow: usize,
+    pub hop:  usize,
+    pub from: IpAddr,
+    pub to:   IpAddr,
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct Error {
     pub task:   u64,
     pub test:   u64,
+    pub run:    u64,
     pub cause:  String,
+    pub lag:    Duration,
+    pub maint:  bool,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Timeout {
-    pub task: u64,
-    pub test: u64,
+    pub task:  u64,
+    pub test:  u64,
+    pub run:   u64,
+    pub lag:   Duration,
+    pub maint: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Followup {
+    pub task:   u64,
+    pub test:   u64,
+    pub run:    u64,
+    pub action: &'static str,
+    pub target: Arc<String>,
+    pub output: String,
+    pub time:   Duration,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Mode {
+    pub task:   u64,
+    pub test:   u64,
+    pub run:    u64,
+    pub fast:   bool,
+    pub cause:  &'static str,
+    pub period: Duration,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Icmp    => write!(f, "icmp"),
+            Self::Tcp(_)  => write!(f, "tcp-syn"),
+            Self::Udp(_)  => write!(f, "udp"),
+        }
+    }
 }
 
 impl From<Fetch> for Record  {
@@ -166,3 +295,15 @@ impl From<Timeout> for Record  {
         Record::Timeout(timeout)
     }
 }
+
+impl From<Followup> for Record  {
+    fn from(followup: Followup) -> Self {
+        Record::Followup(followup)
+    }
+}
+
+impl From<Mode> for Record  {
+    fn from(mode: Mode) -> Self {
+        Record::Mode(mode)
+    }
+}
@@ -0,0 +1,315 @@
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+use std::os::raw::c_int;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use futures::stream::{self, Stream, StreamExt};
+use libc::{IPPROTO_IP, IPPROTO_IPV6, IPV6_TCLASS, IPV6_UNICAST_HOPS, IP_TOS, IP_TTL};
+use netdiag::Bind;
+use nix::sys::socket::{self, InetAddr, MsgFlags, SockAddr};
+use rand::random;
+use tokio::io::unix::AsyncFd;
+use tokio::time::timeout;
+use super::Source;
+use super::source::setopt;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Options {
+    pub size: Option<u16>,
+    pub dscp: u8,
+    pub ttl:  Option<u8>,
+    pub df:   bool,
+}
+
+pub struct Echo {
+    socket: AsyncFd<Socket>,
+    addr:   IpAddr,
+    ident:  u16,
+    size:   usize,
+}
+
+pub(super) struct Socket(RawFd);
+
+impl Options {
+    pub fn new(size: Option<u16>, dscp: u8, ttl: Option<u8>, df: bool) -> Result<Self> {
+        if dscp > 63 {
+            return Err(anyhow!("invalid dscp: {dscp}"));
+        }
+        Ok(Self { size, dscp, ttl, df })
+    }
+
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub fn apply<S: AsRawFd>(&self, socket: &S, addr: &IpAddr) -> Result<()> {
+        let fd = socket.as_raw_fd();
+
+        let tos = c_int::from(self.dscp << 2).to_ne_bytes();
+        let ttl = self.ttl.map(|ttl| c_int::from(ttl).to_ne_bytes());
+        let df  = c_int::from(self.df).to_ne_bytes();
+
+        match addr {
+            IpAddr::V4(_) => {
+                if self.dscp > 0 {
+                    setopt(fd, IPPROTO_IP, IP_TOS, &tos)?;
+                }
+                if let Some(ttl) = ttl {
+                    setopt(fd, IPPROTO_IP, IP_TTL, &ttl)?;
+                }
+                if self.df {
+                    setopt(fd, IPPROTO_IP, IP_MTU_DISCOVER, &IP_PMTUDISC_DO.to_ne_bytes())?;
+                }
+            },
+            IpAddr::V6(_) => {
+                if self.dscp > 0 {
+                    setopt(fd, IPPROTO_IPV6, IPV6_TCLASS, &tos)?;
+                }
+                if let Some(ttl) = ttl {
+                    setopt(fd, IPPROTO_IPV6, IPV6_UNICAST_HOPS, &ttl)?;
+                }
+                if self.df {
+                    setopt(fd, IPPROTO_IPV6, IPV6_DONTFRAG, &df)?;
+                }
+            },
+        }
+
+        Ok(())
+    }
+}
+
+impl Echo {
+    pub async fn new(source: &Source, bind: &Bind, addr: IpAddr, options: &Options) -> Result<Self> {
+        let target = SocketAddr::new(addr, 0);
+        let bind   = match addr {
+            IpAddr::V4(_) => bind.sa4(),
+            IpAddr::V6(_) => bind.sa6(),
+        };
+
+        let socket = source.enter(|| Socket::new(&addr))?;
+        source.apply(&socket, &target)?;
+        options.apply(&socket, &addr)?;
+
+        let local = source.local(bind, &target)?;
+        socket::bind(socket.0, &SockAddr::new_inet(InetAddr::from_std(&local)))?;
+        socket::connect(socket.0, &SockAddr::new_inet(InetAddr::from_std(&target)))?;
+
+        Ok(Self {
+            socket: AsyncFd::new(socket)?,
+            addr:   addr,
+            ident:  random(),
+            size:   options.size.map(usize::from).unwrap_or(PAYLOAD),
+        })
+    }
+
+    pub fn ping(&self, count: usize, expiry: Duration) -> impl Stream<Item = Result<Option<Duration>>> + '_ {
+        stream::iter(0..count).then(move |seq| self.probe(seq as u16, expiry))
+    }
+
+    async fn probe(&self, seq: u16, expiry: Duration) -> Result<Option<Duration>> {
+        let mut pkt = vec![0u8; HEADER + self.size];
+        encode(&mut pkt, self.addr.is_ipv6(), self.ident, seq);
+
+        let sent = Instant::now();
+        self.send(&pkt).await?;
+
+        match timeout(expiry, self.recv(seq)).await {
+            Ok(Ok(when)) => Ok(Some(when.saturating_duration_since(sent))),
+            Ok(Err(e))   => Err(e),
+            Err(_)       => Ok(None),
+        }
+    }
+
+    async fn send(&self, pkt: &[u8]) -> Result<()> {
+        loop {
+            let mut guard = self.socket.writable().await?;
+            match guard.try_io(|socket| socket.get_ref().send(pkt)) {
+                Ok(result) => return Ok(result.map(drop)?),
+                Err(_)     => continue,
+            }
+        }
+    }
+
+    async fn recv(&self, seq: u16) -> Result<Instant> {
+        let mut buf = vec![0u8; IPV4 + HEADER + self.size];
+        let ipv6    = self.addr.is_ipv6();
+
+        loop {
+            let mut guard = self.socket.readable().await?;
+
+            let n = match guard.try_io(|socket| socket.get_ref().recv(&mut buf)) {
+                Ok(result) => result?,
+                Err(_)     => continue,
+            };
+
+            if decode(&buf[..n], ipv6) == Some((self.ident, seq)) {
+                return Ok(Instant::now());
+            }
+        }
+    }
+}
+
+impl Socket {
+    pub(super) fn new(addr: &IpAddr) -> Result<Self> {
+        use libc::{socket, AF_INET, AF_INET6, IPPROTO_ICMP, IPPROTO_ICMPV6, SOCK_CLOEXEC, SOCK_NONBLOCK, SOCK_RAW};
+
+        let (domain, proto) = match addr {
+            IpAddr::V4(_) => (AF_INET,  IPPROTO_ICMP),
+            IpAddr::V6(_) => (AF_INET6, IPPROTO_ICMPV6),
+        };
+
+        match unsafe { socket(domain, SOCK_RAW | SOCK_NONBLOCK | SOCK_CLOEXEC, proto) } {
+            -1 => Err(Error::last_os_error().into()),
+            fd => Ok(Self(fd)),
+        }
+    }
+
+    fn send(&self, buf: &[u8]) -> Result<usize, Error> {
+        socket::send(self.0, buf, MsgFlags::empty()).map_err(Error::from)
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        socket::recv(self.0, buf, MsgFlags::empty()).map_err(Error::from)
+    }
+
+    pub(super) fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, IpAddr), Error> {
+        match socket::recvfrom(self.0, buf).map_err(Error::from)? {
+            (n, Some(SockAddr::Inet(addr))) => Ok((n, addr.ip().to_std())),
+            _                               => Err(Error::from(ErrorKind::InvalidData)),
+        }
+    }
+}
+
+impl AsRawFd for Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0) };
+    }
+}
+
+fn encode(pkt: &mut [u8], ipv6: bool, ident: u16, seq: u16) {
+    pkt[0] = match ipv6 {
+        false => ECHO_REQUEST_V4,
+        true  => ECHO_REQUEST_V6,
+    };
+    pkt[4..6].copy_from_slice(&ident.to_be_bytes());
+    pkt[6..8].copy_from_slice(&seq.to_be_bytes());
+
+    for (n, byte) in pkt[HEADER..].iter_mut().enumerate() {
+        *byte = n as u8;
+    }
+
+    if !ipv6 {
+        let sum = checksum(pkt);
+        pkt[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+}
+
+fn decode(pkt: &[u8], ipv6: bool) -> Option<(u16, u16)> {
+    let (icmp, reply) = match ipv6 {
+        false => (pkt.get(usize::from(pkt.first()? & 0x0f) * 4..)?, ECHO_REPLY_V4),
+        true  => (pkt, ECHO_REPLY_V6),
+    };
+
+    match icmp {
+        [kind, 0, _, _, a, b, c, d, ..] if *kind == reply => {
+            let ident = u16::from_be_bytes([*a, *b]);
+            let seq   = u16::from_be_bytes([*c, *d]);
+            Some((ident, seq))
+        },
+        _ => None,
+    }
+}
+
+fn checksum(pkt: &[u8]) -> u16 {
+    let mut sum = pkt.chunks(2).map(|chunk| {
+        match chunk {
+            [x, y] => u32::from(u16::from_be_bytes([*x, *y])),
+            [x]    => u32::from(u16::from_be_bytes([*x, 0])),
+            _      => 0,
+        }
+    }).sum::<u32>();
+
+    while sum >> 16 > 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+const HEADER:  usize = 8;
+const IPV4:    usize = 60;
+const PAYLOAD: usize = 56;
+
+const ECHO_REQUEST_V4: u8 = 8;
+const ECHO_REPLY_V4:   u8 = 0;
+const ECHO_REQUEST_V6: u8 = 128;
+const ECHO_REPLY_V6:   u8 = 129;
+
+#[cfg(target_os = "linux")]
+const IP_MTU_DISCOVER: c_int = libc::IP_MTU_DISCOVER;
+
+#[cfg(target_os = "linux")]
+const IP_PMTUDISC_DO: c_int = libc::IP_PMTUDISC_DO;
+
+#[cfg(target_os = "linux")]
+const IPV6_DONTFRAG: c_int = libc::IPV6_DONTFRAG;
+
+#[cfg(not(target_os = "linux"))]
+const IP_MTU_DISCOVER: c_int = -1;
+
+#[cfg(not(target_os = "linux"))]
+const IP_PMTUDISC_DO: c_int = -1;
+
+#[cfg(not(target_os = "linux"))]
+const IPV6_DONTFRAG: c_int = -1;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn echo_options() {
+        assert!(Options::new(Some(1400), 46, Some(8), true).is_ok());
+        assert!(Options::new(None, 63, None, false).is_ok());
+        assert!(Options::new(None, 64, None, false).is_err());
+        assert!(Options::new(None, 255, None, false).is_err());
+        assert!(Options::new(None, 0, None, false).unwrap().is_default());
+    }
+
+    #[test]
+    fn echo_encode() {
+        let mut pkt = [0u8; HEADER + 4];
+        encode(&mut pkt, false, 0x1234, 7);
+        assert_eq!(&[8, 0], &pkt[0..2]);
+        assert_eq!(&[0x12, 0x34, 0, 7, 0, 1, 2, 3], &pkt[4..]);
+        assert_eq!(0, checksum(&pkt));
+
+        let mut pkt = [0u8; HEADER];
+        encode(&mut pkt, true, 0x1234, 7);
+        assert_eq!([128, 0, 0, 0, 0x12, 0x34, 0, 7], pkt);
+    }
+
+    #[test]
+    fn echo_decode() {
+        let mut ipv4 = [0u8; 20 + HEADER];
+        ipv4[0] = 0x45;
+        ipv4[20..].copy_from_slice(&[0, 0, 0, 0, 0x12, 0x34, 0, 7]);
+        assert_eq!(Some((0x1234, 7)), decode(&ipv4, false));
+
+        ipv4[20] = ECHO_REQUEST_V4;
+        assert_eq!(None, decode(&ipv4, false));
+        assert_eq!(None, decode(&ipv4[..24], false));
+
+        let ipv6 = [129, 0, 0, 0, 0x12, 0x34, 0, 7];
+        assert_eq!(Some((0x1234, 7)), decode(&ipv6, true));
+        assert_eq!(None, decode(&ipv6, false));
+        assert_eq!(None, decode(&[], true));
+    }
+}
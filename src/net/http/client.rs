@@ -1,19 +1,22 @@
 use std::convert::{TryFrom, TryInto};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use anyhow::{anyhow, Error, Result};
+use futures::stream::{FuturesUnordered, StreamExt};
 use http::{HeaderMap, Method, header::HOST};
 use http::response::Parts;
 use http::uri::{self, Port, Uri};
 use hyper::{self, body::Body};
 use hyper::client::conn::Builder;
+use tokio::net::TcpStream;
+use tokio::time::sleep;
 use tracing::{error, trace};
 use netdiag::Bind;
 use rustls::{ClientConfig, RootCertStore, ServerName};
 use tokio_rustls::TlsConnector;
-use crate::net::{Network, Resolver};
+use crate::net::{Network, Policy, Resolver, Source};
 use crate::net::tls::{Identity, Verifier};
 use super::stream::{socket, Connection, Peer};
 
@@ -27,22 +30,28 @@ pub struct HttpClient {
 
 #[derive(Debug)]
 pub struct Request {
-    method:  Method,
-    uri:     Uri,
-    scheme:  Scheme,
-    host:    String,
-    port:    u16,
-    headers: HeaderMap,
-    body:    Body,
-    network: Network,
+    method:   Method,
+    uri:      Uri,
+    scheme:   Scheme,
+    host:     String,
+    port:     u16,
+    headers:  HeaderMap,
+    body:     Body,
+    network:  Network,
+    addr:     Option<IpAddr>,
+    sni:      Option<String>,
+    race:     bool,
+    resolver: Option<Resolver>,
+    source:   Source,
 }
 
 #[derive(Debug)]
 pub struct Response {
-    pub head:  Parts,
-    pub body:  Body,
-    pub peer:  Peer,
-    pub times: Times,
+    pub head:     Parts,
+    pub body:     Body,
+    pub peer:     Peer,
+    pub times:    Times,
+    pub attempts: usize,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -58,6 +67,8 @@ pub struct Times {
     pub tls: Option<Duration>,
 }
 
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
 impl HttpClient {
     pub fn new(bind: Bind, resolver: Resolver, roots: RootCertStore) -> Result<Self> {
         let verifier = Arc::new(Verifier::new(roots));
@@ -75,7 +86,7 @@ impl HttpClient {
     }
 
     pub async fn request(&self, request: Request) -> Result<Response> {
-        let (conn, times) = self.connect(&request).await?;
+        let (conn, times, attempts) = self.connect(&request).await?;
 
         let http2 = conn.http2();
         let peer  = conn.peer();
@@ -96,33 +107,37 @@ impl HttpClient {
         let res = tx.send_request(req).await?;
         let (head, body) = res.into_parts();
 
-        Ok(Response { head, body, peer, times })
+        Ok(Response { head, body, peer, times, attempts })
     }
 
-    async fn connect(&self, request: &Request) -> Result<(Connection, Times)> {
-        let Request { scheme, ref host, port, network, .. } = *request;
+    async fn connect(&self, request: &Request) -> Result<(Connection, Times, usize)> {
+        let Request { scheme, ref host, port, network, addr, ref sni, race, .. } = *request;
+
+        let resolver = request.resolver.as_ref().unwrap_or(&self.resolver);
 
         let mut times = Times::default();
 
         let start  = Instant::now();
-        let addr   = self.resolver.lookup(host, network).await?;
+        let addrs  = match (addr, race) {
+            (Some(addr), _) => vec![addr],
+            (None,   false) => vec![resolver.lookup(host, network).await?],
+            (None,   true)  => resolver.select(host, network, Policy::All).await?,
+        };
 
         times.dns = start.elapsed();
 
         let start  = Instant::now();
-        let addr   = SocketAddr::new(addr, port);
-        let socket = socket(&self.bind, &addr).await?;
-        let stream = socket.connect(addr).await?;
+        let (stream, attempts) = self.race(addrs, port, &request.source).await?;
 
         times.tcp = start.elapsed();
 
         if scheme == Scheme::HTTP {
             let server = Identity::Unknown;
             let conn   = (stream, server).try_into()?;
-            return Ok((conn, times))
+            return Ok((conn, times, attempts))
         }
 
-        let dnsname = ServerName::try_from(host.as_str())?;
+        let dnsname = ServerName::try_from(sni.as_deref().unwrap_or(host))?;
 
         let start  = Instant::now();
         let stream = self.tls.connect(dnsname.clone(), stream).await?;
@@ -134,7 +149,55 @@ impl HttpClient {
         let server = self.verifier.verify(certs, &dnsname)?;
         let conn   = (stream, server).try_into()?;
 
-        Ok((conn, times))
+        Ok((conn, times, attempts))
+    }
+
+    async fn race(&self, addrs: Vec<IpAddr>, port: u16, source: &Source) -> Result<(TcpStream, usize)> {
+        let mut addrs    = interleave(addrs).into_iter();
+        let mut pending  = FuturesUnordered::new();
+        let mut attempts = 0;
+        let mut error    = None;
+
+        loop {
+            if let Some(addr) = addrs.next() {
+                let addr = SocketAddr::new(addr, port);
+                let bind = &self.bind;
+                pending.push(async move {
+                    let socket = socket(bind, source, &addr).await?;
+                    Result::<_, Error>::Ok(socket.connect(addr).await?)
+                });
+                attempts += 1;
+            } else if pending.is_empty() {
+                return Err(error.unwrap_or_else(|| anyhow!("no address")));
+            }
+
+            let waiting = !addrs.as_slice().is_empty();
+
+            tokio::select! {
+                Some(result) = pending.next() => match result {
+                    Ok(stream) => return Ok((stream, attempts)),
+                    Err(e)     => error = Some(e),
+                },
+                _ = sleep(ATTEMPT_DELAY), if waiting => (),
+            }
+        }
+    }
+}
+
+fn interleave(addrs: Vec<IpAddr>) -> Vec<IpAddr> {
+    let (ip6, ip4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(IpAddr::is_ipv6);
+
+    let mut ip6 = ip6.into_iter();
+    let mut ip4 = ip4.into_iter();
+    let mut vec = Vec::new();
+
+    loop {
+        match (ip6.next(), ip4.next()) {
+            (Some(a), Some(b)) => vec.extend([a, b]),
+            (Some(a), None)    => vec.push(a),
+            (None,    Some(b)) => vec.push(b),
+            (None,    None)    => return vec,
+        }
     }
 }
 
@@ -153,17 +216,42 @@ impl Request {
         };
 
         Ok(Self {
-            method:  method,
-            uri:     uri,
-            scheme:  scheme,
-            host:    host,
-            port:    port,
-            headers: HeaderMap::new(),
-            body:    Body::empty(),
-            network: network,
+            method:   method,
+            uri:      uri,
+            scheme:   scheme,
+            host:     host,
+            port:     port,
+            headers:  HeaderMap::new(),
+            body:     Body::empty(),
+            network:  network,
+            addr:     None,
+            sni:      None,
+            race:     false,
+            resolver: None,
+            source:   Source::default(),
         })
     }
 
+    pub fn addr(&mut self) -> &mut Option<IpAddr> {
+        &mut self.addr
+    }
+
+    pub fn sni(&mut self) -> &mut Option<String> {
+        &mut self.sni
+    }
+
+    pub fn race(&mut self) -> &mut bool {
+        &mut self.race
+    }
+
+    pub fn resolver(&mut self) -> &mut Option<Resolver> {
+        &mut self.resolver
+    }
+
+    pub fn source(&mut self) -> &mut Source {
+        &mut self.source
+    }
+
     pub fn headers(&mut self) -> &mut HeaderMap {
         &mut self.headers
     }
@@ -235,6 +323,20 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn interleave_addrs() -> Result<()> {
+        let a4 = "192.0.2.1".parse::<IpAddr>()?;
+        let b4 = "192.0.2.2".parse::<IpAddr>()?;
+        let c4 = "192.0.2.3".parse::<IpAddr>()?;
+        let a6 = "2001:db8::1".parse::<IpAddr>()?;
+
+        assert_eq!(vec![a6, a4, b4, c4], interleave(vec![a4, b4, a6, c4]));
+        assert_eq!(vec![a4, b4],         interleave(vec![a4, b4]));
+        assert!(interleave(Vec::new()).is_empty());
+
+        Ok(())
+    }
+
     #[test]
     fn request_port() -> Result<()> {
         assert_eq!(80,   request("GET", "http://localhost")?.port);
@@ -8,6 +8,7 @@ use netdiag::Bind;
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::{TcpSocket, TcpStream};
 use tokio_rustls::client::TlsStream;
+use crate::net::Source;
 use crate::net::tls::Identity;
 
 pub struct Connection {
@@ -39,15 +40,8 @@ impl Connection {
     }
 }
 
-pub async fn socket(bind: &Bind, addr: &SocketAddr) -> Result<TcpSocket> {
-    let (socket, bind) = match addr {
-        SocketAddr::V4(_) => (TcpSocket::new_v4()?, bind.sa4()),
-        SocketAddr::V6(_) => (TcpSocket::new_v6()?, bind.sa6()),
-    };
-
-    socket.bind(bind)?;
-
-    Ok(socket)
+pub async fn socket(bind: &Bind, source: &Source, addr: &SocketAddr) -> Result<TcpSocket> {
+    source.tcp(bind, addr)
 }
 
 impl TryFrom<(TcpStream, Identity)> for Connection {
@@ -1,11 +1,22 @@
 pub use listen::Addrs;
 pub use listen::Listener;
+pub use netns::Netns;
 pub use network::Network;
-pub use resolve::Resolver;
+pub use origin::{Origin, Origins};
+pub use resolve::{Policy, Resolver, Rules, Settings};
+pub use source::Source;
+pub use upstream::Health;
 
+pub mod echo;
 pub mod http;
+pub mod mpls;
 pub mod tls;
 
 mod listen;
+mod mrt;
+mod netns;
 mod network;
+mod origin;
 mod resolve;
+mod source;
+mod upstream;
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use anyhow::Result;
+use futures::pin_mut;
+use log::debug;
+use serde::Serialize;
+use tokio::io::unix::AsyncFd;
+use super::Source;
+use super::echo::Socket;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Label {
+    pub label: u32,
+    pub tc:    u8,
+    pub s:     bool,
+    pub ttl:   u8,
+}
+
+/// Listener for the ICMP errors routers send while tracing a target.
+///
+/// netdiag's tracer truncates replies before their extensions, so this
+/// reads a copy of each time-exceeded and unreachable message from a raw
+/// ICMP socket and keeps the label stacks quoting a probe to `target`.
+pub struct Listener {
+    socket: AsyncFd<Socket>,
+    target: IpAddr,
+}
+
+impl Listener {
+    pub fn new(source: &Source, target: IpAddr) -> Result<Self> {
+        let socket = source.enter(|| Socket::new(&target))?;
+        Ok(Self {
+            socket: AsyncFd::new(socket)?,
+            target: target,
+        })
+    }
+
+    pub async fn watch<T>(&self, future: impl Future<Output = T>) -> (T, HashMap<IpAddr, Vec<Label>>) {
+        let mut labels = HashMap::new();
+
+        pin_mut!(future);
+
+        loop {
+            tokio::select! {
+                output = &mut future => {
+                    self.drain(&mut labels);
+                    return (output, labels);
+                },
+                result = self.recv() => match result {
+                    Ok((addr, stack)) => {
+                        labels.insert(addr, stack);
+                    },
+                    Err(e) => {
+                        debug!("MPLS listener failed: {}", e);
+                        return (future.await, labels);
+                    },
+                },
+            }
+        }
+    }
+
+    async fn recv(&self) -> Result<(IpAddr, Vec<Label>)> {
+        let mut buf = [0u8; BUFFER];
+
+        loop {
+            let mut guard = self.socket.readable().await?;
+
+            let (n, addr) = match guard.try_io(|socket| socket.get_ref().recv_from(&mut buf)) {
+                Ok(result) => result?,
+                Err(_)     => continue,
+            };
+
+            if let Some(stack) = labels(&buf[..n], self.target) {
+                return Ok((addr, stack));
+            }
+        }
+    }
+
+    fn drain(&self, labels: &mut HashMap<IpAddr, Vec<Label>>) {
+        let mut buf = [0u8; BUFFER];
+        while let Ok((n, addr)) = self.socket.get_ref().recv_from(&mut buf) {
+            if let Some(stack) = self::labels(&buf[..n], self.target) {
+                labels.insert(addr, stack);
+            }
+        }
+    }
+}
+
+fn labels(pkt: &[u8], target: IpAddr) -> Option<Vec<Label>> {
+    let ipv6 = target.is_ipv6();
+    let icmp = match ipv6 {
+        false => pkt.get(usize::from(pkt.first()? & 0x0f) * 4..)?,
+        true  => pkt,
+    };
+    let orig = icmp.get(ICMP_HEADER..)?;
+
+    let dst = match (ipv6, *icmp.first()?) {
+        (false, TIME_EXCEEDED_V4 | UNREACHABLE_V4) => {
+            IpAddr::from(Ipv4Addr::from(<[u8; 4]>::try_from(orig.get(16..20)?).ok()?))
+        },
+        (true,  TIME_EXCEEDED_V6 | UNREACHABLE_V6) => {
+            IpAddr::from(Ipv6Addr::from(<[u8; 16]>::try_from(orig.get(24..40)?).ok()?))
+        },
+        _                                          => return None,
+    };
+
+    let stack = decode(icmp, ipv6);
+
+    match dst == target && !stack.is_empty() {
+        true  => Some(stack),
+        false => None,
+    }
+}
+
+pub fn decode(icmp: &[u8], ipv6: bool) -> Vec<Label> {
+    let (offset, unit) = match ipv6 {
+        false => (5, 4),
+        true  => (4, 8),
+    };
+
+    let length = match icmp.get(offset) {
+        Some(n) => usize::from(*n) * unit,
+        None    => return Vec::new(),
+    };
+
+    let start = ICMP_HEADER + length.max(ORIG_MINIMUM);
+
+    match icmp.get(start..) {
+        Some(ext) => extensions(ext),
+        None      => Vec::new(),
+    }
+}
+
+fn extensions(ext: &[u8]) -> Vec<Label> {
+    let mut labels = Vec::new();
+
+    if ext.len() < 4 || ext[0] >> 4 != EXT_VERSION {
+        return labels;
+    }
+
+    let mut objects = &ext[4..];
+
+    while objects.len() >= 4 {
+        let length = usize::from(u16::from_be_bytes([objects[0], objects[1]]));
+        let class  = objects[2];
+        let ctype  = objects[3];
+
+        if length < 4 || length > objects.len() {
+            break;
+        }
+
+        if class == MPLS_CLASS && ctype == MPLS_STACK {
+            labels.extend(objects[4..length].chunks_exact(4).map(|entry| {
+                let entry = u32::from_be_bytes(entry.try_into().unwrap_or_default());
+                Label {
+                    label: entry >> 12,
+                    tc:    ((entry >> 9) & 0x7) as u8,
+                    s:     entry & 0x100 != 0,
+                    ttl:   (entry & 0xff) as u8,
+                }
+            }));
+        }
+
+        objects = &objects[length..];
+    }
+
+    labels
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = u8::from(self.s);
+        write!(f, "L={} E={} S={} TTL={}", self.label, self.tc, s, self.ttl)
+    }
+}
+
+const BUFFER:       usize = 1500;
+const ICMP_HEADER:  usize = 8;
+const ORIG_MINIMUM: usize = 128;
+const EXT_VERSION:  u8    = 2;
+const MPLS_CLASS:   u8    = 1;
+const MPLS_STACK:   u8    = 1;
+
+const UNREACHABLE_V4:   u8 = 3;
+const TIME_EXCEEDED_V4: u8 = 11;
+const UNREACHABLE_V6:   u8 = 1;
+const TIME_EXCEEDED_V6: u8 = 3;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn message(ipv6: bool, length: u8, orig: usize, ext: &[u8]) -> Vec<u8> {
+        let mut msg = vec![0u8; ICMP_HEADER + orig];
+        msg[0] = if ipv6 { TIME_EXCEEDED_V6 } else { TIME_EXCEEDED_V4 };
+        msg[if ipv6 { 4 } else { 5 }] = length;
+        msg.extend_from_slice(ext);
+        msg
+    }
+
+    fn stack(entries: &[u32]) -> Vec<u8> {
+        let length = 4 + entries.len() * 4;
+        let mut ext = vec![EXT_VERSION << 4, 0, 0, 0];
+        ext.extend_from_slice(&(length as u16).to_be_bytes());
+        ext.extend_from_slice(&[MPLS_CLASS, MPLS_STACK]);
+        for entry in entries {
+            ext.extend_from_slice(&entry.to_be_bytes());
+        }
+        ext
+    }
+
+    #[test]
+    fn decode_ipv4() {
+        let ext = stack(&[(16001 << 12) | (5 << 9) | 254, (24 << 12) | 0x100 | 1]);
+        let msg = message(false, 32, 128, &ext);
+
+        assert_eq!(vec![
+            Label { label: 16001, tc: 5, s: false, ttl: 254 },
+            Label { label: 24,    tc: 0, s: true,  ttl: 1   },
+        ], decode(&msg, false));
+    }
+
+    #[test]
+    fn decode_ipv6() {
+        let ext = stack(&[(299776 << 12) | 0x100 | 64]);
+        let msg = message(true, 17, 136, &ext);
+
+        assert_eq!(vec![
+            Label { label: 299776, tc: 0, s: true, ttl: 64 },
+        ], decode(&msg, true));
+    }
+
+    #[test]
+    fn decode_noncompliant() {
+        let ext = stack(&[(100 << 12) | 0x100 | 1]);
+        let msg = message(false, 0, 128, &ext);
+        assert_eq!(1, decode(&msg, false).len());
+    }
+
+    #[test]
+    fn decode_invalid() {
+        assert!(decode(&[], false).is_empty());
+        assert!(decode(&message(false, 0, 28, &[]), false).is_empty());
+
+        let mut ext = stack(&[1 << 12]);
+        ext[0] = 1 << 4;
+        assert!(decode(&message(false, 32, 128, &ext), false).is_empty());
+
+        let mut ext = stack(&[1 << 12]);
+        ext[5] = 64;
+        assert!(decode(&message(false, 32, 128, &ext), false).is_empty());
+    }
+
+    #[test]
+    fn listener_labels() {
+        let target = "192.0.2.9".parse().unwrap();
+        let other  = "192.0.2.8".parse().unwrap();
+        let ext    = stack(&[(16001 << 12) | 0x100 | 1]);
+
+        let packet = |dst: IpAddr, kind: u8| {
+            let mut msg = message(false, 32, 128, &ext);
+            msg[0] = kind;
+            msg[ICMP_HEADER] = 0x45;
+            if let IpAddr::V4(dst) = dst {
+                msg[ICMP_HEADER + 16..ICMP_HEADER + 20].copy_from_slice(&dst.octets());
+            }
+            let mut pkt = vec![0x45; 1];
+            pkt.resize(20, 0);
+            pkt.extend(msg);
+            pkt
+        };
+
+        let label = Label { label: 16001, tc: 0, s: true, ttl: 1 };
+        assert_eq!(Some(vec![label]), labels(&packet(target, TIME_EXCEEDED_V4), target));
+        assert_eq!(Some(vec![label]), labels(&packet(target, UNREACHABLE_V4), target));
+        assert_eq!(None, labels(&packet(other, TIME_EXCEEDED_V4), target));
+        assert_eq!(None, labels(&packet(target, 0), target));
+        assert_eq!(None, labels(&[], target));
+
+        let mut msg = message(true, 17, 136, &ext);
+        msg[ICMP_HEADER + 24..ICMP_HEADER + 40].copy_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9]);
+        assert_eq!(Some(vec![label]), labels(&msg, "2001:db8::9".parse().unwrap()));
+        assert_eq!(None, labels(&msg, "2001:db8::8".parse().unwrap()));
+    }
+}
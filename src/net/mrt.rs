@@ -0,0 +1,200 @@
+use std::convert::TryInto;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use anyhow::{anyhow, Result};
+
+pub fn is_mrt(data: &[u8]) -> bool {
+    matches!(data.get(4..6), Some([0, 12]) | Some([0, 13]))
+}
+
+pub fn routes(data: &[u8]) -> Result<Vec<(IpAddr, u8, u32)>> {
+    let mut cursor = Cursor(data);
+    let mut routes = Vec::new();
+
+    while !cursor.0.is_empty() {
+        let _time   = cursor.u32()?;
+        let kind    = cursor.u16()?;
+        let subtype = cursor.u16()?;
+        let length  = cursor.u32()?;
+        let record  = cursor.take(length as usize)?;
+
+        if kind != TABLE_DUMP_V2 {
+            return Err(anyhow!("unsupported MRT type {kind}"));
+        }
+
+        let (ipv6, addpath) = match subtype {
+            RIB_IPV4_UNICAST         => (false, false),
+            RIB_IPV6_UNICAST         => (true,  false),
+            RIB_IPV4_UNICAST_ADDPATH => (false, true),
+            RIB_IPV6_UNICAST_ADDPATH => (true,  true),
+            _                        => continue,
+        };
+
+        if let Some(route) = rib(Cursor(record), ipv6, addpath)? {
+            routes.push(route);
+        }
+    }
+
+    Ok(routes)
+}
+
+fn rib(mut cursor: Cursor, ipv6: bool, addpath: bool) -> Result<Option<(IpAddr, u8, u32)>> {
+    let _seq  = cursor.u32()?;
+    let len   = cursor.u8()?;
+    let bytes = cursor.take(usize::from(len).div_ceil(8))?;
+
+    let addr = match ipv6 {
+        false if len <= 32  => IpAddr::V4(Ipv4Addr::from(pad::<4>(bytes))),
+        true  if len <= 128 => IpAddr::V6(Ipv6Addr::from(pad::<16>(bytes))),
+        _                   => return Err(anyhow!("invalid MRT prefix length {len}")),
+    };
+
+    let mut origin = None;
+
+    for _ in 0..cursor.u16()? {
+        let _peer = cursor.u16()?;
+        let _time = cursor.u32()?;
+        if addpath {
+            let _path = cursor.u32()?;
+        }
+        let length = cursor.u16()?;
+        let attrs  = cursor.take(usize::from(length))?;
+
+        if origin.is_none() {
+            origin = aspath(Cursor(attrs))?;
+        }
+    }
+
+    Ok(origin.map(|asn| (addr, len, asn)))
+}
+
+fn aspath(mut cursor: Cursor) -> Result<Option<u32>> {
+    while !cursor.0.is_empty() {
+        let flags  = cursor.u8()?;
+        let kind   = cursor.u8()?;
+        let length = match flags & EXTENDED_LENGTH {
+            0 => usize::from(cursor.u8()?),
+            _ => usize::from(cursor.u16()?),
+        };
+        let value  = cursor.take(length)?;
+
+        if kind != AS_PATH {
+            continue;
+        }
+
+        let mut cursor = Cursor(value);
+        let mut origin = None;
+
+        while !cursor.0.is_empty() {
+            let kind = cursor.u8()?;
+            let asns = (0..cursor.u8()?).map(|_| cursor.u32()).collect::<Result<Vec<_>>>()?;
+            origin = match kind {
+                AS_SEQUENCE => asns.last().copied(),
+                _           => None,
+            };
+        }
+
+        return Ok(origin);
+    }
+
+    Ok(None)
+}
+
+fn pad<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let mut addr = [0u8; N];
+    addr[..bytes.len()].copy_from_slice(bytes);
+    addr
+}
+
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.0.len() {
+            return Err(anyhow!("truncated MRT record"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+}
+
+const TABLE_DUMP_V2: u16 = 13;
+
+const RIB_IPV4_UNICAST:         u16 = 2;
+const RIB_IPV6_UNICAST:         u16 = 4;
+const RIB_IPV4_UNICAST_ADDPATH: u16 = 8;
+const RIB_IPV6_UNICAST_ADDPATH: u16 = 10;
+
+const EXTENDED_LENGTH: u8 = 0x10;
+const AS_PATH:         u8 = 2;
+const AS_SEQUENCE:     u8 = 2;
+
+#[cfg(test)]
+pub mod test {
+    use anyhow::Result;
+    use super::*;
+
+    pub fn rib(subtype: u16, prefix: &[u8], len: u8, path: &[u32]) -> Vec<u8> {
+        let mut segment = vec![AS_SEQUENCE, path.len() as u8];
+        path.iter().for_each(|asn| segment.extend_from_slice(&asn.to_be_bytes()));
+
+        let mut attrs = vec![0x40, 1, 1, 0];
+        attrs.extend_from_slice(&[0x50, AS_PATH]);
+        attrs.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+        attrs.extend_from_slice(&segment);
+
+        let mut body = vec![0, 0, 0, 1, len];
+        body.extend_from_slice(prefix);
+        body.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+        if subtype == RIB_IPV4_UNICAST_ADDPATH {
+            body.extend_from_slice(&[0, 0, 0, 7]);
+        }
+        body.extend_from_slice(&(attrs.len() as u16).to_be_bytes());
+        body.extend_from_slice(&attrs);
+
+        record(subtype, &body)
+    }
+
+    fn record(subtype: u16, body: &[u8]) -> Vec<u8> {
+        let mut record = vec![0, 0, 0, 0];
+        record.extend_from_slice(&TABLE_DUMP_V2.to_be_bytes());
+        record.extend_from_slice(&subtype.to_be_bytes());
+        record.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        record.extend_from_slice(body);
+        record
+    }
+
+    #[test]
+    fn mrt_routes() -> Result<()> {
+        let mut data = record(1, &[0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend(rib(RIB_IPV4_UNICAST, &[10], 8, &[64496, 64500]));
+        data.extend(rib(RIB_IPV4_UNICAST_ADDPATH, &[10, 1], 16, &[64501]));
+        data.extend(rib(RIB_IPV6_UNICAST, &[0x20, 0x01, 0x0d, 0xb8], 32, &[64496, 64502]));
+
+        assert!(is_mrt(&data));
+        assert!(!is_mrt(b"10.0.0.0/8,64500"));
+
+        assert_eq!(vec![
+            ("10.0.0.0".parse()?,   8, 64500),
+            ("10.1.0.0".parse()?,  16, 64501),
+            ("2001:db8::".parse()?, 32, 64502),
+        ], routes(&data)?);
+
+        assert!(routes(&data[..data.len() - 1]).is_err());
+        assert!(routes(&rib(RIB_IPV4_UNICAST, &[10, 0, 0, 0, 0], 33, &[64500])).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+use std::fs::File;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::thread;
+use anyhow::{anyhow, Result};
+use tokio::sync::oneshot::channel;
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Netns {
+    path: PathBuf,
+}
+
+const NETNS_RUN_DIR: &str = "/var/run/netns";
+
+impl Netns {
+    pub fn new(spec: &str) -> Self {
+        let path = match spec.parse::<u32>() {
+            Ok(pid)                      => PathBuf::from(format!("/proc/{}/ns/net", pid)),
+            Err(_) if spec.contains('/') => PathBuf::from(spec),
+            Err(_)                       => Path::new(NETNS_RUN_DIR).join(spec),
+        };
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn enter<T: Send, F: FnOnce() -> Result<T> + Send>(&self, f: F) -> Result<T> {
+        let target = self.open()?;
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                switch(target.as_raw_fd())?;
+                f()
+            }).join().unwrap_or_else(|_| Err(anyhow!("{}: namespace thread panicked", self)))
+        })
+    }
+
+    pub async fn spawn<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let target   = self.open()?;
+        let (tx, rx) = channel();
+
+        thread::spawn(move || {
+            let _ = tx.send(switch(target.as_raw_fd()).and_then(|_| f()));
+        });
+
+        rx.await.unwrap_or_else(|_| Err(anyhow!("{}: namespace thread panicked", self)))
+    }
+
+    fn open(&self) -> Result<File> {
+        File::open(&self.path).map_err(|e| anyhow!("{}: {}", self.path.display(), e))
+    }
+}
+
+impl fmt::Display for Netns {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path.display())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn switch(fd: RawFd) -> Result<()> {
+    use nix::sched::{setns, CloneFlags};
+    Ok(setns(fd, CloneFlags::CLONE_NEWNET)?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn switch(_fd: RawFd) -> Result<()> {
+    Err(anyhow!("network namespaces are not supported"))
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::runtime::Builder;
+    use super::*;
+
+    #[test]
+    fn netns_path() {
+        assert_eq!(Path::new("/var/run/netns/blue"), Netns::new("blue").path());
+        assert_eq!(Path::new("/proc/1234/ns/net"),    Netns::new("1234").path());
+        assert_eq!(Path::new("/run/netns/red"),       Netns::new("/run/netns/red").path());
+    }
+
+    #[test]
+    fn netns_enter_error() -> Result<()> {
+        let current = || Ok::<_, anyhow::Error>(std::fs::read_link("/proc/thread-self/ns/net")?);
+        let before  = current()?;
+
+        assert!(Netns::new("/nonexistent/netns").enter(|| Ok(())).is_err());
+        assert!(Netns::new("/dev/null").enter(|| Ok(())).is_err());
+
+        let runtime = Builder::new_current_thread().build()?;
+        assert!(runtime.block_on(Netns::new("/dev/null").spawn(|| Ok(()))).is_err());
+
+        assert_eq!(before, current()?);
+
+        Ok(())
+    }
+}
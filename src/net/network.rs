@@ -1,6 +1,8 @@
-use serde::Deserialize;
+use std::fmt;
+use std::net::IpAddr;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Network {
     IPv4,
@@ -19,3 +21,22 @@ impl Default for Network {
         Self::Dual
     }
 }
+
+impl From<IpAddr> for Network {
+    fn from(addr: IpAddr) -> Self {
+        match addr {
+            IpAddr::V4(_) => Self::IPv4,
+            IpAddr::V6(_) => Self::IPv6,
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::IPv4 => "ipv4",
+            Self::IPv6 => "ipv6",
+            Self::Dual => "dual",
+        })
+    }
+}
@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::str::FromStr;
+use anyhow::{anyhow, Error, Result};
+use maxminddb::{geoip2, Reader};
+use serde::Serialize;
+use super::mrt;
+
+#[derive(Debug, Default)]
+pub struct Origins {
+    asn: Database<(u32, String)>,
+    geo: Database<String>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Origin {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn:     Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org:     Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix:  Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Prefix {
+    addr: IpAddr,
+    len:  u8,
+}
+
+#[derive(Debug)]
+enum Database<T> {
+    Table(Table<T>),
+    Mmdb(Reader<Vec<u8>>),
+}
+
+trait Record: Clone + Sized {
+    fn mmdb(reader: &Reader<Vec<u8>>, addr: IpAddr) -> Option<(Self, usize)>;
+}
+
+#[derive(Debug)]
+struct Table<T> {
+    entries: HashMap<Prefix, T>,
+    lengths: Vec<u8>,
+}
+
+impl Origins {
+    pub fn load(asn: Option<&Path>, geo: Option<&Path>) -> Result<Self> {
+        let asn = match asn {
+            Some(path) => Database::load(fs::read(path)?, |data| {
+                if mrt::is_mrt(data) {
+                    return Table::routes(mrt::routes(data)?);
+                }
+
+                Table::read(data, |fields| {
+                    let asn = fields.next().ok_or_else(|| anyhow!("missing ASN"))?;
+                    let asn = asn.trim_start_matches("AS").parse::<u32>()?;
+                    let org = fields.next().unwrap_or_default().to_owned();
+                    Ok((asn, org))
+                })
+            }).map_err(|e| anyhow!("{}: {}", path.display(), e))?,
+            None => Database::default(),
+        };
+
+        let geo = match geo {
+            Some(path) => Database::load(fs::read(path)?, |data| {
+                Table::read(data, |fields| {
+                    let country = fields.next().ok_or_else(|| anyhow!("missing country"))?;
+                    Ok(country.to_owned())
+                })
+            }).map_err(|e| anyhow!("{}: {}", path.display(), e))?,
+            None => Database::default(),
+        };
+
+        Ok(Self { asn, geo })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.asn.is_empty() && self.geo.is_empty()
+    }
+
+    pub fn lookup(&self, addr: IpAddr) -> Option<Origin> {
+        let asn = self.asn.lookup(addr);
+        let geo = self.geo.lookup(addr);
+
+        if asn.is_none() && geo.is_none() {
+            return None;
+        }
+
+        let (prefix, asn, org) = match asn {
+            Some((prefix, (asn, org))) => (Some(prefix.to_string()), Some(asn), Some(org)),
+            None                       => (None, None, None),
+        };
+
+        Some(Origin {
+            asn:     asn,
+            org:     org.filter(|org| !org.is_empty()),
+            prefix:  prefix,
+            country: geo.map(|(_, country)| country),
+        })
+    }
+}
+
+impl<T: Record> Database<T> {
+    fn load<F: FnOnce(&[u8]) -> Result<Table<T>>>(data: Vec<u8>, table: F) -> Result<Self> {
+        let start = data.len().saturating_sub(MMDB_METADATA);
+        match data[start..].windows(MMDB_MARKER.len()).any(|w| w == MMDB_MARKER) {
+            true  => Ok(Self::Mmdb(Reader::from_source(data)?)),
+            false => Ok(Self::Table(table(&data)?)),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Self::Table(table) => table.entries.is_empty(),
+            Self::Mmdb(_)      => false,
+        }
+    }
+
+    fn lookup(&self, addr: IpAddr) -> Option<(Prefix, T)> {
+        match self {
+            Self::Table(table)  => table.lookup(addr).map(|(prefix, value)| (prefix, value.clone())),
+            Self::Mmdb(reader) => T::mmdb(reader, addr).map(|(value, len)| {
+                (Prefix::new(addr, len as u8), value)
+            }),
+        }
+    }
+}
+
+impl<T> Default for Database<T> {
+    fn default() -> Self {
+        Self::Table(Table::default())
+    }
+}
+
+impl Record for (u32, String) {
+    fn mmdb(reader: &Reader<Vec<u8>>, addr: IpAddr) -> Option<(Self, usize)> {
+        let (asn, len) = reader.lookup_prefix::<geoip2::Asn>(addr).ok()?;
+        let org = asn.autonomous_system_organization.unwrap_or_default();
+        Some(((asn.autonomous_system_number?, org.to_owned()), len))
+    }
+}
+
+impl Record for String {
+    fn mmdb(reader: &Reader<Vec<u8>>, addr: IpAddr) -> Option<(Self, usize)> {
+        let (geo, len) = reader.lookup_prefix::<geoip2::Country>(addr).ok()?;
+        Some((geo.country?.iso_code?.to_owned(), len))
+    }
+}
+
+impl<T> Table<T> {
+    fn read<R, F>(source: R, parse: F) -> Result<Self>
+    where
+        R: Read,
+        F: Fn(&mut dyn Iterator<Item = &str>) -> Result<T>,
+    {
+        let mut table = Self::default();
+
+        for (index, line) in BufReader::new(source).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.splitn(3, ',').map(str::trim);
+
+            let result = fields.next().unwrap_or_default().parse::<Prefix>().and_then(|prefix| {
+                Ok((prefix, parse(&mut fields)?))
+            });
+
+            match result {
+                Ok((prefix, value)) => table.insert(prefix, value),
+                Err(e)              => return Err(anyhow!("line {}: {}", index + 1, e)),
+            }
+        }
+
+        Ok(table)
+    }
+
+    fn insert(&mut self, prefix: Prefix, value: T) {
+        if let Err(n) = self.lengths.binary_search_by(|len| prefix.len.cmp(len)) {
+            self.lengths.insert(n, prefix.len);
+        }
+        self.entries.insert(prefix, value);
+    }
+
+    fn lookup(&self, addr: IpAddr) -> Option<(Prefix, &T)> {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        self.lengths.iter().filter(|len| **len <= max).find_map(|len| {
+            let prefix = Prefix::new(addr, *len);
+            self.entries.get(&prefix).map(|value| (prefix, value))
+        })
+    }
+}
+
+impl Table<(u32, String)> {
+    fn routes(routes: Vec<(IpAddr, u8, u32)>) -> Result<Self> {
+        let mut table = Self::default();
+        for (addr, len, asn) in routes {
+            table.insert(Prefix::new(addr, len), (asn, String::new()));
+        }
+        Ok(table)
+    }
+}
+
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            lengths: Vec::new(),
+        }
+    }
+}
+
+impl Prefix {
+    pub fn new(addr: IpAddr, len: u8) -> Self {
+        let addr = match addr {
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(len)).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask))
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(len)).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask))
+            }
+        };
+        Self { addr, len }
+    }
+}
+
+impl FromStr for Prefix {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, len) = match s.split_once('/') {
+            Some((addr, len)) => (addr.parse::<IpAddr>()?, len.parse::<u8>()?),
+            None              => return Err(anyhow!("invalid prefix: {s}")),
+        };
+
+        match (addr, len) {
+            (IpAddr::V4(_), 0..=32)  => Ok(Self::new(addr, len)),
+            (IpAddr::V6(_), 0..=128) => Ok(Self::new(addr, len)),
+            _                        => Err(anyhow!("invalid prefix length: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+const MMDB_MARKER:   &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+const MMDB_METADATA: usize = 128 * 1024;
+
+#[cfg(test)]
+mod test {
+    use anyhow::Result;
+    use super::*;
+
+    use crate::net::mrt::test::rib;
+
+    #[test]
+    fn prefix() -> Result<()> {
+        assert_eq!(Prefix::from_str("10.1.2.0/24")?, Prefix::from_str("10.1.2.3/24")?);
+        assert_eq!("10.0.0.0/8",    Prefix::from_str("10.1.2.3/8")?.to_string());
+        assert_eq!("0.0.0.0/0",     Prefix::from_str("10.1.2.3/0")?.to_string());
+        assert_eq!("2001:db8::/32", Prefix::from_str("2001:db8:1::1/32")?.to_string());
+
+        assert!(Prefix::from_str("10.0.0.0").is_err());
+        assert!(Prefix::from_str("10.0.0.0/33").is_err());
+        assert!(Prefix::from_str("::/129").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn lookup() -> Result<()> {
+        let asn = Table::read(&b"
+            # prefix,asn,org
+            10.0.0.0/8,AS64500,Example, Inc.
+            10.1.0.0/16,64501,
+            2001:db8::/32,64502,Documentation
+        "[..], |fields| {
+            let asn = fields.next().unwrap_or_default().trim_start_matches("AS").parse::<u32>()?;
+            let org = fields.next().unwrap_or_default().to_owned();
+            Ok((asn, org))
+        })?;
+
+        let geo = Table::read(&b"10.1.2.0/24,US"[..], |fields| {
+            Ok(fields.next().unwrap_or_default().to_owned())
+        })?;
+
+        let origins = Origins {
+            asn: Database::Table(asn),
+            geo: Database::Table(geo),
+        };
+
+        assert_eq!(Some(Origin {
+            asn:     Some(64500),
+            org:     Some("Example, Inc.".to_owned()),
+            prefix:  Some("10.0.0.0/8".to_owned()),
+            country: None,
+        }), origins.lookup("10.2.0.1".parse()?));
+
+        assert_eq!(Some(Origin {
+            asn:     Some(64501),
+            org:     None,
+            prefix:  Some("10.1.0.0/16".to_owned()),
+            country: Some("US".to_owned()),
+        }), origins.lookup("10.1.2.3".parse()?));
+
+        assert_eq!(Some(64502), origins.lookup("2001:db8::1".parse()?).and_then(|o| o.asn));
+        assert_eq!(None, origins.lookup("192.0.2.1".parse()?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn mrt() -> Result<()> {
+        let mut data = rib(2, &[10], 8, &[64496, 64500]);
+        data.extend(rib(4, &[0x20, 0x01, 0x0d, 0xb8], 32, &[64502]));
+
+        let origins = Origins {
+            asn: Database::load(data, |data| Table::routes(mrt::routes(data)?))?,
+            geo: Database::default(),
+        };
+
+        assert_eq!(Some(Origin {
+            asn:     Some(64500),
+            org:     None,
+            prefix:  Some("10.0.0.0/8".to_owned()),
+            country: None,
+        }), origins.lookup("10.1.2.3".parse()?));
+
+        assert_eq!(Some(64502), origins.lookup("2001:db8::1".parse()?).and_then(|o| o.asn));
+        assert_eq!(None, origins.lookup("192.0.2.1".parse()?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn mmdb() -> Result<()> {
+        let mut asn = map(2);
+        asn.extend(string("autonomous_system_number"));
+        asn.extend(uint(6, 64500));
+        asn.extend(string("autonomous_system_organization"));
+        asn.extend(string("Example"));
+
+        let mut geo = map(1);
+        geo.extend(string("country"));
+        geo.extend(map(1));
+        geo.extend(string("iso_code"));
+        geo.extend(string("US"));
+
+        let origins = Origins {
+            asn: Database::load(database(&[10, 1], 16, &asn), |_| Err(anyhow!("not mmdb")))?,
+            geo: Database::load(database(&[10, 1, 2], 24, &geo), |_| Err(anyhow!("not mmdb")))?,
+        };
+
+        assert!(!origins.is_empty());
+
+        assert_eq!(Some(Origin {
+            asn:     Some(64500),
+            org:     Some("Example".to_owned()),
+            prefix:  Some("10.1.0.0/16".to_owned()),
+            country: Some("US".to_owned()),
+        }), origins.lookup("10.1.2.3".parse()?));
+
+        assert_eq!(Some(Origin {
+            asn:     Some(64500),
+            org:     Some("Example".to_owned()),
+            prefix:  Some("10.1.0.0/16".to_owned()),
+            country: None,
+        }), origins.lookup("10.1.3.1".parse()?));
+
+        assert_eq!(None, origins.lookup("10.2.0.1".parse()?));
+        assert_eq!(None, origins.lookup("2001:db8::1".parse()?));
+
+        Ok(())
+    }
+
+    fn database(prefix: &[u8], len: usize, record: &[u8]) -> Vec<u8> {
+        let bit   = |n: usize| (prefix[n / 8] >> (7 - n % 8)) & 1;
+        let nodes = len as u32;
+        let data  = nodes + 16;
+
+        let mut db = Vec::new();
+        for n in 0..len {
+            let next = if n + 1 < len { n as u32 + 1 } else { data };
+            let (left, right) = match bit(n) {
+                0 => (next, nodes),
+                _ => (nodes, next),
+            };
+            db.extend_from_slice(&left.to_be_bytes()[1..]);
+            db.extend_from_slice(&right.to_be_bytes()[1..]);
+        }
+
+        db.extend_from_slice(&[0; 16]);
+        db.extend_from_slice(record);
+        db.extend_from_slice(MMDB_MARKER);
+
+        db.extend(map(9));
+        for (key, kind, value) in [
+            ("binary_format_major_version", 5, 2),
+            ("binary_format_minor_version", 5, 0),
+            ("ip_version",                  5, 4),
+            ("node_count",                  6, u64::from(nodes)),
+            ("record_size",                 5, 24),
+        ] {
+            db.extend(string(key));
+            db.extend(uint(kind, value));
+        }
+        db.extend(string("build_epoch"));
+        db.extend([8, 2, 0, 0, 0, 0, 0, 0, 0, 0]);
+        db.extend(string("database_type"));
+        db.extend(string("Test"));
+        db.extend(string("description"));
+        db.extend(map(0));
+        db.extend(string("languages"));
+        db.extend([0, 4]);
+
+        db
+    }
+
+    fn map(n: u8) -> Vec<u8> {
+        vec![7 << 5 | n]
+    }
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut v = match s.len() {
+            n if n < 29 => vec![2 << 5 | n as u8],
+            n           => vec![2 << 5 | 29, n as u8 - 29],
+        };
+        v.extend_from_slice(s.as_bytes());
+        v
+    }
+
+    fn uint(kind: u8, value: u64) -> Vec<u8> {
+        let n = match kind {
+            5 => 2,
+            _ => 4,
+        };
+        let mut v = vec![kind << 5 | n as u8];
+        v.extend_from_slice(&value.to_be_bytes()[8 - n..]);
+        v
+    }
+}
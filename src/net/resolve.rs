@@ -1,70 +1,462 @@
-use std::net::IpAddr;
-use anyhow::{anyhow, Result};
-use tracing::{instrument, trace};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::ptr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Error, Result};
+use tracing::{instrument, trace, warn};
+use netdiag::Bind;
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::time::{sleep, timeout};
 use trust_dns_resolver::TokioAsyncResolver;
+use trust_dns_resolver::config::{LookupIpStrategy, NameServerConfig, Protocol};
+use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
+use trust_dns_resolver::system_conf::read_system_conf;
 use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
 use {super::Network, super::Network::*};
+use super::upstream::{self, nameserver, Health, Upstream};
 
 #[derive(Clone)]
 pub struct Resolver {
-    resolver: TokioAsyncResolver,
+    resolver:  TokioAsyncResolver,
+    reverse:   Arc<Reverse>,
+    rules:     Arc<Rules>,
+    forward:   Arc<Vec<(String, TokioAsyncResolver)>>,
+    upstreams: Arc<Vec<Upstream>>,
 }
 
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Policy {
+    #[default]
+    Random,
+    First,
+    All,
+    Both,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Rules {
+    pub hosts:     HashMap<String, Vec<IpAddr>>,
+    pub forward:   HashMap<String, Vec<SocketAddr>>,
+    pub search:    Option<Vec<String>>,
+    pub upstreams: Vec<String>,
+    pub fallback:  bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub servers:  Vec<SocketAddr>,
+    pub protocol: Protocol,
+    pub timeout:  Option<Duration>,
+    pub nocache:  bool,
+}
+
+struct Reverse {
+    cache: Mutex<HashMap<IpAddr, (Instant, Option<String>)>>,
+    next:  AsyncMutex<Instant>,
+}
+
+const PTR_CACHE_SIZE: usize    = 4096;
+const PTR_CACHE_TTL:  Duration = Duration::from_secs(3600);
+const PTR_INTERVAL:   Duration = Duration::from_millis(10);
+
 impl Resolver {
     pub fn new(resolver: TokioAsyncResolver) -> Self {
-        Self { resolver }
+        let reverse = Arc::new(Reverse {
+            cache: Mutex::new(HashMap::new()),
+            next:  AsyncMutex::new(Instant::now()),
+        });
+        let rules     = Arc::new(Rules::default());
+        let forward   = Arc::new(Vec::new());
+        let upstreams = Arc::new(Vec::new());
+        Self { resolver, reverse, rules, forward, upstreams }
+    }
+
+    pub fn system(bind: &Bind, net: Option<Network>, settings: &Settings, rules: &Rules) -> Result<Self> {
+        let (config, mut options) = read_system_conf().unwrap_or_else(|e| {
+            warn!("resolver configuration error: {}", e);
+            let config  = ResolverConfig::google();
+            let options = ResolverOpts::default();
+            (config, options)
+        });
+
+        let servers = match settings.servers.as_slice() {
+            []      => config.name_servers().to_vec(),
+            servers => servers.iter().map(|addr| {
+                nameserver(*addr, settings.protocol, None)
+            }).collect(),
+        };
+
+        let domain  = config.domain().cloned();
+        let search  = match &rules.search {
+            Some(search) => search.iter().map(|name| name.parse()).collect::<Result<_, _>>()?,
+            None         => config.search().to_vec(),
+        };
+        let servers = servers.into_iter().map(|server| bound(server, bind)).collect::<Vec<_>>();
+
+        let config = ResolverConfig::from_parts(domain, search, servers);
+
+        options.ip_strategy = match net {
+            Some(Network::IPv4)        => LookupIpStrategy::Ipv4Only,
+            Some(Network::IPv6)        => LookupIpStrategy::Ipv6Only,
+            Some(Network::Dual) | None => LookupIpStrategy::Ipv4AndIpv6,
+        };
+
+        if let Some(timeout) = settings.timeout {
+            options.timeout = timeout;
+        }
+
+        if settings.nocache {
+            options.cache_size = 0;
+        }
+
+        let forward = rules.forward.iter().map(|(domain, servers)| {
+            let servers = servers.iter().map(|addr| {
+                bound(nameserver(*addr, Protocol::Udp, None), bind)
+            }).collect::<Vec<_>>();
+            let config   = ResolverConfig::from_parts(None, Vec::new(), servers);
+            let resolver = TokioAsyncResolver::tokio(config, options)?;
+            Ok((domain.trim_end_matches('.').to_ascii_lowercase(), resolver))
+        }).collect::<Result<Vec<_>>>()?;
+
+        let upstreams = match settings.servers.is_empty() {
+            true  => rules.upstreams.as_slice(),
+            false => &[],
+        };
+
+        let upstreams = upstreams.iter().map(|spec| {
+            let server   = bound(upstream::parse(spec)?, bind);
+            let domain   = config.domain().cloned();
+            let search   = config.search().to_vec();
+            let config   = ResolverConfig::from_parts(domain, search, vec![server]);
+            let resolver = TokioAsyncResolver::tokio(config, options)?;
+            Ok(Upstream::new(spec.clone(), resolver))
+        }).collect::<Result<Vec<_>>>()?;
+
+        let resolver = TokioAsyncResolver::tokio(config, options)?;
+
+        Ok(Self {
+            rules:     Arc::new(rules.clone()),
+            forward:   Arc::new(forward),
+            upstreams: Arc::new(upstreams),
+            ..Self::new(resolver)
+        })
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// Whether a clone of this resolver is still held outside the caller.
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.reverse) > 1
+    }
+
+    pub fn health(&self) -> Vec<Health> {
+        let active = upstream::order(&self.upstreams).into_iter().next();
+        self.upstreams.iter().map(|upstream| {
+            upstream.health(active.is_some_and(|active| ptr::eq(active, upstream)))
+        }).collect()
     }
 
     #[instrument(skip_all)]
     pub async fn lookup(&self, host: &str, net: Network) -> Result<IpAddr> {
+        let addrs = self.addrs(host, net).await?;
+
+        match addrs.choose(&mut thread_rng()) {
+            Some(addr) => Ok(*addr),
+            None       => Err(empty(host, net)),
+        }
+    }
+
+    #[instrument(skip_all)]
+    pub async fn select(&self, host: &str, net: Network, policy: Policy) -> Result<Vec<IpAddr>> {
+        if policy == Policy::Random {
+            return Ok(vec![self.lookup(host, net).await?]);
+        }
+
+        let addrs = self.addrs(host, net).await?;
+
+        let addrs = match policy {
+            Policy::First  => addrs.into_iter().take(1).collect(),
+            Policy::All    => addrs,
+            Policy::Both   => {
+                let ip4 = addrs.iter().find(|addr| addr.is_ipv4());
+                let ip6 = addrs.iter().find(|addr| addr.is_ipv6());
+                ip4.into_iter().chain(ip6).copied().collect()
+            },
+            Policy::Random => unreachable!(),
+        };
+
+        match addrs.is_empty() {
+            false => Ok(addrs),
+            true  => Err(empty(host, net)),
+        }
+    }
+
+    async fn addrs(&self, host: &str, net: Network) -> Result<Vec<IpAddr>> {
         if let Ok(ip) = host.parse::<IpAddr>() {
             match net {
-                IPv4 if ip.is_ipv4() => return Ok(ip),
-                IPv6 if ip.is_ipv6() => return Ok(ip),
-                Dual                 => return Ok(ip),
+                IPv4 if ip.is_ipv4() => return Ok(vec![ip]),
+                IPv6 if ip.is_ipv6() => return Ok(vec![ip]),
+                Dual                 => return Ok(vec![ip]),
                 _                    => ()
             }
         }
 
-        let addrs = match net {
-            Dual => self.resolve(host).await?,
-            IPv4 => self.resolve4(host).await?,
-            IPv6 => self.resolve6(host).await?,
+        if let Some(addrs) = self.rules.hosts.get(&host.to_ascii_lowercase()) {
+            return Ok(addrs.iter().filter(|addr| match net {
+                IPv4 => addr.is_ipv4(),
+                IPv6 => addr.is_ipv6(),
+                Dual => true,
+            }).copied().collect());
+        }
+
+        let addrs = match self.forwarder(host) {
+            Some(resolver) => query(resolver, host, net).await,
+            None           => self.upstream(host, net).await,
         };
 
+        let addrs = addrs.or_else(|e| result(host, e))?;
+
         trace!("{}: {:?}", host, addrs);
 
-        match addrs.choose(&mut thread_rng()) {
-            Some(addr)          => Ok(*addr),
-            None if net == IPv4 => Err(anyhow!("no IPv4 addr for {}", host)),
-            None if net == IPv6 => Err(anyhow!("no IPv6 addr for {}", host)),
-            None                => Err(anyhow!("no IP addr for {}", host))
+        Ok(addrs)
+    }
+
+    #[instrument(skip_all)]
+    pub async fn reverse(&self, addr: IpAddr, expiry: Duration) -> Option<String> {
+        if let Some(name) = self.reverse.cached(addr) {
+            return name;
+        }
+
+        let lookup = async {
+            self.reverse.pace().await;
+            self.resolver.reverse_lookup(addr).await
+        };
+
+        let name = match timeout(expiry, lookup).await {
+            Ok(Ok(r))                 => r.iter().next().map(|name| name.to_utf8()),
+            Ok(Err(e)) if missing(&e) => None,
+            Ok(Err(_)) | Err(_)       => return None,
+        };
+
+        let name = name.map(|name| name.trim_end_matches('.').to_owned());
+
+        trace!("{}: {:?}", addr, name);
+
+        self.reverse.insert(addr, name.clone());
+
+        name
+    }
+
+    fn forwarder(&self, host: &str) -> Option<&TokioAsyncResolver> {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.forward.iter().filter(|(domain, _)| {
+            host == *domain || host.ends_with(&format!(".{}", domain))
+        }).max_by_key(|(domain, _)| domain.len()).map(|(_, resolver)| resolver)
+    }
+
+    async fn upstream(&self, host: &str, net: Network) -> Result<Vec<IpAddr>, ResolveError> {
+        let mut error = None;
+
+        for upstream in upstream::order(&self.upstreams) {
+            match query(upstream.resolver(), host, net).await {
+                Err(e) if !missing(&e) => {
+                    warn!("upstream {} failed: {}", upstream.spec(), e);
+                    upstream.failure(&e);
+                    error = Some(e);
+                },
+                result => {
+                    upstream.success();
+                    return result;
+                }
+            }
+        }
+
+        match error {
+            Some(e) if !self.rules.fallback => Err(e),
+            _                               => query(&self.resolver, host, net).await,
+        }
+    }
+}
+
+async fn query(resolver: &TokioAsyncResolver, host: &str, net: Network) -> Result<Vec<IpAddr>, ResolveError> {
+    Ok(match net {
+        Dual => resolver.lookup_ip(host).await?.iter().collect(),
+        IPv4 => resolver.ipv4_lookup(host).await?.iter().copied().map(IpAddr::from).collect(),
+        IPv6 => resolver.ipv6_lookup(host).await?.iter().copied().map(IpAddr::from).collect(),
+    })
+}
+
+impl Reverse {
+    fn cached(&self, addr: IpAddr) -> Option<Option<String>> {
+        let cache = self.cache.lock().unwrap();
+        match cache.get(&addr) {
+            Some((time, name)) if time.elapsed() < PTR_CACHE_TTL => Some(name.clone()),
+            _                                                     => None,
+        }
+    }
+
+    fn insert(&self, addr: IpAddr, name: Option<String>) {
+        let mut cache = self.cache.lock().unwrap();
+
+        if cache.len() >= PTR_CACHE_SIZE {
+            cache.retain(|_, (time, _)| time.elapsed() < PTR_CACHE_TTL);
         }
+
+        if cache.len() >= PTR_CACHE_SIZE {
+            cache.clear();
+        }
+
+        cache.insert(addr, (Instant::now(), name));
+    }
+
+    async fn pace(&self) {
+        let wait = {
+            let mut next = self.next.lock().await;
+            let now  = Instant::now();
+            let wait = next.saturating_duration_since(now);
+            *next = now.max(*next) + PTR_INTERVAL;
+            wait
+        };
+        sleep(wait).await;
     }
+}
+
+impl fmt::Debug for Resolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Resolver").finish_non_exhaustive()
+    }
+}
+
+impl Rules {
+    pub fn parse(hosts: &[String], forward: &[String], search: &[String]) -> Result<Self> {
+        let mut rules = Self::default();
+
+        for entry in hosts {
+            let (name, addr) = split(entry)?;
+            rules.host(name, addr.parse()?);
+        }
+
+        for entry in forward {
+            let (domain, addr) = split(entry)?;
+            rules.forward.entry(domain.to_owned()).or_default().push(server(addr)?);
+        }
 
-    async fn resolve(&self, host: &str) -> Result<Vec<IpAddr>> {
-        match self.resolver.lookup_ip(host).await {
-            Ok(r)  => Ok(r.iter().map(IpAddr::from).collect()),
-            Err(e) => result(host, e),
+        if !search.is_empty() {
+            let search = search.iter().filter(|name| *name != ".");
+            rules.search = Some(search.cloned().collect());
         }
+
+        Ok(rules)
+    }
+
+    pub fn host(&mut self, name: &str, addr: IpAddr) {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        self.hosts.entry(name).or_default().push(addr);
     }
 
-    async fn resolve4(&self, host: &str) -> Result<Vec<IpAddr>> {
-        match self.resolver.ipv4_lookup(host).await {
-            Ok(r)  => Ok(r.iter().copied().map(IpAddr::from).collect()),
-            Err(e) => result(host, e),
+    pub fn extend(&mut self, other: Rules) {
+        for (name, addrs) in other.hosts {
+            self.hosts.entry(name).or_default().extend(addrs);
+        }
+
+        for (domain, servers) in other.forward {
+            self.forward.entry(domain).or_default().extend(servers);
         }
+
+        if other.search.is_some() {
+            self.search = other.search;
+        }
+
+        if !other.upstreams.is_empty() {
+            self.upstreams = other.upstreams;
+        }
+
+        self.fallback |= other.fallback;
     }
+}
+
+impl Settings {
+    pub fn new(servers: &[String], protocol: &str, timeout: Option<Duration>, nocache: bool) -> Result<Self> {
+        let protocol = match protocol {
+            "udp" | "" => Protocol::Udp,
+            "tcp"      => Protocol::Tcp,
+            other      => return Err(anyhow!("invalid resolver protocol: {}", other)),
+        };
+
+        let servers = servers.iter().map(|s| server(s)).collect::<Result<_>>()?;
+
+        Ok(Self { servers, protocol, timeout, nocache })
+    }
+}
 
-    async fn resolve6(&self, host: &str) -> Result<Vec<IpAddr>> {
-        match self.resolver.ipv6_lookup(host).await {
-            Ok(r)  => Ok(r.iter().copied().map(IpAddr::from).collect()),
-            Err(e) => result(host, e)
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            servers:  Vec::new(),
+            protocol: Protocol::Udp,
+            timeout:  None,
+            nocache:  false,
         }
     }
 }
 
+impl FromStr for Policy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" | "" => Ok(Self::Random),
+            "first"       => Ok(Self::First),
+            "all"         => Ok(Self::All),
+            "both"        => Ok(Self::Both),
+            _             => Err(anyhow!("invalid address policy: {}", s)),
+        }
+    }
+}
+
+fn empty(host: &str, net: Network) -> Error {
+    match net {
+        IPv4 => anyhow!("no IPv4 addr for {}", host),
+        IPv6 => anyhow!("no IPv6 addr for {}", host),
+        Dual => anyhow!("no IP addr for {}", host),
+    }
+}
+
+fn split(entry: &str) -> Result<(&str, &str)> {
+    match entry.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name, value)),
+        _                                       => Err(anyhow!("invalid entry: {}", entry)),
+    }
+}
+
+fn bound(mut server: NameServerConfig, bind: &Bind) -> NameServerConfig {
+    let local = server.socket_addr.ip().is_loopback();
+    server.bind_addr = match server.socket_addr {
+        SocketAddr::V4(_) if !local => Some(bind.sa4()),
+        SocketAddr::V6(_) if !local => Some(bind.sa6()),
+        _                           => None,
+    };
+    server
+}
+
+fn server(server: &str) -> Result<SocketAddr> {
+    match server.parse() {
+        Ok(addr) => Ok(addr),
+        Err(_)   => Ok(SocketAddr::new(server.parse()?, 53)),
+    }
+}
+
+fn missing(e: &ResolveError) -> bool {
+    matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. })
+}
+
 fn result(host: &str, e: ResolveError) -> Result<Vec<IpAddr>> {
     match e.kind() {
         ResolveErrorKind::NoRecordsFound { .. } => Ok(Vec::new()),
@@ -112,6 +504,29 @@ mod test {
         })
     }
 
+    #[test]
+    fn resolve_policy() -> Result<()> {
+        test(async {
+            let resolver = resolver()?;
+            assert_eq!(1, resolver.select("8.8.8.8",    Dual, Policy::All).await?.len());
+            assert_eq!(1, resolver.select("google.com", Dual, Policy::First).await?.len());
+            assert!(!resolver.select("google.com", Dual, Policy::All).await?.is_empty());
+            assert!(resolver.select("fd00::1", IPv4, Policy::Both).await.is_err());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn policy_parse() -> Result<()> {
+        assert_eq!(Policy::Random, "".parse()?);
+        assert_eq!(Policy::Random, "random".parse()?);
+        assert_eq!(Policy::First,  "first".parse()?);
+        assert_eq!(Policy::All,    "all".parse()?);
+        assert_eq!(Policy::Both,   "both".parse()?);
+        assert!("any".parse::<Policy>().is_err());
+        Ok(())
+    }
+
     #[test]
     fn resolve_error() -> Result<()> {
         test(async {
@@ -122,6 +537,44 @@ mod test {
         })
     }
 
+    #[test]
+    fn resolve_rules() -> Result<()> {
+        let hosts   = ["db.lab=192.0.2.7".to_owned(), "DB.lab.=2001:db8::7".to_owned()];
+        let forward = ["corp.example=192.0.2.53".to_owned()];
+        let search  = [".".to_owned()];
+        let rules   = Rules::parse(&hosts, &forward, &search)?;
+
+        assert_eq!(2, rules.hosts["db.lab"].len());
+        assert_eq!(vec!["192.0.2.53:53".parse::<SocketAddr>()?], rules.forward["corp.example"]);
+        assert_eq!(Some(Vec::new()), rules.search);
+        assert!(Rules::parse(&["db.lab".to_owned()], &[], &[]).is_err());
+
+        test(async {
+            let resolver = Resolver { rules: Arc::new(rules), ..resolver()? };
+            assert_eq!(vec![IpAddr::from([192, 0, 2, 7])], resolver.select("db.lab", IPv4, Policy::All).await?);
+            assert_eq!(2, resolver.select("db.lab", Dual, Policy::All).await?.len());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn settings_parse() -> Result<()> {
+        let servers  = ["192.0.2.1".to_owned(), "[2001:db8::1]:5353".to_owned()];
+        let settings = Settings::new(&servers, "tcp", None, true)?;
+
+        assert_eq!(vec![
+            "192.0.2.1:53".parse::<SocketAddr>()?,
+            "[2001:db8::1]:5353".parse::<SocketAddr>()?,
+        ], settings.servers);
+        assert_eq!(Protocol::Tcp, settings.protocol);
+        assert!(settings.nocache);
+
+        assert!(Settings::new(&[], "quic", None, false).is_err());
+        assert!(Settings::new(&["invalid".to_owned()], "", None, false).is_err());
+
+        Ok(())
+    }
+
     fn test(future: impl Future<Output = Result<()>>) -> Result<()> {
         Builder::new_current_thread().enable_all().build()?.block_on(future)
     }
@@ -0,0 +1,303 @@
+use std::future::Future;
+use std::io::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::os::raw::{c_int, c_void};
+use std::os::unix::io::{AsRawFd, RawFd};
+use anyhow::{anyhow, Result};
+use libc::{setsockopt, socklen_t, IPPROTO_IP, IPPROTO_IPV6, IPV6_TCLASS, IP_TOS, SOL_SOCKET};
+use netdiag::Bind;
+use nix::ifaddrs::{getifaddrs, InterfaceAddress};
+use nix::sys::socket::SockAddr;
+use tokio::net::{TcpSocket, UdpSocket};
+use tokio::runtime::Handle;
+use synapi::tasks::SourceConfig;
+use crate::secure::Privileges;
+use super::Netns;
+
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Source {
+    pub address: Option<IpAddr>,
+    pub device:  Option<String>,
+    pub netns:   Option<Netns>,
+    pub mark:    Option<u32>,
+    pub dscp:    Option<u8>,
+}
+
+impl Source {
+    pub fn new(cfg: Option<&SourceConfig>) -> Result<Self> {
+        let SourceConfig { address, interface, vrf, netns, fwmark, dscp } = match cfg {
+            Some(cfg) => cfg.clone(),
+            None      => return Ok(Self::default()),
+        };
+
+        let device = match (interface, vrf) {
+            (Some(_), Some(_)) => return Err(anyhow!("interface and vrf are exclusive")),
+            (interface, vrf)   => interface.or(vrf),
+        };
+
+        let netns = netns.as_deref().map(Netns::new);
+        let mark  = fwmark;
+        let dscp  = dscp.map(u8::from);
+
+        if let Some(dscp @ 64..) = dscp {
+            return Err(anyhow!("invalid dscp: {dscp}"));
+        }
+
+        Ok(Self { address, device, netns, mark, dscp })
+    }
+
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub fn device(&self) -> Option<&str> {
+        self.device.as_deref()
+    }
+
+    /// netdiag opens its own raw sockets and only binds them to the source
+    /// address, so kinds that probe through it reject the other options.
+    pub fn netdiag(&self, kind: &str) -> Result<()> {
+        if let Some(device) = &self.device {
+            return Err(anyhow!("{kind} cannot bind to device {device}"));
+        }
+
+        if self.mark.is_some() {
+            return Err(anyhow!("{kind} cannot set fwmark"));
+        }
+
+        if self.dscp.is_some() {
+            return Err(anyhow!("{kind} cannot set dscp"));
+        }
+
+        Ok(())
+    }
+
+    /// fwmark and netns need capabilities the agent only keeps when started
+    /// with --allow-fwmark or --allow-netns.
+    pub fn permit(&self, privileges: Privileges) -> Result<()> {
+        if self.mark.is_some() && !privileges.mark {
+            return Err(anyhow!("fwmark requires CAP_NET_ADMIN (--allow-fwmark)"));
+        }
+
+        if self.netns.is_some() && !privileges.netns {
+            return Err(anyhow!("netns requires CAP_SYS_ADMIN (--allow-netns)"));
+        }
+
+        Ok(())
+    }
+
+    pub fn bind(&self, bind: &Bind) -> Result<Bind> {
+        let mut bind = bind.clone();
+
+        if let Some(device) = &self.device {
+            for addr in self.enter(|| addresses(device))? {
+                bind.set(addr);
+            }
+        }
+
+        if let Some(addr) = self.address {
+            bind.set(addr);
+        }
+
+        Ok(bind)
+    }
+
+    pub fn tcp(&self, bind: &Bind, addr: &SocketAddr) -> Result<TcpSocket> {
+        let (socket, bind) = self.enter(|| Ok(match addr {
+            SocketAddr::V4(_) => (TcpSocket::new_v4()?, bind.sa4()),
+            SocketAddr::V6(_) => (TcpSocket::new_v6()?, bind.sa6()),
+        }))?;
+
+        self.apply(&socket, addr)?;
+        socket.bind(self.local(bind, addr)?)?;
+
+        Ok(socket)
+    }
+
+    pub fn udp(&self, bind: &Bind, addr: &SocketAddr) -> Result<UdpSocket> {
+        let bind = match addr {
+            SocketAddr::V4(_) => bind.sa4(),
+            SocketAddr::V6(_) => bind.sa6(),
+        };
+
+        let local  = self.local(bind, addr)?;
+        let socket = self.enter(|| Ok(std::net::UdpSocket::bind(local)?))?;
+        socket.set_nonblocking(true)?;
+        self.apply(&socket, addr)?;
+
+        Ok(UdpSocket::from_std(socket)?)
+    }
+
+    pub fn enter<T: Send, F: FnOnce() -> Result<T> + Send>(&self, f: F) -> Result<T> {
+        match &self.netns {
+            Some(netns) => netns.enter(f),
+            None        => f(),
+        }
+    }
+
+    pub async fn create<T, F, R>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Future<Output = Result<T>>,
+        T: Send + 'static,
+    {
+        match &self.netns {
+            Some(netns) => {
+                let handle = Handle::current();
+                netns.spawn(move || handle.block_on(f())).await
+            },
+            None        => f().await,
+        }
+    }
+
+    pub fn apply<S: AsRawFd>(&self, socket: &S, addr: &SocketAddr) -> Result<()> {
+        let fd = socket.as_raw_fd();
+
+        if let Some(device) = &self.device {
+            setopt(fd, SOL_SOCKET, SO_BINDTODEVICE, device.as_bytes())?;
+        }
+
+        if let Some(mark) = self.mark {
+            setopt(fd, SOL_SOCKET, SO_MARK, &mark.to_ne_bytes())?;
+        }
+
+        if let Some(dscp) = self.dscp {
+            let tos = c_int::from(dscp << 2).to_ne_bytes();
+            match addr {
+                SocketAddr::V4(_) => setopt(fd, IPPROTO_IP,   IP_TOS,      &tos)?,
+                SocketAddr::V6(_) => setopt(fd, IPPROTO_IPV6, IPV6_TCLASS, &tos)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn local(&self, bind: SocketAddr, addr: &SocketAddr) -> Result<SocketAddr> {
+        match self.address {
+            Some(ip) if ip.is_ipv4() == addr.is_ipv4() => Ok(SocketAddr::new(ip, 0)),
+            Some(ip)                                   => Err(anyhow!("source {} cannot reach {}", ip, addr)),
+            None                                       => Ok(bind),
+        }
+    }
+}
+
+fn addresses(device: &str) -> Result<Vec<IpAddr>> {
+    let mut addrs = Vec::new();
+    for InterfaceAddress { interface_name, address, .. } in getifaddrs()? {
+        if let (true, Some(SockAddr::Inet(inet))) = (interface_name == device, address) {
+            match inet.ip().to_std() {
+                IpAddr::V6(ip) if (ip.segments()[0] & 0xffc0) == 0xfe80 => (),
+                ip                                                      => addrs.push(ip),
+            }
+        }
+    }
+    Ok(addrs)
+}
+
+pub(super) fn setopt(fd: RawFd, level: c_int, name: c_int, value: &[u8]) -> Result<()> {
+    let ptr = value.as_ptr() as *const c_void;
+    let len = value.len() as socklen_t;
+    unsafe {
+        match setsockopt(fd, level, name, ptr, len) {
+            0 => Ok(()),
+            _ => Err(Error::last_os_error().into()),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+const SO_BINDTODEVICE: c_int = libc::SO_BINDTODEVICE;
+
+#[cfg(target_os = "linux")]
+const SO_MARK: c_int = libc::SO_MARK;
+
+#[cfg(not(target_os = "linux"))]
+const SO_BINDTODEVICE: c_int = -1;
+
+#[cfg(not(target_os = "linux"))]
+const SO_MARK: c_int = -1;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn source_config() -> Result<()> {
+        assert!(Source::new(None)?.is_default());
+
+        let source = Source::new(Some(&SourceConfig {
+            address:   Some("192.0.2.1".parse()?),
+            interface: None,
+            vrf:       Some("blue".to_owned()),
+            netns:     Some("blue".to_owned()),
+            fwmark:    Some(42),
+            dscp:      Some(46.into()),
+        }))?;
+        assert_eq!(Some(Netns::new("blue")), source.netns);
+        assert_eq!(Some(42), source.mark);
+        assert_eq!(Some(46), source.dscp);
+        assert_eq!(Some("blue"), source.device());
+
+        assert!(Source::new(Some(&SourceConfig {
+            address:   None,
+            interface: Some("eth0".to_owned()),
+            vrf:       Some("blue".to_owned()),
+            netns:     None,
+            fwmark:    None,
+            dscp:      None,
+        })).is_err());
+
+        assert!(Source::new(Some(&SourceConfig {
+            address:   None,
+            interface: None,
+            vrf:       None,
+            netns:     None,
+            fwmark:    None,
+            dscp:      Some(64.into()),
+        })).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn source_netdiag() -> Result<()> {
+        let address = Some("192.0.2.1".parse()?);
+        let netns   = Some(Netns::new("blue"));
+
+        assert!(Source { address, netns, ..Default::default() }.netdiag("trace").is_ok());
+        assert!(Source { device: Some("eth0".to_owned()), ..Default::default() }.netdiag("trace").is_err());
+        assert!(Source { mark: Some(42), ..Default::default() }.netdiag("trace").is_err());
+        assert!(Source { dscp: Some(46), ..Default::default() }.netdiag("trace").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn source_permit() -> Result<()> {
+        let mark  = Source { mark: Some(42), ..Default::default() };
+        let netns = Source { netns: Some(Netns::new("blue")), ..Default::default() };
+        let all   = Privileges { netns: true, mark: true };
+
+        assert!(Source::default().permit(Privileges::default()).is_ok());
+        assert!(mark.permit(Privileges::default()).is_err());
+        assert!(netns.permit(Privileges { mark: true, ..Default::default() }).is_err());
+        assert!(mark.permit(all).is_ok());
+        assert!(netns.permit(all).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn source_local() -> Result<()> {
+        let bind = "0.0.0.0:0".parse()?;
+        let v4   = "192.0.2.2:53".parse()?;
+        let v6   = "[2001:db8::2]:53".parse()?;
+
+        let source = Source { address: Some("192.0.2.1".parse()?), ..Default::default() };
+        assert_eq!("192.0.2.1:0".parse::<SocketAddr>()?, source.local(bind, &v4)?);
+        assert!(source.local(bind, &v6).is_err());
+        assert_eq!(bind, Source::default().local(bind, &v6)?);
+
+        Ok(())
+    }
+}
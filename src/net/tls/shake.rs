@@ -2,10 +2,11 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 use anyhow::Result;
 use rustls::{ClientConfig, RootCertStore, ServerName};
-use tokio::net::{TcpSocket, TcpStream};
+use tokio::net::TcpStream;
 use tokio_rustls::{TlsConnector, client::TlsStream};
 use netdiag::Bind;
 use crate::cfg::Config;
+use crate::net::Source;
 use super::{Identity, Verifier};
 
 pub struct Shaker {
@@ -38,20 +39,14 @@ impl Shaker {
         })
     }
 
-    pub async fn shake(&self, name: &ServerName, addr: SocketAddr) -> Result<Connection> {
-        self.connect(name, addr).await
+    pub async fn shake(&self, name: &ServerName, addr: SocketAddr, source: &Source) -> Result<Connection> {
+        self.connect(name, addr, source).await
     }
 
-    async fn connect(&self, name: &ServerName, addr: SocketAddr) -> Result<Connection> {
+    async fn connect(&self, name: &ServerName, addr: SocketAddr, source: &Source) -> Result<Connection> {
         let Self { bind, connect, verifier } = self;
 
-        let (socket, bind) = match addr {
-            SocketAddr::V4(_) => (TcpSocket::new_v4()?, bind.sa4()),
-            SocketAddr::V6(_) => (TcpSocket::new_v6()?, bind.sa6()),
-        };
-
-        socket.bind(bind)?;
-
+        let socket = source.tcp(bind, &addr)?;
         let stream = socket.connect(addr).await?;
         let stream = connect.connect(name.clone(), stream).await?;
 
@@ -3,6 +3,7 @@ use std::time::SystemTime;
 use chrono::{DateTime, TimeZone, Utc};
 use rustls::{Certificate, RootCertStore, ServerName, Error};
 use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use serde::{Serialize, Serializer};
 use x509_certificate::{X509Certificate, asn1time::Time};
 
 pub struct Verifier {
@@ -68,6 +69,16 @@ impl Default for Identity {
     }
 }
 
+impl Serialize for Identity {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Valid(until) => s.serialize_newtype_variant("Identity", 0, "valid", &until.to_rfc3339()),
+            Self::Error(e)     => s.serialize_newtype_variant("Identity", 1, "error", &e.to_string()),
+            Self::Unknown      => s.serialize_unit_variant("Identity", 2, "unknown"),
+        }
+    }
+}
+
 impl TryFrom<Error> for Identity {
     type Error = Error;
 
@@ -0,0 +1,154 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use trust_dns_resolver::TokioAsyncResolver;
+use trust_dns_resolver::config::{NameServerConfig, Protocol};
+use trust_dns_resolver::error::ResolveError;
+
+pub struct Upstream {
+    spec:     String,
+    resolver: TokioAsyncResolver,
+    state:    Mutex<State>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Health {
+    pub upstream: String,
+    pub active:   bool,
+    pub healthy:  bool,
+    pub success:  u64,
+    pub failure:  u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error:    Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    success: u64,
+    failure: u64,
+    error:   Option<String>,
+    down:    Option<Instant>,
+}
+
+const UPSTREAM_BACKOFF: Duration = Duration::from_secs(30);
+
+impl Upstream {
+    pub fn new(spec: String, resolver: TokioAsyncResolver) -> Self {
+        let state = Mutex::new(State::default());
+        Self { spec, resolver, state }
+    }
+
+    pub fn spec(&self) -> &str {
+        &self.spec
+    }
+
+    pub fn resolver(&self) -> &TokioAsyncResolver {
+        &self.resolver
+    }
+
+    pub fn healthy(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        match state.down {
+            Some(time) => time.elapsed() >= UPSTREAM_BACKOFF,
+            None       => true,
+        }
+    }
+
+    pub fn success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.success += 1;
+        state.down     = None;
+    }
+
+    pub fn failure(&self, e: &ResolveError) {
+        let mut state = self.state.lock().unwrap();
+        state.failure += 1;
+        state.error    = Some(e.to_string());
+        state.down     = Some(Instant::now());
+    }
+
+    pub fn health(&self, active: bool) -> Health {
+        let healthy = self.healthy();
+        let state   = self.state.lock().unwrap();
+        Health {
+            upstream: self.spec.clone(),
+            active:   active,
+            healthy:  healthy,
+            success:  state.success,
+            failure:  state.failure,
+            error:    state.error.clone(),
+        }
+    }
+}
+
+pub fn order(upstreams: &[Upstream]) -> Vec<&Upstream> {
+    let (healthy, down): (Vec<_>, Vec<_>) = upstreams.iter().partition(|u| u.healthy());
+    healthy.into_iter().chain(down).collect()
+}
+
+pub fn parse(spec: &str) -> Result<NameServerConfig> {
+    let (protocol, rest, port) = match spec.split_once("://") {
+        Some(("udp",   rest)) => (Protocol::Udp,   rest, 53),
+        Some(("tcp",   rest)) => (Protocol::Tcp,   rest, 53),
+        Some(("tls",   rest)) => (Protocol::Tls,   rest, 853),
+        Some(("https", rest)) => (Protocol::Https, rest, 443),
+        Some((other,   _))    => return Err(anyhow!("invalid upstream protocol: {}", other)),
+        None                  => (Protocol::Udp,   spec, 53),
+    };
+
+    let (addr, name) = match rest.split_once('#') {
+        Some((addr, name)) => (addr, Some(name.to_owned())),
+        None               => (rest, None),
+    };
+
+    let addr = match addr.parse() {
+        Ok(addr) => addr,
+        Err(_)   => SocketAddr::new(addr.parse::<IpAddr>()?, port),
+    };
+
+    if matches!(protocol, Protocol::Tls | Protocol::Https) && name.is_none() {
+        return Err(anyhow!("{}: missing server name", spec));
+    }
+
+    Ok(nameserver(addr, protocol, name))
+}
+
+pub fn nameserver(addr: SocketAddr, protocol: Protocol, name: Option<String>) -> NameServerConfig {
+    NameServerConfig {
+        socket_addr:        addr,
+        protocol:           protocol,
+        tls_dns_name:       name,
+        trust_nx_responses: false,
+        tls_config:         None,
+        bind_addr:          None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_upstream() -> Result<()> {
+        let udp = parse("192.0.2.1")?;
+        assert_eq!(Protocol::Udp, udp.protocol);
+        assert_eq!("192.0.2.1:53".parse::<SocketAddr>()?, udp.socket_addr);
+
+        let tls = parse("tls://1.1.1.1#cloudflare-dns.com")?;
+        assert_eq!(Protocol::Tls, tls.protocol);
+        assert_eq!("1.1.1.1:853".parse::<SocketAddr>()?, tls.socket_addr);
+        assert_eq!(Some("cloudflare-dns.com"), tls.tls_dns_name.as_deref());
+
+        let doh = parse("https://[2001:4860:4860::8888]:8443#dns.google")?;
+        assert_eq!(Protocol::Https, doh.protocol);
+        assert_eq!(8443, doh.socket_addr.port());
+
+        assert!(parse("tls://1.1.1.1").is_err());
+        assert!(parse("quic://1.1.1.1#dns.example").is_err());
+        assert!(parse("tcp://dns.example").is_err());
+
+        Ok(())
+    }
+}
@@ -4,9 +4,9 @@ use anyhow::Result;
 use capo::{Ambient, Cap, Caps};
 use libc::*;
 use nix::unistd::getuid;
-use super::setuser;
+use super::{setuser, Privileges};
 
-pub fn apply(user: Option<&str>) -> Result<()> {
+pub fn apply(user: Option<&str>, privileges: Privileges) -> Result<()> {
     if user.is_some() && getuid().is_root() {
         set_securebits(SECBIT_KEEP_CAPS)?;
     }
@@ -17,6 +17,17 @@ pub fn apply(user: Option<&str>) -> Result<()> {
     caps.effective.insert(Cap::NetRaw);
     caps.permitted.insert(Cap::NetRaw);
     caps.inheritable.insert(Cap::NetRaw);
+
+    if privileges.mark {
+        caps.effective.insert(Cap::NetAdmin);
+        caps.permitted.insert(Cap::NetAdmin);
+    }
+
+    if privileges.netns {
+        caps.effective.insert(Cap::SysAdmin);
+        caps.permitted.insert(Cap::SysAdmin);
+    }
+
     caps.set()?;
 
     Ambient::raise(Cap::NetRaw)?;
@@ -24,6 +35,18 @@ pub fn apply(user: Option<&str>) -> Result<()> {
     Ok(())
 }
 
+pub fn privileges(privileges: Privileges) -> Privileges {
+    let caps = match Caps::get() {
+        Ok(caps) => caps,
+        Err(_)   => return Privileges::default(),
+    };
+
+    Privileges {
+        netns: privileges.netns && caps.effective.contains(Cap::SysAdmin),
+        mark:  privileges.mark  && caps.effective.contains(Cap::NetAdmin),
+    }
+}
+
 fn set_securebits(bits: c_int) -> Result<()> {
     unsafe {
         match prctl(PR_SET_SECUREBITS, bits) {
@@ -1,5 +1,14 @@
 pub use user::setuser;
-pub use unix::apply;
+pub use unix::{apply, privileges};
+
+/// Capabilities kept beyond CAP_NET_RAW, enabled only by explicit agent
+/// flags: `mark` keeps CAP_NET_ADMIN and `netns` keeps CAP_SYS_ADMIN,
+/// which is close to full root access.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Privileges {
+    pub netns: bool,
+    pub mark:  bool,
+}
 
 mod user;
 
@@ -1,7 +1,11 @@
 use anyhow::Result;
-use super::setuser;
+use super::{setuser, Privileges};
 
-pub fn apply(user: Option<&str>) -> Result<()> {
+pub fn apply(user: Option<&str>, _privileges: Privileges) -> Result<()> {
     user.map(setuser).transpose()?;
     Ok(())
 }
+
+pub fn privileges(_privileges: Privileges) -> Privileges {
+    Privileges::default()
+}
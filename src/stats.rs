@@ -1,8 +1,9 @@
 use std::convert::TryFrom;
 use std::num::TryFromIntError;
 use std::time::Duration;
+use serde::Serialize;
 
-#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize)]
 pub struct Summary {
     pub min: Duration,
     pub max: Duration,
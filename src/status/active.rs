@@ -69,6 +69,11 @@ impl Active {
         self.count.timeout.fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn running(&self) -> u64 {
+        let Tasks { fetch, knock, ping, query, shake, trace } = &self.tasks;
+        [fetch, knock, ping, query, shake, trace].iter().map(|n| n.load(Ordering::Relaxed)).sum()
+    }
+
     pub fn reset(&self) {
         self.count.success.store(0, Ordering::Relaxed);
         self.count.failure.store(0, Ordering::Relaxed);
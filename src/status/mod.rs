@@ -3,8 +3,10 @@ pub use addrs::Addresses;
 pub use monitor::Monitor;
 pub use system::system;
 
+pub use report::Listing;
 pub use report::Queue;
 pub use report::Report;
+pub use report::Waits;
 
 pub use status::Snapshot;
 pub use status::Status;
@@ -1,12 +1,24 @@
 use std::sync::atomic::Ordering;
 use log::info;
 use serde::{Deserialize, Serialize};
+use crate::export::Output;
+use crate::net::{Health, Rules};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Report {
     pub active: Active,
     pub export: Queue,
     pub tasks:  Vec<u64>,
+    #[serde(default)]
+    pub paused: Vec<u64>,
+    #[serde(default)]
+    pub fast:   Vec<u64>,
+    #[serde(default)]
+    pub dns:    Rules,
+    #[serde(default)]
+    pub health: Vec<Health>,
+    #[serde(default)]
+    pub waits:  Waits,
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
@@ -32,14 +44,48 @@ pub struct Tasks {
     pub trace: u64,
 }
 
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Waits {
+    pub runs:    u64,
+    pub packets: u64,
+    pub http:    u64,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
+pub struct Listing {
+    pub task:   u64,
+    pub test:   u64,
+    pub kind:   String,
+    pub paused: bool,
+    #[serde(default)]
+    pub fast:   bool,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Queue {
     pub length:  usize,
     pub records: usize,
 }
 
+impl Queue {
+    pub fn push(&mut self, output: &Output) {
+        self.length  += 1;
+        self.records += output.values.len();
+    }
+}
+
 impl Report {
-    pub fn new(active: &super::Active, export: Queue, tasks: Vec<u64>) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        active: &super::Active,
+        export: Queue,
+        tasks:  Vec<u64>,
+        paused: Vec<u64>,
+        fast:   Vec<u64>,
+        dns:    Rules,
+        health: Vec<Health>,
+        waits:  Waits,
+    ) -> Self {
         let active = Active {
             count: Count {
                 success: active.count.success.load(Ordering::Relaxed),
@@ -56,7 +102,7 @@ impl Report {
             }
         };
 
-        Self { active, export, tasks }
+        Self { active, export, tasks, paused, fast, dns, health, waits }
     }
 
     pub fn print(&self) {
@@ -79,11 +125,27 @@ impl Report {
         let active  = active.iter().map(u64::to_string).collect::<Vec<_>>();
 
         info!("running {} tasks: {:?}", self.tasks.len(), self.tasks);
+        info!("paused {} tasks: {:?}", self.paused.len(), self.paused);
+        info!("fast {} tasks: {:?}", self.fast.len(), self.fast);
         info!("execution status: {}", counts.join(" / "));
         info!("pending {} count: {}", pending, active.join(" / "));
 
         let Queue { length, records } = self.export;
 
         info!("queue count {}, entries: {}", length, records);
+
+        let Waits { runs, packets, http } = self.waits;
+
+        info!("budget waits runs {}, packets {}, http {}", runs, packets, http);
+
+        let Rules { hosts, forward, .. } = &self.dns;
+
+        info!("resolver hosts {}, forwarders: {}", hosts.len(), forward.len());
+
+        for Health { upstream, active, healthy, success, failure, .. } in &self.health {
+            let state = if *healthy { "up" } else { "down" };
+            let inuse = if *active { " (active)" } else { "" };
+            info!("upstream {upstream}{inuse} {state}, success {success}, failure {failure}");
+        }
     }
 }
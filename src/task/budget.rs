@@ -0,0 +1,214 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::sleep;
+use crate::status::Waits;
+
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    pub runs: Runs<Option<usize>>,
+    pub pps:  Option<u32>,
+    pub http: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Runs<T> {
+    pub fetch: T,
+    pub knock: T,
+    pub ping:  T,
+    pub query: T,
+    pub shake: T,
+    pub trace: T,
+}
+
+#[derive(Debug)]
+pub struct Budget {
+    runs:  Runs<Option<Semaphore>>,
+    pps:   Option<Bucket>,
+    http:  Option<Semaphore>,
+    waits: Counts,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    runs:    AtomicU64,
+    packets: AtomicU64,
+    http:    AtomicU64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate:  f64,
+    burst: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+pub type Permit<'a> = Option<SemaphorePermit<'a>>;
+
+impl Limits {
+    pub fn parse(runs: &[String], pps: Option<u32>, http: Option<usize>) -> Result<Self> {
+        let mut limits = Self { pps, http, ..Default::default() };
+
+        for spec in runs {
+            let (kind, count) = spec.split_once('=').ok_or_else(|| {
+                anyhow!("invalid run limit: {}", spec)
+            })?;
+
+            let count = Some(count.parse()?);
+
+            match kind {
+                "fetch" => limits.runs.fetch = count,
+                "knock" => limits.runs.knock = count,
+                "ping"  => limits.runs.ping  = count,
+                "query" => limits.runs.query = count,
+                "shake" => limits.runs.shake = count,
+                "trace" => limits.runs.trace = count,
+                _       => return Err(anyhow!("invalid task type: {}", kind)),
+            }
+        }
+
+        Ok(limits)
+    }
+}
+
+impl Budget {
+    pub fn new(limits: &Limits) -> Self {
+        let Limits { runs, pps, http } = limits;
+
+        let semaphore = |limit: Option<usize>| limit.map(Semaphore::new);
+
+        Self {
+            runs:  Runs {
+                fetch: semaphore(runs.fetch),
+                knock: semaphore(runs.knock),
+                ping:  semaphore(runs.ping),
+                query: semaphore(runs.query),
+                shake: semaphore(runs.shake),
+                trace: semaphore(runs.trace),
+            },
+            pps:   pps.map(Bucket::new),
+            http:  semaphore(*http),
+            waits: Counts::default(),
+        }
+    }
+
+    pub async fn fetch(&self) -> Permit<'_> {
+        acquire(&self.runs.fetch, &self.waits.runs).await
+    }
+
+    pub async fn knock(&self) -> Permit<'_> {
+        acquire(&self.runs.knock, &self.waits.runs).await
+    }
+
+    pub async fn ping(&self) -> Permit<'_> {
+        acquire(&self.runs.ping, &self.waits.runs).await
+    }
+
+    pub async fn query(&self) -> Permit<'_> {
+        acquire(&self.runs.query, &self.waits.runs).await
+    }
+
+    pub async fn shake(&self) -> Permit<'_> {
+        acquire(&self.runs.shake, &self.waits.runs).await
+    }
+
+    pub async fn trace(&self) -> Permit<'_> {
+        acquire(&self.runs.trace, &self.waits.runs).await
+    }
+
+    pub async fn http(&self) -> Permit<'_> {
+        acquire(&self.http, &self.waits.http).await
+    }
+
+    pub async fn packets(&self, count: usize) {
+        if let Some(bucket) = &self.pps {
+            let wait = bucket.reserve(count, Instant::now());
+            if !wait.is_zero() {
+                self.waits.packets.fetch_add(1, Ordering::Relaxed);
+                sleep(wait).await;
+            }
+        }
+    }
+
+    pub fn waits(&self) -> Waits {
+        Waits {
+            runs:    self.waits.runs.load(Ordering::Relaxed),
+            packets: self.waits.packets.load(Ordering::Relaxed),
+            http:    self.waits.http.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Bucket {
+    fn new(pps: u32) -> Self {
+        let rate  = f64::from(pps.max(1));
+        let state = Mutex::new((rate, Instant::now()));
+        Self { rate, burst: rate, state }
+    }
+
+    fn reserve(&self, count: usize, now: Instant) -> Duration {
+        let mut state = self.state.lock();
+        let (tokens, last) = &mut *state;
+
+        let elapsed = now.saturating_duration_since(*last).as_secs_f64();
+        *tokens = (*tokens + elapsed * self.rate).min(self.burst);
+        *last   = now.max(*last);
+        *tokens -= count as f64;
+
+        match *tokens < 0.0 {
+            true  => Duration::from_secs_f64(-*tokens / self.rate),
+            false => Duration::ZERO,
+        }
+    }
+}
+
+async fn acquire<'a>(semaphore: &'a Option<Semaphore>, waits: &AtomicU64) -> Permit<'a> {
+    let semaphore = match semaphore {
+        Some(semaphore) => semaphore,
+        None            => return None,
+    };
+
+    if let Ok(permit) = semaphore.try_acquire() {
+        return Some(permit);
+    }
+
+    waits.fetch_add(1, Ordering::Relaxed);
+
+    semaphore.acquire().await.ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn budget_limits() -> Result<()> {
+        let runs   = vec!["ping=10".to_owned(), "trace=2".to_owned()];
+        let limits = Limits::parse(&runs, Some(100), None)?;
+
+        assert_eq!(Some(10),  limits.runs.ping);
+        assert_eq!(Some(2),   limits.runs.trace);
+        assert_eq!(None,      limits.runs.fetch);
+        assert_eq!(Some(100), limits.pps);
+
+        assert!(Limits::parse(&["ping".to_owned()], None, None).is_err());
+        assert!(Limits::parse(&["dig=1".to_owned()], None, None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn budget_bucket() {
+        let bucket = Bucket::new(10);
+        let now    = bucket.state.lock().1;
+
+        assert_eq!(Duration::ZERO, bucket.reserve(10, now));
+        assert_eq!(Duration::from_millis(500), bucket.reserve(5, now));
+
+        let now = now + Duration::from_secs(1);
+        assert_eq!(Duration::ZERO, bucket.reserve(5, now));
+        assert_eq!(Duration::from_millis(100), bucket.reserve(1, now));
+    }
+}
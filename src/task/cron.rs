@@ -0,0 +1,202 @@
+use std::str::FromStr;
+use anyhow::{anyhow, Error, Result};
+use chrono::{DateTime, Datelike, Duration, LocalResult, TimeZone, Timelike};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cron {
+    minutes: u64,
+    hours:   u64,
+    days:    u64,
+    months:  u64,
+    weekday: u64,
+    anyday:  bool,
+    anywday: bool,
+}
+
+const MONTHS:   &[&str] = &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+const SEARCH: i64 = 366 * 5;
+
+impl Cron {
+    pub fn next<Z: TimeZone>(&self, after: &DateTime<Z>) -> Option<DateTime<Z>> {
+        let zone  = after.timezone();
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let first = start.date();
+
+        for date in (0..SEARCH).map(|n| first + Duration::days(n)) {
+            if !self.day(date.day(), date.month(), date.weekday().num_days_from_sunday()) {
+                continue;
+            }
+
+            let (h0, m0) = match date == first {
+                true  => (start.hour(), start.minute()),
+                false => (0, 0),
+            };
+
+            for hour in (h0..24).filter(|h| bit(self.hours, *h)) {
+                let m0 = if hour == h0 { m0 } else { 0 };
+                for minute in (m0..60).filter(|m| bit(self.minutes, *m)) {
+                    let time = date.and_hms(hour, minute, 0);
+                    match zone.from_local_datetime(&time) {
+                        LocalResult::Single(t)       if t > *after => return Some(t),
+                        LocalResult::Ambiguous(t, _) if t > *after => return Some(t),
+                        _                                          => continue,
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    fn day(&self, day: u32, month: u32, weekday: u32) -> bool {
+        let dom = bit(self.days, day);
+        let dow = bit(self.weekday, weekday);
+
+        let day = match (self.anyday, self.anywday) {
+            (true,  true)  => true,
+            (true,  false) => dow,
+            (false, true)  => dom,
+            (false, false) => dom || dow,
+        };
+
+        day && bit(self.months, month)
+    }
+}
+
+impl FromStr for Cron {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let spec = match spec.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly"              => "0 0 1 * *",
+            "@weekly"               => "0 0 * * 0",
+            "@daily" | "@midnight"  => "0 0 * * *",
+            "@hourly"               => "0 * * * *",
+            spec                    => spec,
+        };
+
+        let fields = spec.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days, months, wdays] = match fields[..] {
+            [a, b, c, d, e] => [a, b, c, d, e],
+            _               => return Err(anyhow!("invalid cron schedule: {}", spec)),
+        };
+
+        let weekday = field(wdays, 0, 7, WEEKDAYS)?;
+        let weekday = match bit(weekday, 7) {
+            true  => weekday | 1,
+            false => weekday,
+        };
+
+        Ok(Self {
+            minutes: field(minutes, 0, 59, &[])?,
+            hours:   field(hours,   0, 23, &[])?,
+            days:    field(days,    1, 31, &[])?,
+            months:  field(months,  1, 12, MONTHS)?,
+            weekday: weekday,
+            anyday:  days.starts_with('*'),
+            anywday: wdays.starts_with('*'),
+        })
+    }
+}
+
+fn field(spec: &str, min: u32, max: u32, names: &[&str]) -> Result<u64> {
+    let invalid = || anyhow!("invalid cron field: {}", spec);
+
+    let value = |s: &str| -> Result<u32> {
+        let s = s.to_ascii_lowercase();
+        let n = match names.iter().position(|name| *name == s) {
+            Some(index) => index as u32 + min,
+            None        => s.parse().map_err(|_| invalid())?,
+        };
+        match (min..=max).contains(&n) {
+            true  => Ok(n),
+            false => Err(invalid()),
+        }
+    };
+
+    let mut bits = 0u64;
+
+    for part in spec.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse().map_err(|_| invalid())?),
+            None                => (part, 1),
+        };
+
+        let (lo, hi) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((lo, hi))    => (value(lo)?, value(hi)?),
+            None if step > 1  => (value(range)?, max),
+            None              => (value(range)?, value(range)?),
+        };
+
+        if step == 0 || lo > hi {
+            return Err(invalid());
+        }
+
+        for n in (lo..=hi).step_by(step) {
+            bits |= 1 << n;
+        }
+    }
+
+    Ok(bits)
+}
+
+fn bit(bits: u64, n: u32) -> bool {
+    bits & (1 << n) != 0
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use super::*;
+
+    #[test]
+    fn cron_parse() -> Result<()> {
+        let cron = "*/15 9-17 * * mon-fri".parse::<Cron>()?;
+        assert_eq!(0b1 | 1 << 15 | 1 << 30 | 1 << 45, cron.minutes);
+        assert_eq!((9..=17).fold(0, |b, h| b | 1 << h), cron.hours);
+        assert_eq!((1..=5).fold(0, |b, d| b | 1 << d), cron.weekday);
+        assert!(cron.anyday);
+        assert!(!cron.anywday);
+
+        let cron = "0 0 1 jan,jul 7".parse::<Cron>()?;
+        assert_eq!(1 << 1 | 1 << 7, cron.months);
+        assert_eq!(1 << 0 | 1 << 7, cron.weekday);
+
+        assert_eq!("0 0 * * *".parse::<Cron>()?, "@daily".parse::<Cron>()?);
+
+        assert!("* * * *".parse::<Cron>().is_err());
+        assert!("60 * * * *".parse::<Cron>().is_err());
+        assert!("*/0 * * * *".parse::<Cron>().is_err());
+        assert!("5-1 * * * *".parse::<Cron>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn cron_next() -> Result<()> {
+        let at = |y, m, d, h, min| Utc.ymd(y, m, d).and_hms(h, min, 0);
+
+        let cron = "*/15 9-17 * * mon-fri".parse::<Cron>()?;
+        assert_eq!(Some(at(2022, 5, 2, 9, 15)),  cron.next(&at(2022, 5, 2, 9, 0)));
+        assert_eq!(Some(at(2022, 5, 2, 9, 15)),  cron.next(&(at(2022, 5, 2, 9, 14) + Duration::seconds(30))));
+        assert_eq!(Some(at(2022, 5, 3, 9, 0)),   cron.next(&at(2022, 5, 2, 17, 45)));
+        assert_eq!(Some(at(2022, 5, 9, 9, 0)),   cron.next(&at(2022, 5, 6, 18, 0)));
+
+        let cron = "0 12 1 * mon".parse::<Cron>()?;
+        assert_eq!(Some(at(2022, 5, 1, 12, 0)),  cron.next(&at(2022, 4, 30, 0, 0)));
+        assert_eq!(Some(at(2022, 5, 2, 12, 0)),  cron.next(&at(2022, 5, 1, 12, 0)));
+        assert_eq!(Some(at(2022, 6, 1, 12, 0)),  cron.next(&at(2022, 5, 30, 12, 0)));
+
+        let cron = "0 0 29 2 *".parse::<Cron>()?;
+        assert_eq!(Some(at(2024, 2, 29, 0, 0)),  cron.next(&at(2022, 5, 1, 0, 0)));
+
+        let cron = "0 0 31 2 *".parse::<Cron>()?;
+        assert_eq!(None, cron.next(&at(2022, 5, 1, 0, 0)));
+
+        Ok(())
+    }
+}
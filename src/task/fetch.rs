@@ -3,35 +3,46 @@ use std::net::IpAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use bytes::Bytes;
-use anyhow::{Error, Result};
-use hyper::{Body, Method, StatusCode};
+use anyhow::{anyhow, Error, Result};
+use hyper::{Body, Method, StatusCode, Uri};
 use hyper::body::HttpBody;
 use hyper::header::HeaderMap;
 use tracing::{debug, info_span, warn, Instrument};
-use tokio::time::{sleep, timeout};
+use tokio::time::timeout;
 use synapi::tasks::FetchConfig;
 use crate::cfg::Config;
-use crate::export::{record, Envoy};
-use crate::net::Network;
+use crate::export::{record, record::Race, Envoy};
+use crate::net::{Network, Policy, Resolver, Source};
 use crate::net::http::{HttpClient, Request};
 use crate::net::tls::Identity;
 use crate::status::Active;
-use super::Task;
+use super::{fanout, Budget, Followup, Pin, Schedule, Task, Trigger};
 
 pub struct Fetch {
-    task:    u64,
-    test:    u64,
-    network: Network,
-    target:  Arc<String>,
-    method:  Method,
-    headers: Option<HeaderMap>,
-    body:    Option<Bytes>,
-    verify:  bool,
-    period:  Duration,
-    expiry:  Duration,
-    envoy:   Envoy,
-    client:  Arc<Fetcher>,
-    active:  Arc<Active>,
+    task:     u64,
+    test:     u64,
+    network:  Network,
+    policy:   Policy,
+    target:   Arc<String>,
+    method:   Method,
+    headers:  Option<HeaderMap>,
+    body:     Option<Bytes>,
+    verify:   bool,
+    race:     bool,
+    eyeballs: bool,
+    pin:      Pin,
+    sni:      Option<String>,
+    schedule: Schedule,
+    lag:      Duration,
+    run:      u64,
+    expiry:   Duration,
+    envoy:    Envoy,
+    client:   Arc<Fetcher>,
+    resolver: Resolver,
+    budget:   Arc<Budget>,
+    source:   Source,
+    active:   Arc<Active>,
+    followup: Option<Followup>,
 }
 
 impl Fetch {
@@ -47,59 +58,140 @@ impl Fetch {
         }).transpose()?;
 
         Ok(Self {
-            task:    task.task,
-            test:    task.test,
-            network: task.network,
-            target:  Arc::new(cfg.target),
-            method:  method,
-            headers: headers,
-            body:    cfg.body.map(Bytes::from),
-            verify:  !cfg.insecure,
-            period:  cfg.period.into(),
-            expiry:  cfg.expiry.into(),
-            envoy:   task.envoy,
-            client:  client,
-            active:  task.active,
+            task:     task.task,
+            test:     task.test,
+            network:  task.network,
+            policy:   match cfg.race {
+                true  => Policy::Both,
+                false => task.policy(&cfg.policy)?,
+            },
+            target:   Arc::new(cfg.target),
+            method:   method,
+            headers:  headers,
+            body:     cfg.body.map(Bytes::from),
+            verify:   !cfg.insecure,
+            race:     cfg.race,
+            eyeballs: cfg.eyeballs && !cfg.race,
+            pin:      Pin::new(cfg.address, cfg.resolve),
+            sni:      cfg.sni,
+            schedule: Schedule::new(cfg.period.map(Duration::from), &task.schedule, task.fast.clone(), task.stop.clone())?,
+            lag:      Duration::ZERO,
+            run:      0,
+            expiry:   cfg.expiry.into(),
+            envoy:    task.envoy,
+            client:   client,
+            resolver: task.resolver,
+            budget:   task.budget,
+            source:   task.source,
+            active:   task.active,
+            followup: task.followup,
         })
     }
 
-    pub async fn exec(self) -> Result<()> {
+    pub async fn exec(mut self) -> Result<()> {
         loop {
-            let task = self.task;
-            let test = self.test;
+            self.lag = self.schedule.tick().await;
+            self.run += 1;
+            self.run().await;
+            self.adapt().await;
+        }
+    }
+
+    pub async fn once(mut self) -> Result<()> {
+        self.run += 1;
+        self.run().await;
+        Ok(())
+    }
+
+    async fn adapt(&mut self) {
+        if let Some(switch) = self.schedule.adapt() {
+            debug!("{switch}");
+            self.envoy.export(record::Mode {
+                task:   self.task,
+                test:   self.test,
+                run:    self.run,
+                fast:   switch.fast,
+                cause:  switch.cause,
+                period: switch.period,
+            }).await;
+        }
+    }
 
-            let span = info_span!("fetch", task, test);
+    async fn run(&self) {
+        let task = self.task;
+        let test = self.test;
 
-            async {
-                let _guard = self.active.fetch();
-                let result = self.fetch(&self.target);
+        let span = info_span!("fetch", task, test);
 
-                match timeout(self.expiry, result).await {
-                    Ok(Ok(stats)) => self.success(stats).await,
-                    Ok(Err(e))    => self.failure(e).await,
-                    Err(_)        => self.timeout().await,
-                }
-            }.instrument(span).await;
+        async {
+            let _permit = self.budget.fetch().await;
+            let _guard = self.active.fetch();
+            let result = self.fetch(&self.target);
 
-            sleep(self.period).await;
-        }
+            match timeout(self.expiry, result).await {
+                Ok(Ok(outs)) => self.results(outs).await,
+                Ok(Err(e))   => self.failure(e).await,
+                Err(_)       => self.timeout().await,
+            }
+        }.instrument(span).await;
     }
 
-    async fn fetch(&self, target: &str) -> Result<Output> {
+    async fn fetch(&self, target: &str) -> Result<Vec<Result<Output>>> {
         debug!("target {}", target);
 
+        let start = Instant::now();
+        let uri   = target.parse::<Uri>()?;
+        let host  = uri.host().ok_or_else(|| anyhow!("{}: missing host", uri))?;
+
+        if let Some(addr) = self.pin.get(host) {
+            let output = self.measure(uri.clone(), addr, start, Duration::ZERO).await;
+            return Ok(vec![output.map(|out| Output { pinned: true, ..out })]);
+        }
+
+        if self.eyeballs {
+            return Ok(vec![self.request(uri, None, start).await]);
+        }
+
+        let addrs = self.resolver.select(host, self.network, self.policy).await?;
+        let dns   = start.elapsed();
+
+        let mut outs = fanout(addrs, |addr| self.measure(uri.clone(), addr, start, dns)).await;
+
+        if self.race {
+            race(&mut outs);
+        }
+
+        Ok(outs)
+    }
+
+    async fn measure(&self, uri: Uri, addr: IpAddr, start: Instant, dns: Duration) -> Result<Output> {
+        debug!("address {}", addr);
+        let mut output = self.request(uri, Some(addr), start).await?;
+        output.dns = dns;
+        Ok(output)
+    }
+
+    async fn request(&self, uri: Uri, addr: Option<IpAddr>, start: Instant) -> Result<Output> {
         let network = self.network;
         let method  = self.method.clone();
         let body    = self.body.clone();
-        let start   = Instant::now();
 
-        let mut req = Request::new(network, method, target.parse()?)?;
-        *req.body() = body.map(Body::from).unwrap_or_else(Body::empty);
+        let mut req = Request::new(network, method, uri)?;
+        *req.body()     = body.map(Body::from).unwrap_or_else(Body::empty);
+        *req.addr()     = addr;
+        *req.sni()      = self.sni.clone();
+        *req.race()     = self.eyeballs;
+        *req.resolver() = Some(self.resolver.clone());
+        *req.source()   = self.source.clone();
 
         if let Some(headers) = self.headers.as_ref().cloned() {
             req.headers().extend(headers);
         }
 
+        let queued  = Instant::now();
+        let _permit = self.budget.http().await;
+        let start   = start + queued.elapsed();
+
         let output = self.client.execute(start, req).await?;
 
         if let Identity::Error(e) = &output.server {
@@ -111,22 +203,43 @@ impl Fetch {
         Ok(output)
     }
 
+    async fn results(&self, outs: Vec<Result<Output>>) {
+        for out in outs {
+            match out {
+                Ok(out) => self.success(out).await,
+                Err(e)  => self.failure(e).await,
+            }
+        }
+    }
+
     async fn success(&self, out: Output) {
         debug!("{out}");
+        self.schedule.success(out.rtt, 1, 0);
         self.envoy.export(record::Fetch {
-            task:    self.task,
-            test:    self.test,
-            target:  self.target.clone(),
-            addr:    out.addr,
-            server:  out.server,
-            status:  out.status.as_u16(),
-            dns:     out.dns,
-            tcp:     out.tcp,
-            tls:     out.tls,
-            rtt:     out.rtt,
-            size:    out.bytes,
+            task:     self.task,
+            test:     self.test,
+            target:   self.target.clone(),
+            addr:     out.addr,
+            server:   out.server,
+            status:   out.status.as_u16(),
+            dns:      out.dns,
+            tcp:      out.tcp,
+            tls:      out.tls,
+            rtt:      out.rtt,
+            size:     out.bytes,
+            attempts: out.attempts,
+            pinned:   out.pinned,
+            sni:      self.sni.clone(),
+            race:     out.race,
+            device:   self.source.device.clone(),
+            mark:     self.source.mark,
+            lag:      self.lag,
+            maint:    self.schedule.maintenance(),
         }).await;
         self.active.success();
+        if let Some(followup) = &self.followup {
+            followup.success();
+        }
     }
 
     async fn failure(&self, err: Error) {
@@ -134,18 +247,32 @@ impl Fetch {
         self.envoy.export(record::Error {
             task:  self.task,
             test:  self.test,
+            run:   self.run,
             cause: err.to_string(),
+            lag:   self.lag,
+            maint: self.schedule.maintenance(),
         }).await;
         self.active.failure();
+        self.schedule.failure();
+        if let Some(followup) = &self.followup {
+            followup.trigger(Trigger::Failure, self.run, &self.target);
+        }
     }
 
     async fn timeout(&self) {
         warn!("timeout");
         self.envoy.export(record::Timeout {
-            task: self.task,
-            test: self.test,
+            task:  self.task,
+            test:  self.test,
+            run:   self.run,
+            lag:   self.lag,
+            maint: self.schedule.maintenance(),
         }).await;
         self.active.timeout();
+        self.schedule.timeout();
+        if let Some(followup) = &self.followup {
+            followup.trigger(Trigger::Timeout, self.run, &self.target);
+        }
     }
 }
 
@@ -182,20 +309,43 @@ impl Fetcher {
         let tcp    = times.tcp;
         let tls    = times.tls.unwrap_or_default();
 
-        Ok(Output { addr, server, status, dns, tcp, tls, rtt, bytes })
+        let attempts = res.attempts;
+        let pinned   = false;
+        let race     = None;
+
+        Ok(Output { addr, server, status, dns, tcp, tls, rtt, bytes, attempts, pinned, race })
+    }
+}
+
+fn race(outs: &mut [Result<Output>]) {
+    let connect = |net: Network| {
+        outs.iter().flatten().find(|out| Network::from(out.addr) == net).map(|out| out.tcp)
+    };
+
+    let race = match (connect(Network::IPv4), connect(Network::IPv6)) {
+        (Some(ip4), Some(ip6)) if ip4 <= ip6 => Race { winner: Network::IPv4, margin: ip6 - ip4 },
+        (Some(ip4), Some(ip6))               => Race { winner: Network::IPv6, margin: ip4 - ip6 },
+        _                                    => return,
+    };
+
+    for out in outs.iter_mut().flatten() {
+        out.race = Some(race);
     }
 }
 
 #[derive(Debug)]
 pub struct Output {
-    addr:   IpAddr,
-    server: Identity,
-    status: StatusCode,
-    dns:    Duration,
-    tcp:    Duration,
-    tls:    Duration,
-    rtt:    Duration,
-    bytes:  usize,
+    addr:     IpAddr,
+    server:   Identity,
+    status:   StatusCode,
+    dns:      Duration,
+    tcp:      Duration,
+    tls:      Duration,
+    rtt:      Duration,
+    bytes:    usize,
+    attempts: usize,
+    pinned:   bool,
+    race:     Option<Race>,
 }
 
 impl fmt::Display for Output {
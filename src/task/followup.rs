@@ -0,0 +1,356 @@
+use std::convert::TryFrom;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Result};
+use futures::{StreamExt, TryStreamExt};
+use hyper::Uri;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+use tokio::sync::Semaphore;
+use tokio::task::spawn;
+use tokio::time::timeout;
+use netdiag::{Node, Protocol, Tracer};
+use synapi::tasks::FollowupConfig;
+use crate::export::{record, Envoy};
+use crate::net::{Network, Policy, Resolver};
+use crate::stats::summarize;
+use super::{Budget, Task};
+
+pub struct Followup {
+    task:    u64,
+    test:    u64,
+    actions: Vec<Action>,
+    runner:  Arc<Runner>,
+    active:  Arc<Semaphore>,
+    state:   Mutex<State>,
+}
+
+struct Runner {
+    network:  Network,
+    envoy:    Envoy,
+    tracer:   Arc<Tracer>,
+    resolver: Resolver,
+    budget:   Arc<Budget>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Trigger {
+    Failure,
+    Timeout,
+}
+
+#[derive(Debug)]
+struct Action {
+    kind:   Kind,
+    on:     Option<Trigger>,
+    after:  u32,
+    count:  usize,
+    expiry: Duration,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Kind {
+    Trace,
+    Mtr,
+    Query,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    run:    u64,
+    streak: u32,
+}
+
+const ACTIVE: usize    = 2;
+const HOPS:   u8       = 30;
+const PROBE:  Duration = Duration::from_secs(1);
+
+impl Followup {
+    pub fn new(task: &Task, cfgs: &[FollowupConfig], tracer: Arc<Tracer>) -> Result<Self> {
+        let actions = cfgs.iter().map(Action::new).collect::<Result<Vec<_>>>()?;
+
+        if actions.iter().any(|action| matches!(action.kind, Kind::Trace | Kind::Mtr)) {
+            task.source.netdiag("trace followup")?;
+        }
+
+        let runner = Runner {
+            network:  task.network,
+            envoy:    task.envoy.clone(),
+            tracer:   tracer,
+            resolver: task.resolver.clone(),
+            budget:   task.budget.clone(),
+        };
+
+        Ok(Self {
+            task:    task.task,
+            test:    task.test,
+            actions: actions,
+            runner:  Arc::new(runner),
+            active:  Arc::new(Semaphore::new(ACTIVE)),
+            state:   Mutex::new(State::default()),
+        })
+    }
+
+    pub fn success(&self) {
+        self.state.lock().streak = 0;
+    }
+
+    /// Start the actions matching this failure in the background, skipping
+    /// any once ACTIVE followups of this task are already running.
+    pub fn trigger(&self, trigger: Trigger, run: u64, target: &str) {
+        let streak = {
+            let mut state = self.state.lock();
+            if state.run == run {
+                return;
+            }
+            state.run     = run;
+            state.streak += 1;
+            state.streak
+        };
+
+        let target = Arc::new(target.to_owned());
+
+        for action in &self.actions {
+            if !action.matches(trigger, streak) {
+                continue;
+            }
+
+            let permit = match self.active.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_)     => {
+                    debug!("followup {} for run {run} skipped, too many active", action.kind.name());
+                    continue;
+                },
+            };
+
+            debug!("followup {} for run {run} after {streak} failures", action.kind.name());
+
+            let record = record::Followup {
+                task:   self.task,
+                test:   self.test,
+                run:    run,
+                action: action.kind.name(),
+                target: target.clone(),
+                output: String::new(),
+                time:   Duration::ZERO,
+            };
+
+            let Action { kind, count, expiry, .. } = *action;
+            let runner = self.runner.clone();
+            let host   = host(&target);
+
+            spawn(async move {
+                let record = complete(record, within(expiry, runner.run(kind, &host, count))).await;
+                runner.envoy.export(record).await;
+                drop(permit);
+            });
+        }
+    }
+}
+
+impl Runner {
+    async fn run(&self, kind: Kind, host: &str, count: usize) -> Result<Value> {
+        match kind {
+            Kind::Trace => self.trace(host, count).await,
+            Kind::Mtr   => self.mtr(host, count).await,
+            Kind::Query => self.query(host).await,
+        }
+    }
+
+    async fn trace(&self, host: &str, count: usize) -> Result<Value> {
+        let route = self.route(host, count).await?;
+
+        Ok(route.iter().enumerate().map(|(hop, nodes)| {
+            let nodes = nodes.iter().map(|node| match node {
+                Node::Node(_, addr, rtt, _) => json!({ "addr": addr, "rtt": rtt.as_micros() as u64 }),
+                Node::None(_)               => Value::Null,
+            }).collect::<Vec<_>>();
+            json!({ "hop": hop + 1, "nodes": nodes })
+        }).collect())
+    }
+
+    async fn mtr(&self, host: &str, count: usize) -> Result<Value> {
+        let route = self.route(host, count).await?;
+
+        Ok(route.iter().enumerate().map(|(hop, nodes)| {
+            let mut addrs = Vec::new();
+            let mut rtt   = Vec::new();
+
+            for node in nodes {
+                if let Node::Node(_, addr, time, _) = node {
+                    if !addrs.contains(addr) {
+                        addrs.push(*addr);
+                    }
+                    rtt.push(*time);
+                }
+            }
+
+            let sent  = nodes.len();
+            let lost  = sent - rtt.len();
+            let stats = summarize(&rtt).unwrap_or_default();
+
+            json!({
+                "hop":   hop + 1,
+                "addrs": addrs,
+                "sent":  sent,
+                "lost":  lost,
+                "min":   stats.min.as_micros() as u64,
+                "avg":   stats.avg.as_micros() as u64,
+                "max":   stats.max.as_micros() as u64,
+            })
+        }).collect())
+    }
+
+    async fn query(&self, host: &str) -> Result<Value> {
+        let start = Instant::now();
+        let addrs = self.resolver.select(host, self.network, Policy::Both).await?;
+        let time  = start.elapsed();
+        Ok(json!({ "addrs": addrs, "time": time.as_micros() as u64 }))
+    }
+
+    async fn route(&self, host: &str, count: usize) -> Result<Vec<Vec<Node>>> {
+        let addr   = self.resolver.lookup(host, self.network).await?;
+        let tracer = &self.tracer;
+        let source = tracer.reserve(Protocol::default(), addr).await?;
+
+        let mut probe = source.probe()?;
+        let mut route = Vec::new();
+
+        for ttl in 1..=HOPS {
+            self.budget.packets(count).await;
+
+            let stream = tracer.probe(&mut probe, ttl, PROBE);
+            let nodes  = stream.take(count).try_collect::<Vec<_>>().await?;
+
+            let done = nodes.iter().any(|node| match node {
+                Node::Node(_, ip, _, last) => *last || ip == &addr,
+                Node::None(_)              => false,
+            });
+
+            route.push(nodes);
+
+            if done {
+                break;
+            }
+        }
+
+        Ok(route)
+    }
+}
+
+impl Action {
+    fn new(cfg: &FollowupConfig) -> Result<Self> {
+        let kind = match cfg.action.as_str() {
+            "trace"         => Kind::Trace,
+            "mtr"           => Kind::Mtr,
+            "query" | "dns" => Kind::Query,
+            other           => return Err(anyhow!("invalid followup action: {other}")),
+        };
+
+        let on = match cfg.on.as_deref() {
+            Some("failure")    => Some(Trigger::Failure),
+            Some("timeout")    => Some(Trigger::Timeout),
+            Some("any") | None => None,
+            Some(other)        => return Err(anyhow!("invalid followup trigger: {other}")),
+        };
+
+        let count = match kind {
+            Kind::Trace => 3,
+            Kind::Mtr   => 10,
+            Kind::Query => 1,
+        };
+
+        Ok(Self {
+            kind:   kind,
+            on:     on,
+            after:  cfg.after.unwrap_or(1).max(1),
+            count:  cfg.count.map(usize::from).unwrap_or(count),
+            expiry: cfg.expiry.map(Duration::from).unwrap_or(Duration::from_secs(10)),
+        })
+    }
+
+    fn matches(&self, trigger: Trigger, streak: u32) -> bool {
+        let on = self.on.is_none() || self.on == Some(trigger);
+        on && matches!(streak % self.after, 0)
+    }
+}
+
+impl Kind {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Trace => "trace",
+            Self::Mtr   => "mtr",
+            Self::Query => "query",
+        }
+    }
+}
+
+async fn complete<F: Future<Output = Result<Value>>>(mut record: record::Followup, future: F) -> record::Followup {
+    let start  = Instant::now();
+    let output = match future.await {
+        Ok(output) => output,
+        Err(e)     => {
+            warn!(followup = record.action, error = &*e.to_string());
+            json!({ "error": e.to_string() })
+        },
+    };
+
+    record.output = output.to_string();
+    record.time   = start.elapsed();
+    record
+}
+
+async fn within<T, F: Future<Output = Result<T>>>(expiry: Duration, future: F) -> Result<T> {
+    timeout(expiry, future).await.map_err(|_| anyhow!("followup timeout"))?
+}
+
+fn host(target: &str) -> String {
+    match Uri::try_from(target) {
+        Ok(uri) if uri.scheme().is_some() => uri.host().unwrap_or(target).to_owned(),
+        _                                 => target.trim_end_matches('.').to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn action(action: &str, on: Option<&str>, after: Option<u32>) -> Result<Action> {
+        Action::new(&FollowupConfig {
+            action: action.to_owned(),
+            on:     on.map(str::to_owned),
+            after:  after,
+            count:  None,
+            expiry: None,
+        })
+    }
+
+    #[test]
+    fn followup_action() -> Result<()> {
+        let trace = action("trace", None, None)?;
+        assert!(trace.matches(Trigger::Failure, 1));
+        assert!(trace.matches(Trigger::Timeout, 2));
+
+        let mtr = action("mtr", Some("timeout"), Some(3))?;
+        assert_eq!(10, mtr.count);
+        assert!(!mtr.matches(Trigger::Failure, 3));
+        assert!(!mtr.matches(Trigger::Timeout, 2));
+        assert!(mtr.matches(Trigger::Timeout, 3));
+        assert!(mtr.matches(Trigger::Timeout, 6));
+
+        assert!(action("ping", None, None).is_err());
+        assert!(action("capture", None, None).is_err());
+        assert!(action("trace", Some("success"), None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn followup_host() {
+        assert_eq!("example.com", host("https://example.com:8443/path"));
+        assert_eq!("example.com", host("example.com."));
+        assert_eq!("192.0.2.1",   host("192.0.2.1"));
+    }
+}
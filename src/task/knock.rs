@@ -10,75 +10,120 @@ use tokio::time::{sleep, timeout};
 use netdiag::{self, Knocker};
 use synapi::tasks::KnockConfig;
 use crate::export::{record, Envoy};
-use crate::net::{Network, Resolver};
+use crate::net::{Network, Policy, Resolver};
 use crate::stats::{summarize, Summary};
 use crate::status::Active;
-use super::{Expiry, Task};
+use super::{fanout, Budget, Expiry, Followup, Schedule, Task, Trigger};
 
 pub struct Knock {
     task:     u64,
     test:     u64,
     network:  Network,
+    policy:   Policy,
     target:   Arc<String>,
     port:     u16,
-    period:   Duration,
+    schedule: Schedule,
+    lag:      Duration,
+    run:      u64,
     count:    usize,
     delay:    Duration,
     expiry:   Expiry,
     envoy:    Envoy,
     knocker:  Arc<Knocker>,
     resolver: Resolver,
+    budget:   Arc<Budget>,
+    device:   Option<String>,
     active:   Arc<Active>,
+    followup: Option<Followup>,
 }
 
 impl Knock {
-    pub fn new(task: Task, cfg: KnockConfig, knocker: Arc<Knocker>) -> Self {
+    pub fn new(task: Task, cfg: KnockConfig, knocker: Arc<Knocker>) -> Result<Self> {
+        task.source.netdiag("knock")?;
+
         let count  = cfg.count.into();
         let expiry = Expiry::new(cfg.expiry.into(), count);
 
-        Self {
+        Ok(Self {
             task:     task.task,
             test:     task.test,
             network:  task.network,
+            policy:   task.policy(&cfg.policy)?,
             target:   Arc::new(cfg.target),
             port:     cfg.port,
-            period:   cfg.period.into(),
+            schedule: Schedule::new(cfg.period.map(Duration::from), &task.schedule, task.fast.clone(), task.stop.clone())?,
+            lag:      Duration::ZERO,
+            run:      0,
             count:    count,
             delay:    cfg.delay.into(),
             expiry:   expiry,
             envoy:    task.envoy,
             knocker:  knocker,
             resolver: task.resolver,
+            budget:   task.budget,
+            device:   task.source.device,
             active:   task.active,
-        }
+            followup: task.followup,
+        })
     }
 
-    pub async fn exec(self) -> Result<()> {
+    pub async fn exec(mut self) -> Result<()> {
         loop {
-            let Self { task, test, target, port, .. } = &self;
+            self.lag = self.schedule.tick().await;
+            self.run += 1;
+            self.run().await;
+            self.adapt().await;
+        }
+    }
 
-            let span = info_span!("knock", task, test);
+    pub async fn once(mut self) -> Result<()> {
+        self.run += 1;
+        self.run().await;
+        Ok(())
+    }
 
-            async {
-                let _guard = self.active.knock();
-                let result = self.knock(target, *port);
+    async fn adapt(&mut self) {
+        if let Some(switch) = self.schedule.adapt() {
+            debug!("{switch}");
+            self.envoy.export(record::Mode {
+                task:   self.task,
+                test:   self.test,
+                run:    self.run,
+                fast:   switch.fast,
+                cause:  switch.cause,
+                period: switch.period,
+            }).await;
+        }
+    }
 
-                match timeout(self.expiry.task, result).await {
-                    Ok(Ok(rtt)) => self.success(rtt).await,
-                    Ok(Err(e))  => self.failure(e).await,
-                    Err(_)      => self.timeout().await,
-                };
-            }.instrument(span).await;
+    async fn run(&self) {
+        let Self { task, test, target, port, .. } = self;
 
-            sleep(self.period).await;
-        }
+        let span = info_span!("knock", task, test);
+
+        async {
+            let _permit = self.budget.knock().await;
+            let _guard = self.active.knock();
+            let result = self.knock(target, *port);
+
+            match timeout(self.expiry.task, result).await {
+                Ok(Ok(outs)) => self.results(outs).await,
+                Ok(Err(e))   => self.failure(e).await,
+                Err(_)       => self.timeout().await,
+            };
+        }.instrument(span).await;
     }
 
-    async fn knock(&self, target: &str, port: u16) -> Result<Output> {
-        let addr = self.resolver.lookup(target, self.network).await?;
+    async fn knock(&self, target: &str, port: u16) -> Result<Vec<Result<Output>>> {
+        let addrs = self.resolver.select(target, self.network, self.policy).await?;
+        Ok(fanout(addrs, |addr| self.measure(target, addr, port)).await)
+    }
 
+    async fn measure(&self, target: &str, addr: IpAddr, port: u16) -> Result<Output> {
         debug!("target {target}:{port} ({addr})");
 
+        self.budget.packets(self.count).await;
+
         let rtt  = knock(self, addr, port).await?;
         let sent = rtt.len();
         let rtt  = rtt.into_iter().flatten().collect::<Vec<_>>();
@@ -94,8 +139,18 @@ impl Knock {
         })
     }
 
+    async fn results(&self, outs: Vec<Result<Output>>) {
+        for out in outs {
+            match out {
+                Ok(out) => self.success(out).await,
+                Err(e)  => self.failure(e).await,
+            }
+        }
+    }
+
     async fn success(&self, out: Output) {
         debug!("{out}");
+        self.schedule.success(out.rtt.avg, out.sent, out.lost);
         self.envoy.export(record::Knock {
             target: self.target.clone(),
             task:   self.task,
@@ -106,8 +161,15 @@ impl Knock {
             lost:   out.lost,
             rtt:    out.rtt,
             result: out.result,
+            device: self.device.clone(),
+            mark:   None,
+            lag:    self.lag,
+            maint:  self.schedule.maintenance(),
         }).await;
         self.active.success();
+        if let Some(followup) = &self.followup {
+            followup.success();
+        }
     }
 
     async fn failure(&self, err: Error) {
@@ -115,18 +177,32 @@ impl Knock {
         self.envoy.export(record::Error {
             task:  self.task,
             test:  self.test,
+            run:   self.run,
             cause: err.to_string(),
+            lag:   self.lag,
+            maint: self.schedule.maintenance(),
         }).await;
         self.active.failure();
+        self.schedule.failure();
+        if let Some(followup) = &self.followup {
+            followup.trigger(Trigger::Failure, self.run, &self.target);
+        }
     }
 
     async fn timeout(&self) {
         warn!("timeout");
         self.envoy.export(record::Timeout {
-            task: self.task,
-            test: self.test,
+            task:  self.task,
+            test:  self.test,
+            run:   self.run,
+            lag:   self.lag,
+            maint: self.schedule.maintenance(),
         }).await;
         self.active.timeout();
+        self.schedule.timeout();
+        if let Some(followup) = &self.followup {
+            followup.trigger(Trigger::Timeout, self.run, &self.target);
+        }
     }
 }
 
@@ -1,6 +1,11 @@
+pub use budget::{Budget, Limits};
 pub use expiry::Expiry;
+pub use followup::{Followup, Trigger};
+pub use schedule::Schedule;
 pub use task::Config;
+pub use task::Pin;
 pub use task::Task;
+pub use task::fanout;
 
 pub use fetch::{Fetch, Fetcher};
 pub use knock::Knock;
@@ -9,8 +14,13 @@ pub use query::Query;
 pub use shake::Shake;
 pub use trace::Trace;
 
+mod budget;
+mod cron;
 mod expiry;
+mod followup;
+mod schedule;
 mod task;
+mod window;
 
 mod fetch;
 mod knock;
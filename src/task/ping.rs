@@ -1,84 +1,180 @@
 use std::convert::TryFrom;
 use std::fmt;
-use std::net::IpAddr;
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use std::time::Duration;
-use anyhow::{Error, Result};
-use futures::TryStreamExt;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Error, Result};
+use futures::{StreamExt, TryStreamExt};
 use tracing::{debug, warn, info_span, Instrument};
+use tokio::io::Interest;
+use tokio::net::UdpSocket;
 use tokio::time::{sleep, timeout};
-use netdiag::{self, Pinger};
+use netdiag::{self, Bind, Knocker, Pinger};
 use synapi::tasks::PingConfig;
-use crate::export::{record, Envoy};
-use crate::net::{Network, Resolver};
+use crate::export::{record, record::Protocol, Envoy};
+use crate::net::{Network, Policy, Resolver, Source};
+use crate::net::echo::{Echo, Options};
 use crate::stats::{summarize, Summary};
 use crate::status::Active;
-use super::{Expiry, Task};
+use super::{fanout, Budget, Expiry, Followup, Schedule, Task, Trigger};
 
 pub struct Ping {
     task:     u64,
     test:     u64,
     network:  Network,
+    policy:   Policy,
     target:   Arc<String>,
-    period:   Duration,
+    schedule: Schedule,
+    lag:      Duration,
+    run:      u64,
     count:    usize,
     delay:    Duration,
     expiry:   Expiry,
+    options:  Options,
+    protocol: Protocol,
     envoy:    Envoy,
     pinger:   Arc<Pinger>,
+    knocker:  Arc<Knocker>,
     resolver: Resolver,
+    budget:   Arc<Budget>,
+    source:   Source,
+    bind:     Bind,
+    device:   Option<String>,
+    mark:     Option<u32>,
     active:   Arc<Active>,
+    followup: Option<Followup>,
 }
 
 impl Ping {
-    pub fn new(task: Task, cfg: PingConfig, pinger: Arc<Pinger>) -> Self {
+    pub fn new(
+        task:    Task,
+        cfg:     PingConfig,
+        bind:    &Bind,
+        pinger:  Arc<Pinger>,
+        knocker: Arc<Knocker>,
+    ) -> Result<Self> {
+        let PingConfig { protocol, port, .. } = &cfg;
+
+        let protocol = match (protocol.as_str(), *port) {
+            ("icmp",    _) | ("", _)    => Protocol::Icmp,
+            ("tcp-syn", p) if p > 0     => Protocol::Tcp(p),
+            ("udp",     p) if p > 0     => Protocol::Udp(p),
+            ("tcp-syn", _) | ("udp", _) => return Err(anyhow!("missing port")),
+            (other,     _)              => return Err(anyhow!("invalid protocol: {other}")),
+        };
+
         let count  = cfg.count.into();
         let expiry = Expiry::new(cfg.expiry.into(), count);
 
-        Self {
+        if let Protocol::Tcp(_) = protocol {
+            task.source.netdiag("tcp-syn ping")?;
+        }
+
+        let options = Options::new(cfg.size, cfg.dscp.into(), cfg.ttl, cfg.df)?;
+        let options = match protocol {
+            Protocol::Tcp(_) if !options.is_default() => {
+                return Err(anyhow!("size, dscp, ttl and df are not supported by tcp-syn ping"));
+            },
+            Protocol::Tcp(_)                          => options,
+            _ if options.dscp == 0                    => Options {
+                dscp: task.source.dscp.unwrap_or(0),
+                ..options
+            },
+            _                                         => options,
+        };
+
+        Ok(Self {
             task:     task.task,
             test:     task.test,
             network:  task.network,
+            policy:   task.policy(&cfg.policy)?,
             target:   Arc::new(cfg.target),
-            period:   cfg.period.into(),
+            schedule: Schedule::new(cfg.period.map(Duration::from), &task.schedule, task.fast.clone(), task.stop.clone())?,
+            lag:      Duration::ZERO,
+            run:      0,
             count:    count,
             delay:    cfg.delay.into(),
             expiry:   expiry,
+            options:  options,
+            protocol: protocol,
             envoy:    task.envoy,
             pinger:   pinger,
+            knocker:  knocker,
             resolver: task.resolver,
+            budget:   task.budget,
+            device:   task.source.device.clone(),
+            mark:     task.source.mark,
+            source:   task.source,
+            bind:     bind.clone(),
             active:   task.active,
-        }
+            followup: task.followup,
+        })
     }
 
-    pub async fn exec(self) -> Result<()> {
+    pub async fn exec(mut self) -> Result<()> {
         loop {
-            let task = self.task;
-            let test = self.test;
+            self.lag = self.schedule.tick().await;
+            self.run += 1;
+            self.run().await;
+            self.adapt().await;
+        }
+    }
+
+    pub async fn once(mut self) -> Result<()> {
+        self.run += 1;
+        self.run().await;
+        Ok(())
+    }
+
+    async fn adapt(&mut self) {
+        if let Some(switch) = self.schedule.adapt() {
+            debug!("{switch}");
+            self.envoy.export(record::Mode {
+                task:   self.task,
+                test:   self.test,
+                run:    self.run,
+                fast:   switch.fast,
+                cause:  switch.cause,
+                period: switch.period,
+            }).await;
+        }
+    }
 
-            let span = info_span!("ping", task, test);
+    async fn run(&self) {
+        let task = self.task;
+        let test = self.test;
 
-            async {
-                let _guard = self.active.ping();
-                let result = self.ping(&self.target);
+        let span = info_span!("ping", task, test);
 
-                match timeout(self.expiry.task, result).await {
-                    Ok(Ok(rtt)) => self.success(rtt).await,
-                    Ok(Err(e))  => self.failure(e).await,
-                    Err(_)      => self.timeout().await,
-                };
-            }.instrument(span).await;
+        async {
+            let _permit = self.budget.ping().await;
+            let _guard = self.active.ping();
+            let result = self.ping(&self.target);
 
-            sleep(self.period).await;
-        }
+            match timeout(self.expiry.task, result).await {
+                Ok(Ok(outs)) => self.results(outs).await,
+                Ok(Err(e))   => self.failure(e).await,
+                Err(_)       => self.timeout().await,
+            };
+        }.instrument(span).await;
+    }
+
+    async fn ping(&self, target: &str) -> Result<Vec<Result<Output>>> {
+        let addrs = self.resolver.select(target, self.network, self.policy).await?;
+        Ok(fanout(addrs, |addr| self.measure(target, addr)).await)
     }
 
-    async fn ping(&self, target: &str) -> Result<Output> {
-        let addr = self.resolver.lookup(target, self.network).await?;
+    async fn measure(&self, target: &str, addr: IpAddr) -> Result<Output> {
+        debug!("target {target} ({addr}) via {}", self.protocol);
 
-        debug!("target {target} ({addr})");
+        self.budget.packets(self.count).await;
 
-        let rtt  = ping(self, addr).await?;
+        let rtt  = match self.protocol {
+            Protocol::Icmp      => ping(self, addr).await?,
+            Protocol::Tcp(port) => knock(self, addr, port).await?,
+            Protocol::Udp(port) => probe(self, addr, port).await?,
+        };
 
         let sent = rtt.len();
         let rtt  = rtt.into_iter().flatten().collect::<Vec<_>>();
@@ -93,8 +189,18 @@ impl Ping {
         })
     }
 
+    async fn results(&self, outs: Vec<Result<Output>>) {
+        for out in outs {
+            match out {
+                Ok(out) => self.success(out).await,
+                Err(e)  => self.failure(e).await,
+            }
+        }
+    }
+
     async fn success(&self, out: Output) {
         debug!("{out}");
+        self.schedule.success(out.rtt.avg, out.sent, out.lost);
         self.envoy.export(record::Ping {
             task:   self.task,
             test:   self.test,
@@ -104,8 +210,20 @@ impl Ping {
             lost:   out.lost,
             rtt:    out.rtt,
             result: out.result,
+            size:   self.options.size.unwrap_or(0),
+            dscp:   self.options.dscp,
+            ttl:    self.options.ttl.unwrap_or(0),
+            df:     self.options.df,
+            proto:  self.protocol,
+            device: self.device.clone(),
+            mark:   self.mark,
+            lag:    self.lag,
+            maint:  self.schedule.maintenance(),
         }).await;
         self.active.success();
+        if let Some(followup) = &self.followup {
+            followup.success();
+        }
     }
 
     async fn failure(&self, err: Error) {
@@ -113,37 +231,128 @@ impl Ping {
         self.envoy.export(record::Error {
             task:  self.task,
             test:  self.test,
+            run:   self.run,
             cause: err.to_string(),
+            lag:   self.lag,
+            maint: self.schedule.maintenance(),
         }).await;
         self.active.failure();
+        self.schedule.failure();
+        if let Some(followup) = &self.followup {
+            followup.trigger(Trigger::Failure, self.run, &self.target);
+        }
     }
 
     async fn timeout(&self) {
         warn!("timeout");
         self.envoy.export(record::Timeout {
-            task: self.task,
-            test: self.test,
+            task:  self.task,
+            test:  self.test,
+            run:   self.run,
+            lag:   self.lag,
+            maint: self.schedule.maintenance(),
         }).await;
         self.active.timeout();
+        self.schedule.timeout();
+        if let Some(followup) = &self.followup {
+            followup.trigger(Trigger::Timeout, self.run, &self.target);
+        }
     }
 }
 
 async fn ping(ping: &Ping, addr: IpAddr) -> Result<Vec<Option<Duration>>> {
-    let pinger = &ping.pinger;
+    let count  = ping.count;
     let delay  = ping.delay;
+    let expiry = ping.expiry.probe;
+
+    let echo;
+    let stream = match ping.options.is_default() && ping.device.is_none() && ping.mark.is_none() {
+        true  => ping.pinger.ping(&netdiag::Ping { addr, count, expiry }).boxed(),
+        false => {
+            echo = Echo::new(&ping.source, &ping.bind, addr, &ping.options).await?;
+            echo.ping(count, expiry).boxed()
+        }
+    };
 
-    let ping = netdiag::Ping {
+    stream.and_then(|rtt| async move {
+        sleep(delay).await;
+        Ok(rtt)
+    }).try_collect().await
+}
+
+async fn knock(ping: &Ping, addr: IpAddr, port: u16) -> Result<Vec<Option<Duration>>> {
+    let knocker = &ping.knocker;
+    let delay   = ping.delay;
+
+    let knock = netdiag::Knock {
         addr:   addr,
+        port:   port,
         count:  ping.count,
         expiry: ping.expiry.probe,
     };
 
-    pinger.ping(&ping).and_then(|rtt| async move {
+    knocker.knock(&knock).await?.and_then(|rtt| async move {
         sleep(delay).await;
         Ok(rtt)
     }).try_collect().await
 }
 
+/// Each UDP probe is sent from a fresh connected socket. A datagram from
+/// the target, or an ICMP port unreachable reported as ECONNREFUSED, both
+/// count as a reply: either one shows the target is reachable.
+async fn probe(ping: &Ping, addr: IpAddr, port: u16) -> Result<Vec<Option<Duration>>> {
+    let addr    = SocketAddr::new(addr, port);
+    let payload = vec![0u8; ping.options.size.map(usize::from).unwrap_or(0)];
+    let expiry  = ping.expiry.probe;
+
+    let mut rtts = Vec::with_capacity(ping.count);
+
+    for _ in 0..ping.count {
+        let socket = ping.source.udp(&ping.bind, &addr)?;
+        ping.options.apply(&socket, &addr.ip())?;
+        socket.connect(addr).await?;
+
+        rtts.push(timeout(expiry, echo(&socket, &payload)).await.ok().flatten());
+
+        sleep(ping.delay).await;
+    }
+
+    Ok(rtts)
+}
+
+async fn echo(socket: &UdpSocket, payload: &[u8]) -> Option<Duration> {
+    let mut buf = [0u8; 512];
+
+    let sent = Instant::now();
+    socket.send(payload).await.ok()?;
+
+    loop {
+        let ready = socket.ready(Interest::READABLE | Interest::WRITABLE).await.ok()?;
+
+        if ready.is_write_closed() {
+            return match socket.take_error() {
+                Ok(Some(e)) if e.kind() == ErrorKind::ConnectionRefused => Some(sent.elapsed()),
+                Ok(Some(_)) | Err(_)                                    => None,
+                Ok(None)                                                => {
+                    socket.recv(&mut buf).await.ok()?;
+                    Some(sent.elapsed())
+                }
+            };
+        }
+
+        if ready.is_readable() {
+            match socket.try_recv(&mut buf) {
+                Ok(_)                                              => return Some(sent.elapsed()),
+                Err(e) if e.kind() == ErrorKind::ConnectionRefused => return Some(sent.elapsed()),
+                Err(e) if e.kind() == ErrorKind::WouldBlock        => continue,
+                Err(_)                                             => return None,
+            }
+        }
+
+        let _ = socket.try_io(Interest::WRITABLE, || Err::<(), _>(ErrorKind::WouldBlock.into()));
+    }
+}
+
 #[derive(Debug)]
 struct Output {
     addr:   IpAddr,
@@ -160,3 +369,45 @@ impl fmt::Display for Output {
         write!(f, "{}/{} min rtt {:.2?}, max {:.2?}, avg {:.2?}, jitter {:.2?}", good, sent, min, max, avg, jit)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::future::Future;
+    use tokio::runtime::Builder;
+    use super::*;
+
+    #[test]
+    fn ping_udp_echo() -> Result<()> {
+        test(async {
+            let server = UdpSocket::bind("127.0.0.1:0").await?;
+            let silent = UdpSocket::bind("127.0.0.1:0").await?;
+            let closed = UdpSocket::bind("127.0.0.1:0").await?.local_addr()?;
+
+            let open   = server.local_addr()?;
+            let silent = silent.local_addr()?;
+
+            tokio::spawn(async move {
+                let mut buf = [0u8; 64];
+                while let Ok((n, peer)) = server.recv_from(&mut buf).await {
+                    let _ = server.send_to(&buf[..n], peer).await;
+                }
+            });
+
+            for (addr, reply) in [(open, true), (closed, true), (silent, false)] {
+                for _ in 0..2 {
+                    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+                    socket.connect(addr).await?;
+                    let expiry = Duration::from_millis(250);
+                    let rtt    = timeout(expiry, echo(&socket, &[0u8; 8])).await.ok().flatten();
+                    assert_eq!(reply, rtt.is_some(), "{addr}");
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    fn test(future: impl Future<Output = Result<()>>) -> Result<()> {
+        Builder::new_current_thread().enable_all().build()?.block_on(future)
+    }
+}
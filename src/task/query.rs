@@ -5,80 +5,113 @@ use std::time::{Duration, Instant};
 use anyhow::{Error, Result};
 use tracing::{debug, info_span, warn, Instrument};
 use netdiag::Bind;
-use tokio::net::UdpSocket;
-use tokio::time::{sleep, timeout};
-use trust_dns_client::client::{AsyncClient, ClientHandle};
-use trust_dns_client::op::{DnsResponse, ResponseCode};
+use rand::random;
+use tokio::time::timeout;
+use trust_dns_client::op::{self, Message, MessageType, OpCode, ResponseCode};
 use trust_dns_client::rr::{DNSClass, Name, RecordType, RData};
-use trust_dns_client::udp::UdpClientStream;
 use synapi::tasks::QueryConfig;
 use crate::export::{record, Envoy};
+use crate::net::Source;
 use crate::status::Active;
-use super::Task;
+use super::{Budget, Followup, Schedule, Task, Trigger};
 
 pub struct Query {
-    task:   u64,
-    test:   u64,
-    target: Name,
-    period: Duration,
-    expiry: Duration,
-    record: RecordType,
-    envoy:  Envoy,
-    client: AsyncClient,
-    active: Arc<Active>,
+    task:     u64,
+    test:     u64,
+    target:   Name,
+    schedule: Schedule,
+    lag:      Duration,
+    run:      u64,
+    expiry:   Duration,
+    record:   RecordType,
+    envoy:    Envoy,
+    budget:   Arc<Budget>,
+    client:   Client,
+    active:   Arc<Active>,
+    followup: Option<Followup>,
+}
+
+struct Client {
+    server: SocketAddr,
+    bind:   Bind,
+    source: Source,
 }
 
 impl Query {
-    pub async fn new(task: Task, cfg: QueryConfig, bind: &Bind) -> Result<Self> {
-        let expiry = cfg.expiry.into();
+    pub fn new(task: Task, cfg: QueryConfig, bind: &Bind) -> Result<Self> {
         let server = SocketAddr::from((&cfg.server.parse()?, cfg.port));
 
-        let bind = Some(match server {
-            SocketAddr::V4(_) => bind.sa4(),
-            SocketAddr::V6(_) => bind.sa6(),
-        });
-
-        let stream = UdpClientStream::<UdpSocket>::with_bind_addr_and_timeout(server, bind, expiry);
-
-        let (client, bg) = AsyncClient::connect(stream).await?;
-        tokio::spawn(bg);
-
         Ok(Self {
-            task:   task.task,
-            test:   task.test,
-            target: cfg.target.parse()?,
-            period: cfg.period.into(),
-            expiry: expiry,
-            record: cfg.record.parse()?,
-            envoy:  task.envoy,
-            client: client,
-            active: task.active,
+            task:     task.task,
+            test:     task.test,
+            target:   cfg.target.parse()?,
+            schedule: Schedule::new(cfg.period.map(Duration::from), &task.schedule, task.fast.clone(), task.stop.clone())?,
+            lag:      Duration::ZERO,
+            run:      0,
+            expiry:   cfg.expiry.into(),
+            record:   cfg.record.parse()?,
+            envoy:    task.envoy,
+            budget:   task.budget,
+            client:   Client {
+                server: server,
+                bind:   bind.clone(),
+                source: task.source,
+            },
+            active:   task.active,
+            followup: task.followup,
         })
     }
 
     pub async fn exec(mut self) -> Result<()> {
         loop {
-            let task = self.task;
-            let test = self.test;
+            self.lag = self.schedule.tick().await;
+            self.run += 1;
+            self.run().await;
+            self.adapt().await;
+        }
+    }
 
-            let span = info_span!("query", task, test);
+    pub async fn once(mut self) -> Result<()> {
+        self.run += 1;
+        self.run().await;
+        Ok(())
+    }
 
-            async {
-                let expiry = self.expiry;
-                let result = self.query(self.target.clone());
+    async fn adapt(&mut self) {
+        if let Some(switch) = self.schedule.adapt() {
+            debug!("{switch}");
+            self.envoy.export(record::Mode {
+                task:   self.task,
+                test:   self.test,
+                run:    self.run,
+                fast:   switch.fast,
+                cause:  switch.cause,
+                period: switch.period,
+            }).await;
+        }
+    }
 
-                match timeout(expiry, result).await {
-                    Ok(Ok(out)) => self.success(out).await,
-                    Ok(Err(e))  => self.failure(e).await,
-                    Err(_)      => self.timeout().await,
-                };
-            }.instrument(span).await;
+    async fn run(&self) {
+        let task = self.task;
+        let test = self.test;
 
-            sleep(self.period).await;
-        }
+        let span = info_span!("query", task, test);
+
+        async {
+            let _permit = self.budget.query().await;
+
+            let expiry = self.expiry;
+            let result = self.query(self.target.clone());
+
+            match timeout(expiry, result).await {
+                Ok(Ok(out)) => self.success(out).await,
+                Ok(Err(e))  => self.failure(e).await,
+                Err(_)      => self.timeout().await,
+            };
+        }.instrument(span).await;
     }
 
-    async fn query(&mut self, target: Name) -> Result<Output> {
+    async fn query(&self, target: Name) -> Result<Output> {
         let _guard = self.active.query();
 
         debug!("target {target}");
@@ -95,6 +128,7 @@ impl Query {
 
     async fn success(&self, out: Output) {
         debug!("{out}");
+        self.schedule.success(out.time, 1, 0);
         self.envoy.export(record::Query {
             task:    self.task,
             test:    self.test,
@@ -102,8 +136,15 @@ impl Query {
             record:  out.record,
             answers: out.answers,
             time:    out.time,
+            device:  self.client.source.device.clone(),
+            mark:    self.client.source.mark,
+            lag:     self.lag,
+            maint:   self.schedule.maintenance(),
         }).await;
         self.active.success();
+        if let Some(followup) = &self.followup {
+            followup.success();
+        }
     }
 
     async fn failure(&self, err: Error) {
@@ -111,18 +152,59 @@ impl Query {
         self.envoy.export(record::Error {
             task:  self.task,
             test:  self.test,
+            run:   self.run,
             cause: err.to_string(),
+            lag:   self.lag,
+            maint: self.schedule.maintenance(),
         }).await;
         self.active.failure();
+        self.schedule.failure();
+        if let Some(followup) = &self.followup {
+            followup.trigger(Trigger::Failure, self.run, &self.target.to_string());
+        }
     }
 
     async fn timeout(&self) {
         warn!("timeout");
         self.envoy.export(record::Timeout {
-            task: self.task,
-            test: self.test,
+            task:  self.task,
+            test:  self.test,
+            run:   self.run,
+            lag:   self.lag,
+            maint: self.schedule.maintenance(),
         }).await;
         self.active.timeout();
+        self.schedule.timeout();
+        if let Some(followup) = &self.followup {
+            followup.trigger(Trigger::Timeout, self.run, &self.target.to_string());
+        }
+    }
+}
+
+impl Client {
+    async fn query(&self, name: Name, class: DNSClass, record: RecordType) -> Result<Message> {
+        let mut query = op::Query::query(name, record);
+        query.set_query_class(class);
+
+        let mut msg = Message::new();
+        msg.set_id(random());
+        msg.set_message_type(MessageType::Query);
+        msg.set_op_code(OpCode::Query);
+        msg.set_recursion_desired(true);
+        msg.add_query(query);
+
+        let socket = self.source.udp(&self.bind, &self.server)?;
+        socket.connect(self.server).await?;
+        socket.send(&msg.to_vec()?).await?;
+
+        let mut buf = [0u8; 4096];
+        loop {
+            let n   = socket.recv(&mut buf).await?;
+            let res = Message::from_vec(&buf[..n])?;
+            if res.id() == msg.id() {
+                return Ok(res);
+            }
+        }
     }
 }
 
@@ -135,7 +217,7 @@ struct Output {
 }
 
 impl Output {
-    fn new(record: RecordType, time: Duration, res: DnsResponse) -> Result<Self> {
+    fn new(record: RecordType, time: Duration, res: Message) -> Result<Self> {
         let mut answers = res.answers().iter().map(|rec| {
             match rec.data() {
                 Some(RData::A(addr))     => addr.to_string(),
@@ -0,0 +1,361 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use chrono_tz::Tz;
+use futures::future::pending;
+use parking_lot::Mutex;
+use rand::{thread_rng, Rng};
+use tokio::time::{sleep_until, Instant};
+use tracing::debug;
+use synapi::tasks::{AdaptiveConfig, ScheduleConfig};
+use super::cron::Cron;
+use super::window::{zone, Action, Window};
+
+#[derive(Debug)]
+pub struct Schedule {
+    timer:   Timer,
+    next:    Instant,
+    adapt:   Option<Adapt>,
+    windows: Vec<Window>,
+    stop:    Arc<AtomicBool>,
+}
+
+#[derive(Debug)]
+enum Timer {
+    Period(Duration),
+    Cron(Cron, Tz),
+}
+
+#[derive(Debug)]
+pub struct Switch {
+    pub fast:   bool,
+    pub cause:  &'static str,
+    pub period: Duration,
+}
+
+#[derive(Debug)]
+struct Adapt {
+    period:  Duration,
+    after:   u32,
+    recover: u32,
+    rtt:     Option<Duration>,
+    loss:    Option<u8>,
+    fast:    Arc<AtomicBool>,
+    health:  Mutex<Health>,
+    streak:  u32,
+}
+
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum Health {
+    Healthy,
+    Breach,
+    Timeout,
+    Failure,
+}
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+impl Schedule {
+    pub fn new(period: Option<Duration>, cfg: &ScheduleConfig, fast: Arc<AtomicBool>, stop: Arc<AtomicBool>) -> Result<Self> {
+        let timer = match (&cfg.cron, period) {
+            (Some(cron), _)      => Timer::Cron(cron.parse()?, zone(cfg.timezone.as_deref())?),
+            (None, Some(period)) => Timer::Period(period),
+            (None, None)         => return Err(anyhow!("missing period or cron schedule")),
+        };
+
+        let jitter = Duration::from(cfg.jitter);
+
+        let start = match &timer {
+            Timer::Period(period) if cfg.align => align(SystemTime::now(), *period),
+            Timer::Period(_)                   => Duration::ZERO,
+            Timer::Cron(cron, zone)            => until(cron, zone).ok_or_else(|| {
+                anyhow!("cron schedule never fires")
+            })?,
+        };
+
+        let offset = match jitter.is_zero() {
+            true  => Duration::ZERO,
+            false => thread_rng().gen_range(Duration::ZERO..jitter),
+        };
+
+        let windows = cfg.maintenance.iter().map(Window::new).collect::<Result<_>>()?;
+
+        Ok(Self {
+            timer:   timer,
+            next:    Instant::now() + start + offset,
+            adapt:   cfg.adaptive.as_ref().map(|cfg| Adapt::new(cfg, fast)),
+            windows: windows,
+            stop:    stop,
+        })
+    }
+
+    pub async fn tick(&mut self) -> Duration {
+        loop {
+            sleep_until(self.next).await;
+
+            if self.stop.load(Ordering::Relaxed) {
+                pending::<()>().await;
+            }
+
+            let lag = self.advance(Instant::now());
+
+            if !self.within(Action::Skip) {
+                return lag;
+            }
+
+            debug!("skipping run in maintenance window");
+        }
+    }
+
+    pub fn maintenance(&self) -> bool {
+        self.within(Action::Tag)
+    }
+
+    pub fn success(&self, rtt: Duration, sent: u32, lost: u32) {
+        if let Some(adapt) = &self.adapt {
+            adapt.observe(adapt.check(rtt, sent, lost));
+        }
+    }
+
+    pub fn failure(&self) {
+        if let Some(adapt) = &self.adapt {
+            adapt.observe(Health::Failure);
+        }
+    }
+
+    pub fn timeout(&self) {
+        if let Some(adapt) = &self.adapt {
+            adapt.observe(Health::Timeout);
+        }
+    }
+
+    pub fn adapt(&mut self) -> Option<Switch> {
+        let adapt  = self.adapt.as_mut()?;
+        let health = mem::replace(adapt.health.get_mut(), Health::Healthy);
+
+        if !adapt.update(health) {
+            return None;
+        }
+
+        let fast   = adapt.is_fast();
+        let period = self.period().unwrap_or_default();
+
+        if fast {
+            self.next = self.next.min(Instant::now() + period);
+        }
+
+        Some(Switch { fast, cause: health.name(), period })
+    }
+
+    fn period(&self) -> Option<Duration> {
+        match (&self.adapt, &self.timer) {
+            (Some(adapt), _) if adapt.is_fast() => Some(adapt.period),
+            (_, Timer::Period(period))          => Some(*period),
+            (_, Timer::Cron(..))                => None,
+        }
+    }
+
+    fn delay(&self) -> Option<Duration> {
+        match &self.timer {
+            Timer::Cron(cron, zone) => until(cron, zone),
+            Timer::Period(_)        => None,
+        }
+    }
+
+    fn within(&self, action: Action) -> bool {
+        let now = Utc::now();
+        self.windows.iter().any(|w| w.action() == action && w.contains(&now))
+    }
+
+    fn advance(&mut self, now: Instant) -> Duration {
+        let lag = now.saturating_duration_since(self.next);
+
+        let period = match self.period() {
+            Some(period) => period,
+            None         => {
+                self.next = now + self.delay().unwrap_or(DAY);
+                return lag;
+            }
+        };
+
+        self.next += period;
+        if self.next <= now {
+            let missed = lag.as_nanos().checked_div(period.as_nanos()).unwrap_or(0);
+            let missed = u32::try_from(missed).unwrap_or(u32::MAX);
+            self.next += period * missed;
+        }
+
+        lag
+    }
+}
+
+impl Adapt {
+    fn new(cfg: &AdaptiveConfig, fast: Arc<AtomicBool>) -> Self {
+        Self {
+            period:  cfg.period.into(),
+            after:   cfg.after.unwrap_or(3).max(1),
+            recover: cfg.recover.unwrap_or(3).max(1),
+            rtt:     cfg.rtt.map(Duration::from),
+            loss:    cfg.loss,
+            fast:    fast,
+            health:  Mutex::new(Health::Healthy),
+            streak:  0,
+        }
+    }
+
+    fn is_fast(&self) -> bool {
+        self.fast.load(Ordering::Relaxed)
+    }
+
+    fn check(&self, rtt: Duration, sent: u32, lost: u32) -> Health {
+        let slow  = self.rtt.is_some_and(|limit| rtt > limit);
+        let lossy = self.loss.is_some_and(|limit| {
+            u64::from(lost) * 100 > u64::from(limit) * u64::from(sent)
+        });
+
+        match slow || lossy {
+            true  => Health::Breach,
+            false => Health::Healthy,
+        }
+    }
+
+    fn observe(&self, health: Health) {
+        let mut worst = self.health.lock();
+        *worst = health.max(*worst);
+    }
+
+    fn update(&mut self, health: Health) -> bool {
+        let fast = self.is_fast();
+
+        let progress = match fast {
+            true  => health == Health::Healthy,
+            false => health != Health::Healthy,
+        };
+
+        self.streak = match progress {
+            true  => self.streak + 1,
+            false => 0,
+        };
+
+        let limit = match fast {
+            true  => self.recover,
+            false => self.after,
+        };
+
+        if self.streak < limit {
+            return false;
+        }
+
+        self.streak = 0;
+        self.fast.store(!fast, Ordering::Relaxed);
+
+        true
+    }
+}
+
+impl fmt::Display for Switch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mode = if self.fast { "fast" } else { "normal" };
+        write!(f, "{} period {:?} after {}", mode, self.period, self.cause)
+    }
+}
+
+impl Health {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Healthy => "recovery",
+            Self::Breach  => "breach",
+            Self::Timeout => "timeout",
+            Self::Failure => "failure",
+        }
+    }
+}
+
+fn until(cron: &Cron, zone: &Tz) -> Option<Duration> {
+    let now  = Utc::now().with_timezone(zone);
+    let next = cron.next(&now)?;
+    (next - now).to_std().ok()
+}
+
+fn align(now: SystemTime, period: Duration) -> Duration {
+    let since = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    match since.as_nanos().checked_rem(period.as_nanos()) {
+        Some(0) | None => Duration::ZERO,
+        Some(rem)      => period - Duration::from_nanos(u64::try_from(rem).unwrap_or(0)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn schedule_align() {
+        let secs = |n| UNIX_EPOCH + Duration::from_secs(n);
+        let period = Duration::from_secs(60);
+
+        assert_eq!(Duration::ZERO,          align(secs(120), period));
+        assert_eq!(Duration::from_secs(50), align(secs(130), period));
+        assert_eq!(Duration::from_secs(1),  align(secs(179), period));
+        assert_eq!(Duration::ZERO,          align(secs(179), Duration::ZERO));
+    }
+
+    #[test]
+    fn schedule_advance() {
+        let period = Duration::from_secs(10);
+        let start  = Instant::now();
+        let mut schedule = Schedule {
+            timer:   Timer::Period(period),
+            next:    start,
+            adapt:   None,
+            windows: Vec::new(),
+            stop:    Arc::new(AtomicBool::new(false)),
+        };
+
+        assert_eq!(Duration::ZERO, schedule.advance(start));
+        assert_eq!(start + period, schedule.next);
+
+        let late = start + period + Duration::from_secs(3);
+        assert_eq!(Duration::from_secs(3), schedule.advance(late));
+        assert_eq!(start + period * 2, schedule.next);
+
+        let late = start + period * 5 + Duration::from_secs(1);
+        assert_eq!(period * 3 + Duration::from_secs(1), schedule.advance(late));
+        assert_eq!(start + period * 6, schedule.next);
+    }
+
+    #[test]
+    fn schedule_adapt() {
+        let fast = Arc::new(AtomicBool::new(false));
+        let mut adapt = Adapt::new(&AdaptiveConfig {
+            period:  Duration::from_secs(5).into(),
+            after:   Some(2),
+            recover: Some(3),
+            rtt:     Some(Duration::from_millis(100).into()),
+            loss:    Some(10),
+        }, fast.clone());
+
+        assert_eq!(Health::Healthy, adapt.check(Duration::from_millis(50), 10, 1));
+        assert_eq!(Health::Breach,  adapt.check(Duration::from_millis(150), 10, 0));
+        assert_eq!(Health::Breach,  adapt.check(Duration::from_millis(50), 10, 2));
+
+        assert!(!adapt.update(Health::Failure));
+        assert!(!adapt.update(Health::Healthy));
+        assert!(!adapt.update(Health::Timeout));
+        assert!(adapt.update(Health::Breach));
+        assert!(fast.load(Ordering::Relaxed));
+
+        assert!(!adapt.update(Health::Healthy));
+        assert!(!adapt.update(Health::Healthy));
+        assert!(!adapt.update(Health::Failure));
+        assert!(!adapt.update(Health::Healthy));
+        assert!(!adapt.update(Health::Healthy));
+        assert!(adapt.update(Health::Healthy));
+        assert!(!fast.load(Ordering::Relaxed));
+    }
+}
@@ -6,90 +6,152 @@ use std::time::{Duration, Instant};
 use anyhow::{Error, Result};
 use tracing::{debug, info_span, warn, Instrument};
 use rustls::ServerName;
-use tokio::time::{sleep, timeout};
+use tokio::time::timeout;
 use synapi::tasks::ShakeConfig;
 use crate::export::{record, Envoy};
-use crate::net::{Network, Resolver};
+use crate::net::{Network, Policy, Resolver, Source};
 use crate::net::tls::{Identity, Shaker};
 use crate::status::Active;
-use super::Task;
+use super::{fanout, Budget, Followup, Pin, Schedule, Task, Trigger};
 
 pub struct Shake {
     task:     u64,
     test:     u64,
     target:   Arc<String>,
     network:  Network,
+    policy:   Policy,
     port:     u16,
-    period:   Duration,
+    pin:      Pin,
+    sni:      Option<String>,
+    schedule: Schedule,
+    lag:      Duration,
+    run:      u64,
     expiry:   Duration,
     envoy:    Envoy,
     shaker:   Arc<Shaker>,
     resolver: Resolver,
+    budget:   Arc<Budget>,
+    source:   Source,
     active:   Arc<Active>,
+    followup: Option<Followup>,
 }
 
 impl Shake {
-    pub fn new(task: Task, cfg: ShakeConfig, shaker: Arc<Shaker>) -> Self {
-        Self {
+    pub fn new(task: Task, cfg: ShakeConfig, shaker: Arc<Shaker>) -> Result<Self> {
+        Ok(Self {
             task:     task.task,
             test:     task.test,
             network:  task.network,
+            policy:   task.policy(&cfg.policy)?,
             target:   Arc::new(cfg.target),
             port:     cfg.port,
-            period:   cfg.period.into(),
+            pin:      Pin::new(cfg.address, cfg.resolve),
+            sni:      cfg.sni,
+            schedule: Schedule::new(cfg.period.map(Duration::from), &task.schedule, task.fast.clone(), task.stop.clone())?,
+            lag:      Duration::ZERO,
+            run:      0,
             expiry:   cfg.expiry.into(),
             envoy:    task.envoy,
             shaker:   shaker,
             resolver: task.resolver,
+            budget:   task.budget,
+            source:   task.source,
             active:   task.active,
-        }
+            followup: task.followup,
+        })
     }
 
-    pub async fn exec(self) -> Result<()> {
+    pub async fn exec(mut self) -> Result<()> {
         loop {
-            let task = self.task;
-            let test = self.test;
+            self.lag = self.schedule.tick().await;
+            self.run += 1;
+            self.run().await?;
+            self.adapt().await;
+        }
+    }
+
+    pub async fn once(mut self) -> Result<()> {
+        self.run += 1;
+        self.run().await
+    }
 
-            let span = info_span!("shake", task, test);
+    async fn adapt(&mut self) {
+        if let Some(switch) = self.schedule.adapt() {
+            debug!("{switch}");
+            self.envoy.export(record::Mode {
+                task:   self.task,
+                test:   self.test,
+                run:    self.run,
+                fast:   switch.fast,
+                cause:  switch.cause,
+                period: switch.period,
+            }).await;
+        }
+    }
 
-            async {
-                let _guard = self.active.shake();
-                let result = self.shake(&self.target);
+    async fn run(&self) -> Result<()> {
+        let task = self.task;
+        let test = self.test;
 
-                match timeout(self.expiry, result).await {
-                    Ok(Ok(out)) => self.success(out).await?,
-                    Ok(Err(e))  => self.failure(e).await,
-                    Err(_)      => self.timeout().await,
-                }
+        let span = info_span!("shake", task, test);
 
-                Result::<_, Error>::Ok(())
-            }.instrument(span).await?;
+        async {
+            let _permit = self.budget.shake().await;
+            let _guard = self.active.shake();
+            let result = self.shake(&self.target);
 
-            sleep(self.period).await;
-        }
+            match timeout(self.expiry, result).await {
+                Ok(Ok(outs)) => self.results(outs).await?,
+                Ok(Err(e))   => self.failure(e).await,
+                Err(_)       => self.timeout().await,
+            }
+
+            Result::<_, Error>::Ok(())
+        }.instrument(span).await
     }
 
-    async fn shake(&self, target: &str) -> Result<Output> {
+    async fn shake(&self, target: &str) -> Result<Vec<Result<Output>>> {
         let time = Instant::now();
-        let addr = self.resolver.lookup(target, self.network).await?;
 
+        if let Some(addr) = self.pin.get(target) {
+            return Ok(vec![self.measure(target, addr, time, true).await]);
+        }
+
+        let addrs = self.resolver.select(target, self.network, self.policy).await?;
+        Ok(fanout(addrs, |addr| self.measure(target, addr, time, false)).await)
+    }
+
+    async fn measure(&self, target: &str, addr: IpAddr, time: Instant, pinned: bool) -> Result<Output> {
         debug!("target {target} ({addr})");
 
-        let name = ServerName::try_from(self.target.as_str())?;
+        let name = self.sni.as_deref().unwrap_or(target);
+        let name = ServerName::try_from(name)?;
         let addr = SocketAddr::new(addr, self.port);
 
-        let c = self.shaker.shake(&name, addr).await?;
+        let c = self.shaker.shake(&name, addr, &self.source).await?;
 
         Ok(Output {
             addr:   addr.ip(),
             port:   addr.port(),
             server: c.server,
             time:   time.elapsed(),
+            pinned: pinned,
         })
     }
 
+    async fn results(&self, outs: Vec<Result<Output>>) -> Result<()> {
+        for out in outs {
+            match out {
+                Ok(out) => self.success(out).await?,
+                Err(e)  => self.failure(e).await,
+            }
+        }
+        Ok(())
+    }
+
     async fn success(&self, out: Output) -> Result<()> {
         debug!("{out}");
+        self.schedule.success(out.time, 1, 0);
 
         self.envoy.export(record::Shake {
             task:   self.task,
@@ -99,9 +161,18 @@ impl Shake {
             port:   out.port,
             server: out.server,
             time:   out.time,
+            pinned: out.pinned,
+            sni:    self.sni.clone(),
+            device: self.source.device.clone(),
+            mark:   self.source.mark,
+            lag:    self.lag,
+            maint:  self.schedule.maintenance(),
         }).await;
 
         self.active.success();
+        if let Some(followup) = &self.followup {
+            followup.success();
+        }
 
         Ok(())
     }
@@ -111,18 +182,32 @@ impl Shake {
         self.envoy.export(record::Error {
             task:  self.task,
             test:  self.test,
+            run:   self.run,
             cause: err.to_string(),
+            lag:   self.lag,
+            maint: self.schedule.maintenance(),
         }).await;
         self.active.failure();
+        self.schedule.failure();
+        if let Some(followup) = &self.followup {
+            followup.trigger(Trigger::Failure, self.run, &self.target);
+        }
     }
 
     async fn timeout(&self) {
         warn!("timeout");
         self.envoy.export(record::Timeout {
-            task: self.task,
-            test: self.test,
+            task:  self.task,
+            test:  self.test,
+            run:   self.run,
+            lag:   self.lag,
+            maint: self.schedule.maintenance(),
         }).await;
         self.active.timeout();
+        self.schedule.timeout();
+        if let Some(followup) = &self.followup {
+            followup.trigger(Trigger::Timeout, self.run, &self.target);
+        }
     }
 }
 
@@ -132,6 +217,7 @@ struct Output {
     port:   u16,
     server: Identity,
     time:   Duration,
+    pinned: bool,
 }
 
 impl fmt::Display for Output {
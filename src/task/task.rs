@@ -1,17 +1,38 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::net::IpAddr;
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use anyhow::{anyhow, Result};
+use futures::future::join_all;
 use rustls::RootCertStore;
 use netdiag::Bind;
+use synapi::tasks::ScheduleConfig;
 use crate::export::Envoy;
-use crate::net::{Network, Resolver};
+use crate::net::{Network, Policy, Resolver, Source};
 use crate::status::Active;
+use super::{Budget, Followup};
 
 pub struct Task {
     pub task:     u64,
     pub test:     u64,
     pub active:   Arc<Active>,
     pub network:  Network,
+    pub compare:  bool,
     pub envoy:    Envoy,
     pub resolver: Resolver,
+    pub budget:   Arc<Budget>,
+    pub source:   Source,
+    pub schedule: ScheduleConfig,
+    pub followup: Option<Followup>,
+    pub fast:     Arc<AtomicBool>,
+    pub stop:     Arc<AtomicBool>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Pin {
+    address: Option<IpAddr>,
+    resolve: BTreeMap<String, IpAddr>,
 }
 
 #[derive(Clone)]
@@ -23,14 +44,57 @@ pub struct Config {
 }
 
 impl Task {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         active:   Arc<Active>,
         task:     u64,
         test:     u64,
         network:  Network,
+        compare:  bool,
         envoy:    Envoy,
         resolver: Resolver,
+        budget:   Arc<Budget>,
     ) -> Self {
-        Self { active, task, test, network, envoy, resolver }
+        let source   = Source::default();
+        let schedule = ScheduleConfig::default();
+        let followup = None;
+        let fast     = Arc::new(AtomicBool::new(false));
+        let stop     = Arc::new(AtomicBool::new(false));
+        Self { active, task, test, network, compare, envoy, resolver, budget, source, schedule, followup, fast, stop }
+    }
+
+    pub fn policy(&self, policy: &str) -> Result<Policy> {
+        match self.compare {
+            true  => Ok(Policy::Both),
+            false => policy.parse(),
+        }
+    }
+}
+
+impl Pin {
+    pub fn new(address: Option<IpAddr>, resolve: BTreeMap<String, IpAddr>) -> Self {
+        Self { address, resolve }
+    }
+
+    pub fn get(&self, host: &str) -> Option<IpAddr> {
+        self.address.or_else(|| self.resolve.get(host).copied())
     }
 }
+
+pub async fn fanout<F, T, R>(addrs: Vec<IpAddr>, probe: F) -> Vec<Result<R>>
+where
+    F: Fn(IpAddr) -> T,
+    T: Future<Output = Result<R>>,
+{
+    let many = addrs.len() > 1;
+
+    join_all(addrs.into_iter().map(|addr| {
+        let result = probe(addr);
+        async move {
+            result.await.map_err(|e| match many {
+                true  => anyhow!("{}: {}", addr, e),
+                false => e,
+            })
+        }
+    })).await
+}
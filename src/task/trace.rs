@@ -1,19 +1,23 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert::TryFrom;
 use std::fmt;
 use std::net::IpAddr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
+use futures::future::join_all;
 use futures::stream::{StreamExt, TryStreamExt};
 use tracing::{debug, info_span, warn, Instrument};
 use tokio::time::{sleep, timeout};
 use netdiag::{self, Node, Protocol, Tracer};
+use netdiag::trace::Probe;
 use synapi::tasks::TraceConfig;
-use crate::export::{record, Hop, Envoy};
-use crate::net::{Network, Resolver};
+use crate::export::{record, Envoy, Graph, Hop};
+use crate::export::record::{Edge, Vertex};
+use crate::net::{Network, Origins, Policy, Resolver, Source};
+use crate::net::mpls::{Label, Listener};
 use crate::status::Active;
-use super::{Expiry, Task};
+use super::{fanout, Budget, Expiry, Followup, Schedule, Task, Trigger};
 
 pub struct Trace {
     task:     u64,
@@ -21,19 +25,36 @@ pub struct Trace {
     protocol: Protocol,
     target:   Arc<String>,
     network:  Network,
-    period:   Duration,
+    policy:   Policy,
+    schedule: Schedule,
+    lag:      Duration,
+    run:      u64,
     count:    usize,
     limit:    usize,
+    flows:    usize,
+    names:    bool,
     delay:    Duration,
     expiry:   Expiry,
     envoy:    Envoy,
     tracer:   Arc<Tracer>,
+    origins:  Arc<Origins>,
     resolver: Resolver,
+    reverse:  Resolver,
+    budget:   Arc<Budget>,
+    source:   Source,
+    device:   Option<String>,
     active:   Arc<Active>,
+    followup: Option<Followup>,
 }
 
 impl Trace {
-    pub fn new(task: Task, cfg: TraceConfig, tracer: Arc<Tracer>) -> Self {
+    pub fn new(
+        task:    Task,
+        cfg:     TraceConfig,
+        tracer:  Arc<Tracer>,
+        origins: Arc<Origins>,
+        reverse: Resolver,
+    ) -> Result<Self> {
         let TraceConfig { protocol, port, .. } = cfg;
 
         let protocol = match &*protocol {
@@ -45,82 +66,192 @@ impl Trace {
 
         let count  = usize::from(cfg.count);
         let limit  = usize::from(cfg.limit);
-        let expiry = Expiry::new(cfg.expiry.into(), count * limit);
+        let flows  = usize::from(cfg.flows);
 
-        Self {
+        if flows == 0 {
+            return Err(anyhow!("invalid flows: {flows}"));
+        }
+
+        task.source.netdiag("trace")?;
+
+        let mut expiry = Expiry::new(cfg.expiry.into(), count * limit);
+        expiry.task *= u32::try_from(flows)?;
+
+        Ok(Self {
             task:     task.task,
             test:     task.test,
             network:  task.network,
+            policy:   task.policy(&cfg.policy)?,
             protocol: protocol,
             target:   Arc::new(cfg.target),
-            period:   cfg.period.into(),
+            schedule: Schedule::new(cfg.period.map(Duration::from), &task.schedule, task.fast.clone(), task.stop.clone())?,
+            lag:      Duration::ZERO,
+            run:      0,
             count:    count,
             limit:    limit,
+            flows:    flows,
+            names:    cfg.names,
             delay:    cfg.delay.into(),
             expiry:   expiry,
             envoy:    task.envoy,
             tracer:   tracer,
+            origins:  origins,
             resolver: task.resolver,
+            reverse:  reverse,
+            budget:   task.budget,
+            device:   task.source.device.clone(),
+            source:   task.source,
             active:   task.active,
-        }
+            followup: task.followup,
+        })
     }
 
-    pub async fn exec(self) -> Result<()> {
+    pub async fn exec(mut self) -> Result<()> {
         loop {
-            let task = self.task;
-            let test = self.test;
+            self.lag = self.schedule.tick().await;
+            self.run += 1;
+            self.run().await?;
+            self.adapt().await;
+        }
+    }
 
-            let span = info_span!("trace", task, test);
+    pub async fn once(mut self) -> Result<()> {
+        self.run += 1;
+        self.run().await
+    }
 
-            async {
-                let _guard = self.active.trace();
-                let result = self.trace(&self.target);
+    async fn adapt(&mut self) {
+        if let Some(switch) = self.schedule.adapt() {
+            debug!("{switch}");
+            self.envoy.export(record::Mode {
+                task:   self.task,
+                test:   self.test,
+                run:    self.run,
+                fast:   switch.fast,
+                cause:  switch.cause,
+                period: switch.period,
+            }).await;
+        }
+    }
 
-                match timeout(self.expiry.task, result).await {
-                    Ok(Ok(stats)) => self.success(stats).await?,
-                    Ok(Err(e))    => self.failure(e).await,
-                    Err(_)        => self.timeout().await,
-                }
+    async fn run(&self) -> Result<()> {
+        let task = self.task;
+        let test = self.test;
 
-                Result::<_, Error>::Ok(())
-            }.instrument(span).await?;
+        let span = info_span!("trace", task, test);
 
-            sleep(self.period).await;
-        }
+        async {
+            let _permit = self.budget.trace().await;
+            let _guard = self.active.trace();
+            let result = self.trace(&self.target);
+            let expiry = Instant::now() + self.expiry.task;
+
+            match timeout(self.expiry.task, result).await {
+                Ok(Ok(outs)) => self.results(outs, expiry).await?,
+                Ok(Err(e))   => self.failure(e).await,
+                Err(_)       => self.timeout().await,
+            }
+
+            Result::<_, Error>::Ok(())
+        }.instrument(span).await
     }
 
-    async fn trace(&self, target: &str) -> Result<Output> {
+    async fn trace(&self, target: &str) -> Result<Vec<Result<Output>>> {
         let time  = Instant::now();
-        let addr  = self.resolver.lookup(target, self.network).await?;
+        let addrs = self.resolver.select(target, self.network, self.policy).await?;
+        Ok(fanout(addrs, |addr| self.measure(target, addr, time)).await)
+    }
 
+    async fn measure(&self, target: &str, addr: IpAddr, time: Instant) -> Result<Output> {
         debug!("target {target} ({addr})");
 
-        let route = trace(self, addr).await?;
+        let (route, labels) = trace(self, addr).await?;
 
         Ok(Output {
-            addr:  addr,
-            route: route,
-            time:  time.elapsed(),
+            addr:   addr,
+            route:  route,
+            labels: labels,
+            time:   time.elapsed(),
         })
     }
 
-    async fn success(&self, out: Output) -> Result<()> {
+    async fn results(&self, outs: Vec<Result<Output>>, expiry: Instant) -> Result<()> {
+        for out in outs {
+            match out {
+                Ok(out) => self.success(out, expiry).await?,
+                Err(e)  => self.failure(e).await,
+            }
+        }
+        Ok(())
+    }
+
+    async fn success(&self, out: Output, expiry: Instant) -> Result<()> {
         debug!("{out}");
+        self.schedule.success(out.time, 1, 0);
+
+        let origins = &self.origins;
+
+        let mut hops = Vec::<Hop>::new();
+
+        for route in &out.route {
+            for (hop, nodes) in route.iter().enumerate() {
+                if hops.len() <= hop {
+                    hops.push(Hop {
+                        hop:    hop + 1,
+                        nodes:  HashMap::new(),
+                        names:  HashMap::new(),
+                        labels: HashMap::new(),
+                        origin: HashMap::new(),
+                    });
+                }
 
-        let hops = out.route.into_iter().enumerate().map(|(hop, nodes)| {
-            let mut map = HashMap::<IpAddr, Vec<u64>>::new();
+                for node in nodes {
+                    if let Node::Node(_, addr, rtt, _) = node {
+                        let rtt = rtt.as_micros() as u64;
+                        hops[hop].nodes.entry(*addr).or_default().push(rtt);
 
-            for node in nodes {
-                if let Node::Node(_, addr, rtt, _) = node {
-                    let rtt = rtt.as_micros() as u64;
-                    map.entry(addr).or_default().push(rtt);
+                        if let Some(labels) = out.labels.get(addr) {
+                            hops[hop].labels.insert(*addr, labels.clone());
+                        }
+                    }
                 }
             }
+        }
 
-            Hop { hop: hop + 1, nodes: map }
-        }).collect::<Vec<_>>();
+        if !origins.is_empty() {
+            for hop in &mut hops {
+                for addr in hop.nodes.keys() {
+                    if let Some(origin) = origins.lookup(*addr) {
+                        hop.origin.insert(*addr, origin);
+                    }
+                }
+            }
+        }
+
+        let names = match self.names {
+            true  => self.names(&hops, expiry).await,
+            false => HashMap::new(),
+        };
+
+        for hop in &mut hops {
+            for addr in hop.nodes.keys() {
+                if let Some(name) = names.get(addr) {
+                    hop.names.insert(*addr, name.clone());
+                }
+            }
+        }
+
+        let path = aspath(&out.route, origins);
 
-        let route = serde_json::to_string(&hops)?;
+        let graph = match self.flows {
+            1 => None,
+            _ => Some(graph(&out.route, origins, &names, &out.labels)),
+        };
+
+        let route = match &graph {
+            Some(graph) => serde_json::to_string(graph)?,
+            None        => serde_json::to_string(&hops)?,
+        };
 
         self.envoy.export(record::Trace {
             task:   self.task,
@@ -128,50 +259,103 @@ impl Trace {
             target: self.target.clone(),
             addr:   out.addr,
             hops:   hops,
+            graph:  graph,
+            path:   path,
             route:  route,
             time:   out.time,
+            device: self.device.clone(),
+            mark:   None,
+            lag:    self.lag,
+            maint:  self.schedule.maintenance(),
         }).await;
 
         self.active.success();
+        if let Some(followup) = &self.followup {
+            followup.success();
+        }
 
         Ok(())
     }
 
+    async fn names(&self, hops: &[Hop], expiry: Instant) -> HashMap<IpAddr, String> {
+        let addrs  = hops.iter().flat_map(|hop| hop.nodes.keys()).collect::<BTreeSet<_>>();
+        let expiry = expiry.saturating_duration_since(Instant::now());
+
+        join_all(addrs.into_iter().map(|addr| async move {
+            let name = self.reverse.reverse(*addr, expiry).await?;
+            Some((*addr, name))
+        })).await.into_iter().flatten().collect()
+    }
+
     async fn failure(&self, err: Error) {
         warn!(error = &*err.to_string());
         self.envoy.export(record::Error {
             task:  self.task,
             test:  self.test,
+            run:   self.run,
             cause: err.to_string(),
+            lag:   self.lag,
+            maint: self.schedule.maintenance(),
         }).await;
         self.active.failure();
+        self.schedule.failure();
+        if let Some(followup) = &self.followup {
+            followup.trigger(Trigger::Failure, self.run, &self.target);
+        }
     }
 
     async fn timeout(&self) {
         warn!("timeout");
         self.envoy.export(record::Timeout {
-            task: self.task,
-            test: self.test,
+            task:  self.task,
+            test:  self.test,
+            run:   self.run,
+            lag:   self.lag,
+            maint: self.schedule.maintenance(),
         }).await;
         self.active.timeout();
+        self.schedule.timeout();
+        if let Some(followup) = &self.followup {
+            followup.trigger(Trigger::Timeout, self.run, &self.target);
+        }
     }
 }
 
-async fn trace(trace: &Trace, addr: IpAddr) -> Result<Vec<Vec<Node>>> {
+async fn trace(trace: &Trace, addr: IpAddr) -> Result<(Vec<Vec<Vec<Node>>>, HashMap<IpAddr, Vec<Label>>)> {
+    let tracer   = &trace.tracer;
+    let listener = Listener::new(&trace.source, addr)?;
+
+    let (flows, labels) = listener.watch(async {
+        let mut sources = Vec::with_capacity(trace.flows);
+        for _ in 0..trace.flows {
+            sources.push(tracer.reserve(trace.protocol, addr).await?);
+        }
+
+        let mut flows = Vec::with_capacity(sources.len());
+        for source in &sources {
+            flows.push(route(trace, source.probe()?, addr).await?);
+        }
+
+        Result::<_, Error>::Ok(flows)
+    }).await;
+
+    Ok((flows?, labels))
+}
+
+async fn route(trace: &Trace, mut probe: Probe, addr: IpAddr) -> Result<Vec<Vec<Node>>> {
     let tracer = &trace.tracer;
     let count  = trace.count;
     let limit  = u8::try_from(trace.limit)?;
     let delay  = trace.delay;
     let expiry = trace.expiry.probe;
 
-    let source = tracer.reserve(trace.protocol, addr).await?;
-
     let mut done  = false;
     let mut ttl   = 1;
-    let mut probe = source.probe()?;
     let mut route = Vec::new();
 
     while !done && ttl <= limit {
+        trace.budget.packets(count).await;
+
         let done = |node: &Node| {
             done = match node {
                 Node::Node(_, _ , _, true)  => true,
@@ -194,16 +378,178 @@ async fn trace(trace: &Trace, addr: IpAddr) -> Result<Vec<Vec<Node>>> {
     Ok(route)
 }
 
+fn aspath(flows: &[Vec<Vec<Node>>], origins: &Origins) -> Vec<u32> {
+    let mut path = Vec::new();
+
+    if let Some(route) = flows.first() {
+        for probes in route {
+            let asn = probes.iter().find_map(|node| match node {
+                Node::Node(_, addr, _, _) => origins.lookup(*addr)?.asn,
+                Node::None(_)             => None,
+            });
+
+            if let Some(asn) = asn {
+                if path.last() != Some(&asn) {
+                    path.push(asn);
+                }
+            }
+        }
+    }
+
+    path
+}
+
+fn graph(
+    flows:   &[Vec<Vec<Node>>],
+    origins: &Origins,
+    names:   &HashMap<IpAddr, String>,
+    labels:  &HashMap<IpAddr, Vec<Label>>,
+) -> Graph {
+    let mut nodes = BTreeMap::<(usize, IpAddr), Vertex>::new();
+    let mut edges = BTreeSet::<Edge>::new();
+
+    for (flow, route) in flows.iter().enumerate() {
+        let mut prev = Vec::new();
+
+        for (hop, probes) in route.iter().enumerate() {
+            let hop = hop + 1;
+
+            let mut addrs = Vec::new();
+            for node in probes {
+                if let Node::Node(_, addr, rtt, _) = node {
+                    let vertex = nodes.entry((hop, *addr)).or_insert_with(|| Vertex {
+                        hop:    hop,
+                        addr:   *addr,
+                        flows:  Vec::new(),
+                        rtt:    Vec::new(),
+                        name:   names.get(addr).cloned(),
+                        labels: labels.get(addr).cloned().unwrap_or_default(),
+                        origin: origins.lookup(*addr),
+                    });
+
+                    if !vertex.flows.contains(&flow) {
+                        vertex.flows.push(flow);
+                    }
+                    vertex.rtt.push(rtt.as_micros() as u64);
+
+                    addrs.push(*addr);
+                }
+            }
+
+            addrs.sort_unstable();
+            addrs.dedup();
+
+            for from in &prev {
+                for to in &addrs {
+                    edges.insert(Edge { flow, hop, from: *from, to: *to });
+                }
+            }
+
+            if !addrs.is_empty() {
+                prev = addrs;
+            }
+        }
+    }
+
+    Graph {
+        flows: flows.len(),
+        nodes: nodes.into_values().collect(),
+        edges: edges.into_iter().collect(),
+    }
+}
+
 #[derive(Debug)]
 struct Output {
-    addr:  IpAddr,
-    route: Vec<Vec<Node>>,
-    time:  Duration,
+    addr:   IpAddr,
+    route:  Vec<Vec<Vec<Node>>>,
+    labels: HashMap<IpAddr, Vec<Label>>,
+    time:   Duration,
 }
 
 impl fmt::Display for Output {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let Self { route, time, .. } = self;
-        write!(f, "{} hops in {:0.2?}", route.len(), time)
+        let hops = route.iter().map(Vec::len).max().unwrap_or_default();
+        write!(f, "{} hops over {} flows in {:0.2?}", hops, route.len(), time)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trace_graph() {
+        let a = "192.0.2.1".parse().unwrap();
+        let b = "192.0.2.2".parse().unwrap();
+        let c = "192.0.2.3".parse().unwrap();
+        let d = "192.0.2.4".parse().unwrap();
+
+        let rtt  = Duration::from_micros;
+        let node = |ttl, addr, us| Node::Node(ttl, addr, rtt(us), false);
+
+        let flows = vec![
+            vec![
+                vec![node(1, a, 10)],
+                vec![node(2, b, 20)],
+                vec![node(3, d, 30)],
+            ],
+            vec![
+                vec![node(1, a, 11)],
+                vec![Node::None(2), node(2, c, 21), node(2, c, 22)],
+                vec![node(3, d, 31)],
+            ],
+        ];
+
+        let label  = Label { label: 16001, tc: 0, s: true, ttl: 1 };
+        let labels = vec![(b, vec![label])].into_iter().collect();
+
+        let graph = graph(&flows, &Origins::default(), &HashMap::new(), &labels);
+        assert_eq!(2, graph.flows);
+
+        let nodes = graph.nodes.iter().map(|v| {
+            (v.hop, v.addr, v.flows.clone(), v.rtt.clone(), v.labels.len())
+        }).collect::<Vec<_>>();
+
+        assert_eq!(vec![
+            (1, a, vec![0, 1], vec![10, 11], 0),
+            (2, b, vec![0],    vec![20],     1),
+            (2, c, vec![1],    vec![21, 22], 0),
+            (3, d, vec![0, 1], vec![30, 31], 0),
+        ], nodes);
+
+        assert_eq!(vec![
+            Edge { flow: 0, hop: 2, from: a, to: b },
+            Edge { flow: 0, hop: 3, from: b, to: d },
+            Edge { flow: 1, hop: 2, from: a, to: c },
+            Edge { flow: 1, hop: 3, from: c, to: d },
+        ], graph.edges);
+    }
+
+    #[test]
+    fn trace_graph_silent_hop() {
+        let a = "192.0.2.1".parse().unwrap();
+        let b = "192.0.2.2".parse().unwrap();
+
+        let node = |ttl, addr| Node::Node(ttl, addr, Duration::ZERO, false);
+
+        let flows = vec![
+            vec![vec![node(1, a)], vec![Node::None(2)], vec![node(3, b)]],
+            vec![vec![node(1, a)], vec![node(2, b)]],
+        ];
+
+        let graph = graph(&flows, &Origins::default(), &HashMap::new(), &HashMap::new());
+
+        let nodes = graph.nodes.iter().map(|v| (v.hop, v.addr, v.flows.clone())).collect::<Vec<_>>();
+        assert_eq!(vec![
+            (1, a, vec![0, 1]),
+            (2, b, vec![1]),
+            (3, b, vec![0]),
+        ], nodes);
+
+        assert_eq!(vec![
+            Edge { flow: 0, hop: 3, from: a, to: b },
+            Edge { flow: 1, hop: 2, from: a, to: b },
+        ], graph.edges);
     }
 }
@@ -0,0 +1,117 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use synapi::tasks::MaintenanceConfig;
+
+#[derive(Clone, Debug)]
+pub struct Window {
+    span:   Span,
+    zone:   Tz,
+    action: Action,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Action {
+    Skip,
+    Tag,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Span {
+    Once(NaiveDateTime, NaiveDateTime),
+    Daily(NaiveTime, NaiveTime),
+}
+
+const DATETIME: &[&str] = &["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"];
+const TIME:     &[&str] = &["%H:%M:%S", "%H:%M"];
+
+impl Window {
+    pub fn new(cfg: &MaintenanceConfig) -> Result<Self> {
+        let MaintenanceConfig { start, end, timezone, action } = cfg;
+
+        let action = match action.as_str() {
+            "skip" | "" => Action::Skip,
+            "tag"       => Action::Tag,
+            other       => return Err(anyhow!("invalid maintenance action: {}", other)),
+        };
+
+        let span = match (datetime(start), datetime(end), time(start), time(end)) {
+            (Some(start), Some(end), _, _) if start < end => Span::Once(start, end),
+            (_, _, Some(start), Some(end))                => Span::Daily(start, end),
+            _ => return Err(anyhow!("invalid maintenance window: {} - {}", start, end)),
+        };
+
+        let zone = zone(timezone.as_deref())?;
+
+        Ok(Self { span, zone, action })
+    }
+
+    pub fn action(&self) -> Action {
+        self.action
+    }
+
+    pub fn contains(&self, now: &DateTime<Utc>) -> bool {
+        let now = now.with_timezone(&self.zone).naive_local();
+        match self.span {
+            Span::Once(start, end)                  => start <= now && now < end,
+            Span::Daily(start, end) if start <= end => start <= now.time() && now.time() < end,
+            Span::Daily(start, end)                 => start <= now.time() || now.time() < end,
+        }
+    }
+}
+
+pub fn zone(name: Option<&str>) -> Result<Tz> {
+    match name {
+        Some(name) => name.parse().map_err(|e| anyhow!("invalid timezone {}: {}", name, e)),
+        None       => Ok(Tz::UTC),
+    }
+}
+
+fn datetime(s: &str) -> Option<NaiveDateTime> {
+    DATETIME.iter().find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())
+}
+
+fn time(s: &str) -> Option<NaiveTime> {
+    TIME.iter().find_map(|fmt| NaiveTime::parse_from_str(s, fmt).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+    use super::*;
+
+    fn window(start: &str, end: &str, timezone: Option<&str>, action: &str) -> Result<Window> {
+        Window::new(&MaintenanceConfig {
+            start:    start.to_owned(),
+            end:      end.to_owned(),
+            timezone: timezone.map(str::to_owned),
+            action:   action.to_owned(),
+        })
+    }
+
+    #[test]
+    fn window_contains() -> Result<()> {
+        let at = |d, h, m| Utc.ymd(2022, 5, d).and_hms(h, m, 0);
+
+        let once = window("2022-05-02 22:00", "2022-05-03T01:30", None, "")?;
+        assert_eq!(Action::Skip, once.action());
+        assert!(!once.contains(&at(2, 21, 59)));
+        assert!(once.contains(&at(2, 22, 0)));
+        assert!(once.contains(&at(3, 1, 29)));
+        assert!(!once.contains(&at(3, 1, 30)));
+
+        let daily = window("23:00", "02:00", Some("Europe/Berlin"), "tag")?;
+        assert_eq!(Action::Tag, daily.action());
+        assert!(daily.contains(&at(2, 21, 0)));
+        assert!(daily.contains(&at(2, 23, 59)));
+        assert!(!daily.contains(&at(3, 0, 0)));
+        assert!(!daily.contains(&at(3, 20, 59)));
+
+        assert!(window("02:00", "2022-05-03 01:00", None, "").is_err());
+        assert!(window("2022-05-03 01:00", "2022-05-02 01:00", None, "").is_err());
+        assert!(window("01:00", "02:00", Some("Mars/Olympus"), "").is_err());
+        assert!(window("01:00", "02:00", None, "ignore").is_err());
+
+        Ok(())
+    }
+}
@@ -6,11 +6,13 @@ use log::{debug, info, warn};
 use tokio::sync::mpsc::Sender;
 use tokio::time::sleep;
 use synapi::{self, Client, Error, Retry};
-use synapi::agent::Agent;
+use synapi::agent::{Agent, Net};
 use synapi::auth::Auth;
-use synapi::tasks::Group;
+use synapi::tasks::{Group, TaskConfig};
 use synapi::Error::Unauthorized;
-use crate::status::Report;
+use crate::ctl::Control;
+use crate::export::Record;
+use crate::status::{Listing, Queue, Report};
 
 pub struct Watcher {
     client: Arc<Client>,
@@ -23,6 +25,9 @@ pub enum Event {
     Tasks(Tasks),
     Reset,
     Report(Sender<Report>),
+    Run(Once, Sender<Result<Vec<Record>>>),
+    Control(Control, Sender<Result<Vec<Listing>>>),
+    Shutdown(Duration, Sender<Queue>),
 }
 
 #[derive(Debug)]
@@ -31,6 +36,12 @@ pub struct Tasks {
     pub tasks: Vec<Group>,
 }
 
+#[derive(Debug)]
+pub enum Once {
+    Task(u64),
+    Config(Net, Box<TaskConfig>),
+}
+
 impl Watcher {
     pub fn new(client: Arc<Client>, keys: KeyPair, output: Sender<Event>) -> Self {
         Self { client, keys, output }
@@ -6,7 +6,7 @@ pub struct Agent {
     pub net: Net,
 }
 
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
 pub enum Net {
     #[serde(rename = "V4")]
     IPv4,
@@ -14,4 +14,6 @@ pub enum Net {
     IPv6,
     #[serde(rename = "DUAL")]
     Dual,
+    #[serde(rename = "COMPARE")]
+    Compare,
 }
@@ -1,7 +1,8 @@
 use std::cmp::max;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::fmt;
+use std::net::IpAddr;
 use std::time::Duration;
 use serde::{Deserialize, de::{Deserializer, Error, Visitor}};
 use crate::serde::id;
@@ -32,7 +33,7 @@ pub struct Task {
     pub state:  State,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum TaskConfig {
     Fetch(FetchConfig),
@@ -44,76 +45,250 @@ pub enum TaskConfig {
     Unknown,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Hash)]
 pub struct FetchConfig {
     pub target:   String,
-    pub period:   Period,
+    #[serde(default)]
+    pub period:   Option<Period>,
     pub expiry:   Expiry,
     #[serde(default)]
     pub method:   String,
     #[serde(default)]
     pub body:     Option<String>,
     #[serde(default)]
-    pub headers:  Option<HashMap<String, String>>,
+    pub headers:  Option<BTreeMap<String, String>>,
     #[serde(rename = "ignore_tls_errors", default)]
     pub insecure: bool,
+    #[serde(default)]
+    pub policy:   String,
+    #[serde(default)]
+    pub race:     bool,
+    #[serde(rename = "happy_eyeballs", default)]
+    pub eyeballs: bool,
+    #[serde(default)]
+    pub address:  Option<IpAddr>,
+    #[serde(default)]
+    pub resolve:  BTreeMap<String, IpAddr>,
+    #[serde(default)]
+    pub sni:      Option<String>,
+    #[serde(default)]
+    pub resolver: Option<ResolverConfig>,
+    #[serde(default)]
+    pub source:   Option<SourceConfig>,
+    #[serde(default)]
+    pub schedule: Option<ScheduleConfig>,
+    #[serde(default)]
+    pub followup: Vec<FollowupConfig>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Hash)]
 pub struct KnockConfig {
-    pub target:  String,
-    pub period:  Period,
-    pub count:   Count,
+    pub target:   String,
+    #[serde(default)]
+    pub period:   Option<Period>,
+    pub count:    Count,
+    #[serde(default)]
+    pub delay:    Delay,
+    pub expiry:   Expiry,
+    pub port:     u16,
+    #[serde(default)]
+    pub policy:   String,
+    #[serde(default)]
+    pub resolver: Option<ResolverConfig>,
     #[serde(default)]
-    pub delay:   Delay,
-    pub expiry:  Expiry,
-    pub port:    u16,
+    pub source:   Option<SourceConfig>,
+    #[serde(default)]
+    pub schedule: Option<ScheduleConfig>,
+    #[serde(default)]
+    pub followup: Vec<FollowupConfig>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Hash)]
 pub struct PingConfig {
-    pub target:  String,
-    pub period:  Period,
-    pub count:   Count,
+    pub target:   String,
+    #[serde(default)]
+    pub period:   Option<Period>,
+    pub count:    Count,
+    #[serde(default)]
+    pub delay:    Delay,
+    pub expiry:   Expiry,
+    #[serde(default)]
+    pub size:     Option<u16>,
+    #[serde(default)]
+    pub dscp:     Dscp,
+    #[serde(default)]
+    pub ttl:      Option<u8>,
+    #[serde(rename = "dont_fragment", default)]
+    pub df:       bool,
     #[serde(default)]
-    pub delay:   Delay,
-    pub expiry:  Expiry,
+    pub protocol: String,
+    #[serde(default)]
+    pub port:     u16,
+    #[serde(default)]
+    pub policy:   String,
+    #[serde(default)]
+    pub resolver: Option<ResolverConfig>,
+    #[serde(default)]
+    pub source:   Option<SourceConfig>,
+    #[serde(default)]
+    pub schedule: Option<ScheduleConfig>,
+    #[serde(default)]
+    pub followup: Vec<FollowupConfig>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Hash)]
 pub struct QueryConfig {
-    pub target:  String,
-    pub period:  Period,
-    pub expiry:  Expiry,
+    pub target:   String,
+    #[serde(default)]
+    pub period:   Option<Period>,
+    pub expiry:   Expiry,
     #[serde(rename = "resolver")]
-    pub server:  String,
-    pub port:    u16,
+    pub server:   String,
+    pub port:     u16,
     #[serde(rename = "type")]
-    pub record:  String,
+    pub record:   String,
+    #[serde(default)]
+    pub source:   Option<SourceConfig>,
+    #[serde(default)]
+    pub schedule: Option<ScheduleConfig>,
+    #[serde(default)]
+    pub followup: Vec<FollowupConfig>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Hash)]
 pub struct ShakeConfig {
     pub target:   String,
     pub port:     u16,
-    pub period:   Period,
+    #[serde(default)]
+    pub period:   Option<Period>,
     pub expiry:   Expiry,
+    #[serde(default)]
+    pub policy:   String,
+    #[serde(default)]
+    pub address:  Option<IpAddr>,
+    #[serde(default)]
+    pub resolve:  BTreeMap<String, IpAddr>,
+    #[serde(default)]
+    pub sni:      Option<String>,
+    #[serde(default)]
+    pub resolver: Option<ResolverConfig>,
+    #[serde(default)]
+    pub source:   Option<SourceConfig>,
+    #[serde(default)]
+    pub schedule: Option<ScheduleConfig>,
+    #[serde(default)]
+    pub followup: Vec<FollowupConfig>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Hash)]
 pub struct TraceConfig {
     #[serde(default)]
     pub protocol: String,
     #[serde(default)]
     pub port:     u16,
     pub target:   String,
-    pub period:   Period,
+    #[serde(default)]
+    pub period:   Option<Period>,
     #[serde(default = "default_trace_count")]
     pub count:    Count,
     pub limit:    Limit,
     #[serde(default)]
     pub delay:    Delay,
     pub expiry:   Expiry,
+    #[serde(default = "default_trace_flows")]
+    pub flows:    Count,
+    #[serde(default)]
+    pub names:    bool,
+    #[serde(default)]
+    pub policy:   String,
+    #[serde(default)]
+    pub resolver: Option<ResolverConfig>,
+    #[serde(default)]
+    pub source:   Option<SourceConfig>,
+    #[serde(default)]
+    pub schedule: Option<ScheduleConfig>,
+    #[serde(default)]
+    pub followup: Vec<FollowupConfig>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Hash)]
+pub struct ResolverConfig {
+    #[serde(default)]
+    pub servers:  Vec<String>,
+    #[serde(default)]
+    pub protocol: String,
+    #[serde(default)]
+    pub timeout:  Option<Expiry>,
+    #[serde(rename = "no_cache", default)]
+    pub nocache:  bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Hash)]
+pub struct SourceConfig {
+    #[serde(default)]
+    pub address:   Option<IpAddr>,
+    #[serde(default)]
+    pub interface: Option<String>,
+    #[serde(default)]
+    pub vrf:       Option<String>,
+    #[serde(default)]
+    pub netns:     Option<String>,
+    #[serde(default)]
+    pub fwmark:    Option<u32>,
+    #[serde(default)]
+    pub dscp:      Option<Dscp>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Hash)]
+pub struct ScheduleConfig {
+    #[serde(default)]
+    pub align:       bool,
+    #[serde(default)]
+    pub jitter:      Delay,
+    #[serde(default)]
+    pub adaptive:    Option<AdaptiveConfig>,
+    #[serde(default)]
+    pub cron:        Option<String>,
+    #[serde(default)]
+    pub timezone:    Option<String>,
+    #[serde(default)]
+    pub maintenance: Vec<MaintenanceConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize, Hash)]
+pub struct AdaptiveConfig {
+    pub period:  Period,
+    #[serde(default)]
+    pub after:   Option<u32>,
+    #[serde(default)]
+    pub recover: Option<u32>,
+    #[serde(default)]
+    pub rtt:     Option<Delay>,
+    #[serde(default)]
+    pub loss:    Option<u8>,
+}
+
+#[derive(Clone, Debug, Deserialize, Hash)]
+pub struct MaintenanceConfig {
+    pub start:    String,
+    pub end:      String,
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub action:   String,
+}
+
+#[derive(Clone, Debug, Deserialize, Hash)]
+pub struct FollowupConfig {
+    pub action: String,
+    #[serde(default)]
+    pub on:     Option<String>,
+    #[serde(default)]
+    pub after:  Option<u32>,
+    #[serde(default)]
+    pub count:  Option<Count>,
+    #[serde(default)]
+    pub expiry: Option<Expiry>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -156,21 +331,85 @@ pub enum Kind {
     Addr,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Hash)]
 pub struct Count(usize);
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Hash)]
 pub struct Limit(usize);
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Hash)]
 pub struct Delay(Duration);
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default, Hash)]
+pub struct Dscp(u8);
+
+#[derive(Copy, Clone, Debug, Hash)]
 pub struct Expiry(Duration);
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Hash)]
 pub struct Period(Duration);
 
+impl TaskConfig {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Fetch(_) => "fetch",
+            Self::Knock(_) => "knock",
+            Self::Ping(_)  => "ping",
+            Self::Query(_) => "query",
+            Self::Shake(_) => "shake",
+            Self::Trace(_) => "trace",
+            Self::Unknown  => "unknown",
+        }
+    }
+
+    pub fn resolver(&self) -> Option<&ResolverConfig> {
+        match self {
+            Self::Fetch(cfg) => cfg.resolver.as_ref(),
+            Self::Knock(cfg) => cfg.resolver.as_ref(),
+            Self::Ping(cfg)  => cfg.resolver.as_ref(),
+            Self::Shake(cfg) => cfg.resolver.as_ref(),
+            Self::Trace(cfg) => cfg.resolver.as_ref(),
+            _                => None,
+        }
+    }
+
+    pub fn source(&self) -> Option<&SourceConfig> {
+        match self {
+            Self::Fetch(cfg) => cfg.source.as_ref(),
+            Self::Knock(cfg) => cfg.source.as_ref(),
+            Self::Ping(cfg)  => cfg.source.as_ref(),
+            Self::Query(cfg) => cfg.source.as_ref(),
+            Self::Shake(cfg) => cfg.source.as_ref(),
+            Self::Trace(cfg) => cfg.source.as_ref(),
+            _                => None,
+        }
+    }
+
+    pub fn schedule(&self) -> Option<&ScheduleConfig> {
+        match self {
+            Self::Fetch(cfg) => cfg.schedule.as_ref(),
+            Self::Knock(cfg) => cfg.schedule.as_ref(),
+            Self::Ping(cfg)  => cfg.schedule.as_ref(),
+            Self::Query(cfg) => cfg.schedule.as_ref(),
+            Self::Shake(cfg) => cfg.schedule.as_ref(),
+            Self::Trace(cfg) => cfg.schedule.as_ref(),
+            _                => None,
+        }
+    }
+
+    pub fn followup(&self) -> &[FollowupConfig] {
+        match self {
+            Self::Fetch(cfg) => &cfg.followup,
+            Self::Knock(cfg) => &cfg.followup,
+            Self::Ping(cfg)  => &cfg.followup,
+            Self::Query(cfg) => &cfg.followup,
+            Self::Shake(cfg) => &cfg.followup,
+            Self::Trace(cfg) => &cfg.followup,
+            _                => &[],
+        }
+    }
+}
+
 impl<'d> Deserialize<'d> for Task {
     fn deserialize<D: Deserializer<'d>>(de: D) -> Result<Self, D::Error> {
         #[derive(Debug, Deserialize)]
@@ -255,6 +494,16 @@ impl<'de> Deserialize<'de> for Limit {
     }
 }
 
+impl<'de> Deserialize<'de> for Dscp {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        let n = de.deserialize_u64(U64Visitor)?;
+        match u8::try_from(n) {
+            Ok(n) if n < 64 => Ok(Self(n)),
+            _               => Err(D::Error::custom(format!("dscp out of range: {}", n))),
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for Delay {
     fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
         let millis = de.deserialize_u64(U64Visitor)?;
@@ -300,6 +549,18 @@ impl From<usize> for Limit {
     }
 }
 
+impl From<Dscp> for u8 {
+    fn from(dscp: Dscp) -> Self {
+        dscp.0
+    }
+}
+
+impl From<u8> for Dscp {
+    fn from(dscp: u8) -> Self {
+        Self(dscp)
+    }
+}
+
 impl From<Delay> for Duration  {
     fn from(delay: Delay) -> Self {
         delay.0
@@ -345,3 +606,7 @@ impl Default for Delay  {
 fn default_trace_count() -> Count {
     Count(3)
 }
+
+fn default_trace_flows() -> Count {
+    Count(1)
+}